@@ -0,0 +1,387 @@
+use anyhow::{Context, Result};
+use log::warn;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+
+use crate::config::{self, cudup_home};
+use crate::cuda::metadata::CudaReleaseMetadata;
+
+/// How long a fetched version list is trusted before refetching.
+pub const VERSIONS_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long fetched release metadata is trusted before refetching.
+pub const METADATA_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// How long the newest cuDNN found for a CUDA major version is trusted. It can only
+/// change when a cuDNN release is published, so it expires with the version list.
+pub const COMPATIBLE_CUDNN_TTL: Duration = VERSIONS_TTL;
+
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Turns every cache read into a miss and every write into a no-op for this process.
+pub fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+fn is_disabled() -> bool {
+    DISABLED.load(Ordering::Relaxed)
+}
+
+static REFRESH: AtomicBool = AtomicBool::new(false);
+
+/// Makes this process refetch everything it looks up, still writing the results back
+/// so later commands benefit from them.
+pub fn refresh() {
+    REFRESH.store(true, Ordering::Relaxed);
+}
+
+/// Whether `--refresh` was passed; callers hand this to the loaders as `force_refresh`.
+pub fn refresh_requested() -> bool {
+    REFRESH.load(Ordering::Relaxed)
+}
+
+pub fn cache_dir() -> Result<PathBuf> {
+    Ok(cudup_home()?.join("cache"))
+}
+
+fn product_dir(product: &str) -> Result<PathBuf> {
+    Ok(cache_dir()?.join(product.to_lowercase()))
+}
+
+fn versions_path(product: &str) -> Result<PathBuf> {
+    Ok(product_dir(product)?.join("versions.json"))
+}
+
+fn metadata_path(product: &str, version: &str) -> Result<PathBuf> {
+    Ok(product_dir(product)?.join(format!("{}.json", version)))
+}
+
+/// Kept outside the per-product directories, which `gc` prunes by version.
+fn compatible_cudnn_path(cuda_major: &str) -> Result<PathBuf> {
+    Ok(cache_dir()?
+        .join("compatible-cudnn")
+        .join(format!("cuda{}.json", cuda_major)))
+}
+
+fn is_fresh(path: &Path, ttl: Duration) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age < ttl)
+}
+
+fn load<T: DeserializeOwned>(path: &Path, ttl: Duration, force_refresh: bool) -> Result<Option<T>> {
+    if force_refresh || is_disabled() || !is_fresh(path, ttl) {
+        return Ok(None);
+    }
+
+    let contents = fs::read_to_string(path)?;
+    match serde_json::from_str(&contents) {
+        Ok(value) => Ok(Some(value)),
+        Err(e) => {
+            warn!("Ignoring unreadable cache file {}: {}", path.display(), e);
+            Ok(None)
+        }
+    }
+}
+
+/// Writes `value` to the cache. The cache only saves refetching, so a failed write, e.g. to
+/// a read-only or full cudup home, is logged rather than failing the command.
+fn save<T: Serialize>(path: Result<PathBuf>, value: &T) {
+    if let Err(e) = path.and_then(|path| write(&path, value)) {
+        warn!("Failed to update the cache: {:#}", e);
+    }
+}
+
+fn write<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    if is_disabled() {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // Write to a sibling file and rename it into place, so a crash mid-write
+    // never leaves a truncated cache file behind.
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, serde_json::to_string(value)?)?;
+    fs::rename(&tmp_path, path)
+        .with_context(|| format!("Failed to write cache file {}", path.display()))?;
+
+    if let Some(max_bytes) = config::get().cache.max_bytes {
+        evict_oldest(&cache_dir()?, max_bytes, Some(path))?;
+    }
+    Ok(())
+}
+
+pub fn load_cached_versions(
+    product: &str,
+    force_refresh: bool,
+) -> Result<Option<BTreeSet<String>>> {
+    load(&versions_path(product)?, VERSIONS_TTL, force_refresh)
+}
+
+pub fn save_versions(product: &str, versions: &BTreeSet<String>) {
+    save(versions_path(product), versions)
+}
+
+pub fn load_cached_metadata(
+    product: &str,
+    version: &str,
+    force_refresh: bool,
+) -> Result<Option<CudaReleaseMetadata>> {
+    load(
+        &metadata_path(product, version)?,
+        METADATA_TTL,
+        force_refresh,
+    )
+}
+
+pub fn save_metadata(product: &str, version: &str, metadata: &CudaReleaseMetadata) {
+    save(metadata_path(product, version), metadata)
+}
+
+/// The newest cuDNN version with a build for `cuda_major`, as found by an earlier lookup.
+pub fn load_cached_compatible_cudnn(
+    cuda_major: &str,
+    force_refresh: bool,
+) -> Result<Option<String>> {
+    load(
+        &compatible_cudnn_path(cuda_major)?,
+        COMPATIBLE_CUDNN_TTL,
+        force_refresh,
+    )
+}
+
+pub fn save_compatible_cudnn(cuda_major: &str, cudnn_version: &str) {
+    save(compatible_cudnn_path(cuda_major), &cudnn_version)
+}
+
+/// Removes cached metadata for `product` versions not in `keep`, returning the bytes freed.
+pub fn prune_metadata(product: &str, keep: &BTreeSet<String>) -> Result<u64> {
+    let dir = product_dir(product)?;
+    if !dir.exists() {
+        return Ok(0);
+    }
+
+    let mut freed = 0;
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        let Some(version) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        if path.file_name() == versions_path(product)?.file_name() || keep.contains(version) {
+            continue;
+        }
+
+        freed += fs::metadata(&path)?.len();
+        fs::remove_file(&path)?;
+    }
+
+    Ok(freed)
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<(PathBuf, u64, SystemTime)>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), files)?;
+        } else {
+            let metadata = entry.metadata()?;
+            files.push((entry.path(), metadata.len(), metadata.modified()?));
+        }
+    }
+    Ok(())
+}
+
+/// Removes the least recently written files under `dir` until it holds at most
+/// `max_bytes`, never removing `keep`. Returns the bytes freed.
+fn evict_oldest(dir: &Path, max_bytes: u64, keep: Option<&Path>) -> Result<u64> {
+    if !dir.exists() {
+        return Ok(0);
+    }
+
+    let mut files = Vec::new();
+    collect_files(dir, &mut files)?;
+    files.sort_by_key(|(_, _, modified)| *modified);
+
+    let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+    let mut freed = 0;
+    for (path, size, _) in files {
+        if total <= max_bytes {
+            break;
+        }
+        if Some(path.as_path()) == keep {
+            continue;
+        }
+        fs::remove_file(&path)?;
+        total -= size;
+        freed += size;
+    }
+
+    Ok(freed)
+}
+
+/// Trims the cache to `cache.max_bytes`, oldest entries first, returning the bytes freed.
+pub fn enforce_max_bytes() -> Result<u64> {
+    match config::get().cache.max_bytes {
+        Some(max_bytes) => evict_oldest(&cache_dir()?, max_bytes, None),
+        None => Ok(0),
+    }
+}
+
+/// Number of files and total bytes held in the cache.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CacheStats {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+fn count_files_in_dir(dir: &Path) -> Result<CacheStats> {
+    let mut stats = CacheStats::default();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            let nested = count_files_in_dir(&entry.path())?;
+            stats.files += nested.files;
+            stats.bytes += nested.bytes;
+        } else {
+            stats.files += 1;
+            stats.bytes += entry.metadata()?.len();
+        }
+    }
+    Ok(stats)
+}
+
+/// Counts every file under the cache directory, however deeply nested.
+pub fn stats() -> Result<CacheStats> {
+    let dir = cache_dir()?;
+    if !dir.exists() {
+        return Ok(CacheStats::default());
+    }
+    count_files_in_dir(&dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use tempfile::TempDir;
+
+    fn write_aged(path: &Path, bytes: usize, age_secs: u64) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, vec![b'x'; bytes]).unwrap();
+        let modified = SystemTime::now() - Duration::from_secs(age_secs);
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    #[test]
+    fn evict_oldest_removes_oldest_files_until_under_limit() {
+        let dir = TempDir::new().unwrap();
+        let oldest = dir.path().join("cuda/12.4.1.json");
+        let older = dir.path().join("cudnn/9.1.0.json");
+        let newest = dir.path().join("cuda/versions.json");
+        write_aged(&oldest, 100, 300);
+        write_aged(&older, 100, 200);
+        write_aged(&newest, 100, 100);
+
+        let freed = evict_oldest(dir.path(), 150, None).unwrap();
+
+        assert_eq!(freed, 200);
+        assert!(!oldest.exists());
+        assert!(!older.exists());
+        assert!(newest.exists());
+    }
+
+    #[test]
+    fn evict_oldest_keeps_the_file_just_written() {
+        let dir = TempDir::new().unwrap();
+        let kept = dir.path().join("cuda/12.4.1.json");
+        let other = dir.path().join("cuda/12.6.0.json");
+        write_aged(&kept, 100, 300);
+        write_aged(&other, 100, 100);
+
+        evict_oldest(dir.path(), 100, Some(&kept)).unwrap();
+
+        assert!(kept.exists());
+        assert!(!other.exists());
+    }
+
+    #[test]
+    fn evict_oldest_leaves_a_cache_under_the_limit_alone() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("cuda/versions.json");
+        write_aged(&file, 100, 100);
+
+        assert_eq!(evict_oldest(dir.path(), 1000, None).unwrap(), 0);
+        assert!(file.exists());
+    }
+
+    #[test]
+    fn unreadable_cache_file_is_a_miss() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("cuda/versions.json");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "[\"12.4.1\", \"12.").unwrap();
+
+        let loaded: Option<BTreeSet<String>> = load(&path, VERSIONS_TTL, false).unwrap();
+        assert_eq!(loaded, None);
+    }
+
+    #[test]
+    fn write_replaces_a_corrupt_file_without_leaving_a_temp_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("cuda/versions.json");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "garbage").unwrap();
+        let versions = BTreeSet::from(["12.4.1".to_string(), "12.6.0".to_string()]);
+
+        write(&path, &versions).unwrap();
+
+        let loaded: Option<BTreeSet<String>> = load(&path, VERSIONS_TTL, false).unwrap();
+        assert_eq!(loaded, Some(versions));
+        assert!(!path.with_extension("json.tmp").exists());
+    }
+
+    #[test]
+    fn failed_save_is_not_an_error() {
+        let dir = TempDir::new().unwrap();
+        let blocker = dir.path().join("cache");
+        fs::write(&blocker, "not a directory").unwrap();
+        let path = blocker.join("cuda/versions.json");
+
+        assert!(write(&path, &"12.4.1").is_err());
+        save(Ok(path.clone()), &"12.4.1");
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn count_files_in_dir_counts_nested_files_and_bytes() {
+        let dir = TempDir::new().unwrap();
+        for (path, bytes) in [
+            ("cuda/versions.json", 10),
+            ("cuda/12.4.1.json", 200),
+            ("cudnn/9.1.0.json", 300),
+            ("compatible-cudnn/cuda12.json", 40),
+            ("deeply/nested/dir/entry.json", 5),
+        ] {
+            write_aged(&dir.path().join(path), bytes, 0);
+        }
+        fs::create_dir_all(dir.path().join("empty/dir")).unwrap();
+
+        let stats = count_files_in_dir(dir.path()).unwrap();
+        assert_eq!(stats.files, 5);
+        assert_eq!(stats.bytes, 555);
+    }
+}
@@ -0,0 +1,14 @@
+use anyhow::Result;
+
+use crate::cache;
+use crate::fetch::format_size;
+
+pub fn cache_stats() -> Result<()> {
+    let stats = cache::stats()?;
+
+    println!("Cache directory: {}", cache::cache_dir()?.display());
+    println!("Files: {}", stats.files);
+    println!("Size:  {}", format_size(stats.bytes));
+
+    Ok(())
+}
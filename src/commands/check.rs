@@ -1,16 +1,33 @@
-use anyhow::Result;
+use anyhow::{Result, bail};
+use reqwest::{Client, Url};
+use serde::Serialize;
 use std::env;
-use std::path::PathBuf;
+use std::error::Error as _;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::{Duration, Instant};
 
-use crate::config::{cudup_home, get_installed_versions};
+use crate::config::{cudup_home, get_installed_versions, versions_dir};
+use crate::cuda::source::{cuda_source, cudnn_source};
+use crate::fetch::{filesystem_warnings, http, same_release, toolkit_version};
 
+/// Known metadata files used to probe each redist host.
+fn network_probes() -> [(&'static str, String); 2] {
+    [
+        ("cuda", cuda_source().metadata_url("12.0.0")),
+        ("cudnn", cudnn_source().metadata_url("9.0.0")),
+    ]
+}
+
+#[derive(Serialize)]
 struct CheckResult {
     name: String,
     status: CheckStatus,
     detail: Option<String>,
 }
 
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
 enum CheckStatus {
     Ok,
     Warning,
@@ -89,6 +106,29 @@ fn check_shell_integration() -> CheckResult {
     }
 }
 
+fn check_install_filesystem() -> CheckResult {
+    let versions_dir = match versions_dir() {
+        Ok(dir) => dir,
+        Err(e) => return CheckResult::error("install filesystem", e.to_string()),
+    };
+
+    let warnings = filesystem_warnings(&versions_dir);
+    if warnings.is_empty() {
+        CheckResult::ok(
+            "install filesystem",
+            Some(versions_dir.display().to_string()),
+        )
+    } else {
+        CheckResult::warning(
+            "install filesystem",
+            format!(
+                "{} (set CUDUP_HOME or use install --dest elsewhere)",
+                warnings.join("; ")
+            ),
+        )
+    }
+}
+
 fn check_installed_versions() -> CheckResult {
     let versions = match get_installed_versions() {
         Ok(v) => v,
@@ -103,24 +143,41 @@ fn check_installed_versions() -> CheckResult {
     CheckResult::ok("installed versions", Some(detail))
 }
 
+/// Returns the active CUDA root, preferring `CUDA_HOME` over `CUDA_PATH`.
+fn active_cuda_root() -> Option<(&'static str, String)> {
+    ["CUDA_HOME", "CUDA_PATH"]
+        .into_iter()
+        .find_map(|var| env::var(var).ok().map(|value| (var, value)))
+}
+
 fn check_active_version() -> CheckResult {
-    match env::var("CUDA_HOME") {
-        Ok(cuda_home) => {
+    match active_cuda_root() {
+        Some((var, cuda_home)) => {
             let path = PathBuf::from(&cuda_home);
             if path.exists() {
                 let version = path
                     .file_name()
                     .and_then(|n| n.to_str())
                     .unwrap_or("unknown");
-                CheckResult::ok("active version", Some(version))
+                match toolkit_version(&path) {
+                    Some(toolkit) if !same_release(version, &toolkit) => CheckResult::warning(
+                        "active version",
+                        format!("{} reports toolkit version {}", version, toolkit),
+                    ),
+                    Some(toolkit) => CheckResult::ok(
+                        "active version",
+                        Some(format!("{} (toolkit {})", version, toolkit)),
+                    ),
+                    None => CheckResult::ok("active version", Some(version)),
+                }
             } else {
                 CheckResult::error(
                     "active version",
-                    format!("CUDA_HOME={} does not exist", cuda_home),
+                    format!("{}={} does not exist", var, cuda_home),
                 )
             }
         }
-        Err(_) => CheckResult::warning("active version", "CUDA_HOME not set"),
+        None => CheckResult::warning("active version", "CUDA_HOME not set"),
     }
 }
 
@@ -145,6 +202,60 @@ fn check_nvcc() -> CheckResult {
     }
 }
 
+/// Where a system-package CUDA toolkit is usually installed.
+const SYSTEM_CUDA_PATH: &str = "/usr/local/cuda";
+
+/// First `nvcc` on `PATH`, with symlinks resolved.
+fn nvcc_on_path() -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path)
+        .map(|dir| dir.join("nvcc"))
+        .find(|candidate| candidate.is_file())
+        .map(|nvcc| nvcc.canonicalize().unwrap_or(nvcc))
+}
+
+/// Warns when a CUDA outside the cudup home may shadow the active cudup version.
+fn check_system_cuda() -> CheckResult {
+    let name = "system cuda";
+    let home = match cudup_home() {
+        Ok(home) => home.canonicalize().unwrap_or(home),
+        Err(e) => return CheckResult::error(name, e.to_string()),
+    };
+    let cuda_home = active_cuda_root()
+        .map(|(_, root)| root)
+        .unwrap_or_else(|| "(not set)".to_string());
+
+    if let Some(nvcc) = nvcc_on_path().filter(|nvcc| !nvcc.starts_with(&home)) {
+        return CheckResult::warning(
+            name,
+            format!(
+                "nvcc on PATH is {}, outside {}; it may shadow CUDA_HOME={}",
+                nvcc.display(),
+                home.display(),
+                cuda_home
+            ),
+        );
+    }
+
+    let system_cuda = Path::new(SYSTEM_CUDA_PATH);
+    if system_cuda.symlink_metadata().is_ok() {
+        let target = system_cuda
+            .canonicalize()
+            .unwrap_or_else(|_| system_cuda.to_path_buf());
+        return CheckResult::warning(
+            name,
+            format!(
+                "{} ({}) may be picked up instead of CUDA_HOME={}",
+                SYSTEM_CUDA_PATH,
+                target.display(),
+                cuda_home
+            ),
+        );
+    }
+
+    CheckResult::ok(name, Some("none found"))
+}
+
 fn check_nvidia_driver() -> CheckResult {
     match Command::new("nvidia-smi")
         .arg("--query-gpu=driver_version")
@@ -185,35 +296,158 @@ fn check_gpu() -> CheckResult {
     }
 }
 
-pub fn check() -> Result<()> {
-    println!("cudup check");
-    println!();
+fn format_latency(elapsed: Duration) -> String {
+    format!("{}ms", elapsed.as_millis())
+}
 
-    let checks = vec![
+/// Walks the error source chain to surface the underlying reason (DNS, TLS, ...).
+fn root_cause(err: &reqwest::Error) -> String {
+    let mut source = err.source();
+    let mut reason = err.to_string();
+    while let Some(inner) = source {
+        reason = inner.to_string();
+        source = inner.source();
+    }
+    reason
+}
+
+async fn check_dns(host: &str, timeout: Duration) -> CheckResult {
+    let name = format!("dns {}", host);
+    let start = Instant::now();
+
+    match tokio::time::timeout(timeout, tokio::net::lookup_host((host, 443))).await {
+        Ok(Ok(mut addrs)) => match addrs.next() {
+            Some(addr) => CheckResult::ok(
+                name,
+                Some(format!(
+                    "{} ({})",
+                    addr.ip(),
+                    format_latency(start.elapsed())
+                )),
+            ),
+            None => CheckResult::error(name, "no addresses returned"),
+        },
+        Ok(Err(e)) => CheckResult::error(name, format!("resolution failed: {}", e)),
+        Err(_) => CheckResult::error(name, format!("timed out after {}s", timeout.as_secs())),
+    }
+}
+
+async fn check_https(client: &Client, label: &str, url: &str) -> CheckResult {
+    let name = format!("{} redist", label);
+    let start = Instant::now();
+
+    match client.head(url).send().await {
+        Ok(response) if response.status().is_success() => CheckResult::ok(
+            name,
+            Some(format!(
+                "HTTP {} ({})",
+                response.status().as_u16(),
+                format_latency(start.elapsed())
+            )),
+        ),
+        Ok(response) => {
+            CheckResult::error(name, format!("HTTP {} from {}", response.status(), url))
+        }
+        Err(e) if e.is_timeout() => CheckResult::error(
+            name,
+            format!("timed out after {}", format_latency(start.elapsed())),
+        ),
+        Err(e) if e.is_connect() => {
+            CheckResult::error(name, format!("connection failed: {}", root_cause(&e)))
+        }
+        Err(e) => CheckResult::error(name, root_cause(&e)),
+    }
+}
+
+async fn check_network(connect_timeout: Duration) -> Vec<CheckResult> {
+    let client = match http::builder()
+        .timeout(http::METADATA_TIMEOUT)
+        .connect_timeout(connect_timeout)
+        .build()
+    {
+        Ok(c) => c,
+        Err(e) => return vec![CheckResult::error("network", e.to_string())],
+    };
+
+    let mut results = Vec::new();
+    let mut resolved_hosts = Vec::new();
+
+    for (label, url) in network_probes() {
+        if let Some(host) = Url::parse(&url)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_string))
+            && !resolved_hosts.contains(&host)
+        {
+            results.push(check_dns(&host, connect_timeout).await);
+            resolved_hosts.push(host);
+        }
+
+        results.push(check_https(&client, label, &url).await);
+    }
+
+    results
+}
+
+async fn run_checks(network: bool, connect_timeout: Duration) -> Vec<CheckResult> {
+    let mut checks = vec![
         check_cudup_home(),
         check_shell_integration(),
+        check_install_filesystem(),
         check_installed_versions(),
         check_active_version(),
         check_nvcc(),
+        check_system_cuda(),
         check_nvidia_driver(),
         check_gpu(),
     ];
 
-    for result in &checks {
-        result.print();
+    if network {
+        checks.extend(check_network(connect_timeout).await);
     }
 
+    checks
+}
+
+/// Runs every check and fails if any reports an error, or a warning when `strict` is set.
+pub async fn check(
+    network: bool,
+    connect_timeout: Duration,
+    json: bool,
+    strict: bool,
+) -> Result<()> {
+    if !json {
+        println!("cudup check");
+        println!();
+    }
+
+    let checks = run_checks(network, connect_timeout).await;
+
     let (errors, warnings) = checks.iter().fold((0, 0), |(e, w), c| match c.status {
         CheckStatus::Error => (e + 1, w),
         CheckStatus::Warning => (e, w + 1),
         CheckStatus::Ok => (e, w),
     });
 
-    println!();
-    match (errors > 0, warnings > 0) {
-        (true, _) => println!("{} error(s), {} warning(s)", errors, warnings),
-        (false, true) => println!("No errors, {} warning(s)", warnings),
-        (false, false) => println!("All checks passed!"),
+    if json {
+        println!("{}", serde_json::to_string_pretty(&checks)?);
+    } else {
+        for result in &checks {
+            result.print();
+        }
+
+        println!();
+        match (errors > 0, warnings > 0) {
+            (true, _) => println!("{} error(s), {} warning(s)", errors, warnings),
+            (false, true) => println!("No errors, {} warning(s)", warnings),
+            (false, false) => println!("All checks passed!"),
+        }
+    }
+
+    if errors > 0 {
+        bail!("{} check(s) failed", errors);
+    }
+    if strict && warnings > 0 {
+        bail!("{} check(s) reported warnings (--strict)", warnings);
     }
 
     Ok(())
@@ -0,0 +1,153 @@
+use anyhow::Result;
+
+use crate::config::get_installed_versions;
+use crate::cuda::discover::fetch_available_cuda_versions;
+
+/// Subcommands whose version argument completes from installed versions.
+const INSTALLED_VERSION_COMMANDS: &[&str] = &["use", "uninstall", "local", "verify"];
+
+/// Subcommands whose version argument completes from remotely available versions.
+const AVAILABLE_VERSION_COMMANDS: &[&str] = &["install", "lock", "info", "list-packages"];
+
+/// Subcommands taking any number of versions, so every positional completes.
+const MULTI_VERSION_COMMANDS: &[&str] = &["install"];
+
+/// Prefixes clap gives zsh `version` positionals: required, optional, and repeated.
+const ZSH_VERSION_SPECS: &[&str] = &[
+    "':version -- ",
+    "'::version -- ",
+    "'*:version -- ",
+    "'*::version -- ",
+];
+
+/// Prints one version per line for shell completion scripts.
+pub async fn complete_versions(available: bool) -> Result<()> {
+    let versions: Vec<String> = if available {
+        fetch_available_cuda_versions().await?.into_iter().collect()
+    } else {
+        let mut installed = get_installed_versions()?;
+        installed.sort();
+        installed
+    };
+
+    for version in versions {
+        println!("{}", version);
+    }
+
+    Ok(())
+}
+
+const BASH_DYNAMIC: &str = r#"
+_cudup_dynamic() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" prev="${COMP_WORDS[COMP_CWORD-1]}"
+    local versions=""
+    if [[ ${COMP_CWORD} -ge 2 && "${cur}" != -* ]]; then
+        case "${COMP_WORDS[1]}" in
+            MULTI_COMMANDS)
+                # Every positional, but not the value of an option such as --archive-dir.
+                if [[ ${COMP_CWORD} -eq 2 || "${prev}" != -* ]]; then
+                    versions="--available"
+                fi
+                ;;
+            INSTALLED_COMMANDS)
+                [[ ${COMP_CWORD} -eq 2 ]] && versions="--installed"
+                ;;
+            AVAILABLE_COMMANDS)
+                [[ ${COMP_CWORD} -eq 2 ]] && versions="--available"
+                ;;
+        esac
+    fi
+    if [[ -n "${versions}" ]]; then
+        COMPREPLY=( $(compgen -W "$(command cudup __complete-versions ${versions} 2>/dev/null)" -- "${cur}") )
+        return 0
+    fi
+    _cudup "$@"
+}
+
+complete -F _cudup_dynamic -o nosort -o bashdefault -o default cudup
+"#;
+
+const ZSH_DYNAMIC: &str = r#"(( $+functions[_cudup_installed_versions] )) ||
+_cudup_installed_versions() {
+    local versions; versions=(${(f)"$(command cudup __complete-versions 2>/dev/null)"})
+    compadd -a versions
+}
+(( $+functions[_cudup_available_versions] )) ||
+_cudup_available_versions() {
+    local versions; versions=(${(f)"$(command cudup __complete-versions --available 2>/dev/null)"})
+    compadd -a versions
+}
+
+"#;
+
+const FISH_DYNAMIC: &str = r#"
+complete -c cudup -n "__fish_cudup_using_subcommand INSTALLED_COMMANDS" -f -a "(command cudup __complete-versions 2>/dev/null)"
+complete -c cudup -n "__fish_cudup_using_subcommand AVAILABLE_COMMANDS" -f -a "(command cudup __complete-versions --available 2>/dev/null)"
+"#;
+
+fn fill_commands(template: &str, separator: &str) -> String {
+    template
+        .replace("MULTI_COMMANDS", &MULTI_VERSION_COMMANDS.join(separator))
+        .replace(
+            "INSTALLED_COMMANDS",
+            &INSTALLED_VERSION_COMMANDS.join(separator),
+        )
+        .replace(
+            "AVAILABLE_COMMANDS",
+            &AVAILABLE_VERSION_COMMANDS.join(separator),
+        )
+}
+
+/// Points the zsh `version` positionals at the dynamic helpers instead of `_default`.
+fn patch_zsh(script: &str) -> String {
+    let mut section = "";
+    let mut patched = Vec::new();
+
+    for line in script.lines() {
+        let trimmed = line.trim();
+        if let Some(name) = trimmed.strip_prefix('(').and_then(|l| l.strip_suffix(')')) {
+            section = name;
+        }
+
+        let helper = if INSTALLED_VERSION_COMMANDS.contains(&section) {
+            Some("_cudup_installed_versions")
+        } else if AVAILABLE_VERSION_COMMANDS.contains(&section) {
+            Some("_cudup_available_versions")
+        } else {
+            None
+        };
+
+        match helper {
+            Some(helper)
+                if ZSH_VERSION_SPECS
+                    .iter()
+                    .any(|spec| trimmed.starts_with(spec)) =>
+            {
+                patched.push(line.replace(":_default'", &format!(":{}'", helper)));
+            }
+            _ => patched.push(line.to_string()),
+        }
+
+        if trimmed == "if [ \"$funcstack[1]\" = \"_cudup\" ]; then" {
+            let last = patched.pop().unwrap_or_default();
+            patched.push(ZSH_DYNAMIC.to_string());
+            patched.push(last);
+        }
+    }
+
+    patched.join("\n") + "\n"
+}
+
+/// Extends a generated completion script so version arguments complete dynamically.
+pub fn with_dynamic_versions(shell: clap_complete::Shell, script: Vec<u8>) -> Vec<u8> {
+    let script = String::from_utf8_lossy(&script);
+
+    let patched = match shell {
+        clap_complete::Shell::Bash => format!("{}{}", script, fill_commands(BASH_DYNAMIC, "|")),
+        clap_complete::Shell::Zsh => patch_zsh(&script),
+        clap_complete::Shell::Fish => format!("{}{}", script, fill_commands(FISH_DYNAMIC, " ")),
+        _ => script.into_owned(),
+    };
+
+    patched.into_bytes()
+}
@@ -0,0 +1,120 @@
+use anyhow::{Context, Result};
+use log::warn;
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::cache;
+use crate::config::{self, CONFIG_KEYS, Config};
+
+pub fn config_get(key: &str) -> Result<()> {
+    match config::load_config()?.get(key)? {
+        Some(value) => println!("{}", value),
+        None => println!("{} is not set", key),
+    }
+
+    Ok(())
+}
+
+/// Loads the config file for editing. A file that doesn't parse is backed up and
+/// replaced with defaults, so `config set` can repair it.
+fn load_for_edit() -> Result<Config> {
+    let error = match config::load_config() {
+        Ok(settings) => return Ok(settings),
+        Err(e) => e,
+    };
+
+    let path = config::config_path()?;
+    let backup = path.with_extension("toml.bak");
+    fs::copy(&path, &backup)
+        .with_context(|| format!("{:#}; failed to back it up to {}", error, backup.display()))?;
+    warn!(
+        "{:#}; backed it up to {} and starting from default settings",
+        error,
+        backup.display()
+    );
+
+    Ok(Config::default())
+}
+
+pub fn config_set(key: &str, value: &str) -> Result<()> {
+    let mut settings = load_for_edit()?;
+    settings.set(key, value)?;
+    config::save_config(&settings)?;
+
+    println!("Set {} = {}", key, value);
+
+    Ok(())
+}
+
+pub fn config_list() -> Result<()> {
+    let settings = config::load_config()?;
+
+    println!("Config file: {}", config::config_path()?.display());
+    println!();
+    for key in CONFIG_KEYS {
+        match settings.get(key)? {
+            Some(value) => println!("{} = {}", key, value),
+            None => println!("{} = (not set)", key),
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct ResolvedPath {
+    path: PathBuf,
+    exists: bool,
+}
+
+impl ResolvedPath {
+    fn new(path: PathBuf) -> Self {
+        let exists = path.exists();
+        Self { path, exists }
+    }
+}
+
+/// Every directory and file location cudup uses, after `CUDUP_HOME` and `CUDUP_VERSIONS_DIR`
+/// are applied.
+#[derive(Serialize)]
+struct Paths {
+    cudup_home: ResolvedPath,
+    config_file: ResolvedPath,
+    versions_dir: ResolvedPath,
+    downloads_dir: ResolvedPath,
+    cache_dir: ResolvedPath,
+}
+
+pub fn config_paths(json: bool) -> Result<()> {
+    let paths = Paths {
+        cudup_home: ResolvedPath::new(config::cudup_home()?),
+        config_file: ResolvedPath::new(config::config_path()?),
+        versions_dir: ResolvedPath::new(config::versions_dir()?),
+        downloads_dir: ResolvedPath::new(config::downloads_dir()?),
+        cache_dir: ResolvedPath::new(cache::cache_dir()?),
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&paths)?);
+        return Ok(());
+    }
+
+    for (label, resolved) in [
+        ("cudup home", &paths.cudup_home),
+        ("config file", &paths.config_file),
+        ("versions", &paths.versions_dir),
+        ("downloads", &paths.downloads_dir),
+        ("cache", &paths.cache_dir),
+    ] {
+        let state = if resolved.exists { "" } else { " (missing)" };
+        println!(
+            "{:<12} {}{}",
+            format!("{}:", label),
+            resolved.path.display(),
+            state
+        );
+    }
+
+    Ok(())
+}
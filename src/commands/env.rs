@@ -0,0 +1,58 @@
+use anyhow::{Result, bail};
+
+use super::local::{find_version_file, parse_cuda_version_file};
+use crate::config;
+use crate::cuda::CudaVersion;
+use crate::fetch;
+
+/// The version selected by `.cuda-version` or, failing that, `install.default_version`,
+/// along with where it came from.
+pub(super) fn configured_version() -> Result<Option<(CudaVersion, String)>> {
+    if let Some(path) = find_version_file(true)? {
+        let contents = std::fs::read_to_string(&path)?;
+        let version = parse_cuda_version_file(&contents)?.cuda_version;
+        return Ok(Some((version, path.display().to_string())));
+    }
+
+    match &config::get().install.default_version {
+        Some(version) => Ok(Some((
+            CudaVersion::new(version.as_str())?,
+            "install.default_version".to_string(),
+        ))),
+        None => Ok(None),
+    }
+}
+
+/// Picks the version to activate: explicit argument, then `.cuda-version`, then
+/// `install.default_version` from the config.
+fn resolve_version(version: Option<&CudaVersion>) -> Result<CudaVersion> {
+    if let Some(version) = version {
+        return Ok(version.clone());
+    }
+
+    match configured_version()? {
+        Some((version, _)) => Ok(version),
+        None => bail!(
+            "No CUDA version selected. Pass --version, create a .cuda-version file, \
+             or set install.default_version."
+        ),
+    }
+}
+
+pub fn env(version: Option<&CudaVersion>, with_stubs: bool) -> Result<()> {
+    let version = resolve_version(version)?;
+
+    let install_dir = fetch::version_install_dir(version.as_str())?;
+    if !install_dir.exists() {
+        bail!(
+            "CUDA {} is not installed. Run `cudup install {}` to install it.",
+            version,
+            version
+        );
+    }
+    super::ensure_downloaded(&install_dir, version.as_str())?;
+
+    super::print_shell_exports(&install_dir, with_stubs);
+
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use anyhow::{Result, bail};
+use log::warn;
+use std::path::Path;
+
+use crate::config::get_installed_versions;
+use crate::cuda::CudaVersion;
+use crate::fetch::{self, FreezeFile, InstallManifest, Lockfile};
+
+/// Writes every installed version, with the exact archives its manifest records, to `output`.
+pub fn freeze(output: &Path) -> Result<()> {
+    let mut versions: Vec<CudaVersion> = get_installed_versions()?
+        .into_iter()
+        .filter_map(|v| CudaVersion::new(v).ok())
+        .collect();
+    versions.sort();
+
+    let mut freeze = FreezeFile {
+        versions: Vec::new(),
+    };
+    for version in &versions {
+        let install_dir = fetch::version_install_dir(version.as_str())?;
+        match InstallManifest::load(&install_dir)? {
+            Some(manifest) if manifest.pending => {
+                warn!("Skipping CUDA {}: it is staged but not downloaded", version)
+            }
+            Some(manifest) if manifest.is_incomplete() => warn!(
+                "Skipping CUDA {}: it was installed without {}",
+                version,
+                manifest.failed_packages.join(", ")
+            ),
+            Some(manifest) if !manifest.packages.is_empty() => {
+                freeze.versions.push(Lockfile::from_manifest(&manifest))
+            }
+            _ => warn!(
+                "Skipping CUDA {}: no packages are recorded for it. Reinstall it to include it.",
+                version
+            ),
+        }
+    }
+
+    if freeze.versions.is_empty() {
+        bail!("No installed versions with a recorded install manifest to freeze");
+    }
+    freeze.write(output)?;
+
+    println!(
+        "Froze {} version(s) in {}: {}",
+        freeze.versions.len(),
+        output.display(),
+        freeze
+            .versions
+            .iter()
+            .map(|lockfile| lockfile.cuda_version.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use anyhow::Result;
+use log::{debug, info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::cache;
+use crate::config::{cudup_home, downloads_dir, versions_dir};
+use crate::cuda::discover::{fetch_available_cuda_versions, fetch_available_cudnn_versions};
+use crate::fetch::{format_size, is_kept_archive};
+
+/// Archives written to more recently than this may belong to a running install.
+const IN_PROGRESS_GRACE: Duration = Duration::from_secs(10 * 60);
+
+fn is_recent(path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age < IN_PROGRESS_GRACE)
+}
+
+/// Whether `path` is an archive kept with `install --keep-downloads`, or its checksum stamp.
+fn is_kept(path: &Path) -> bool {
+    let archive = match path.to_str().and_then(|p| p.strip_suffix(".sha256")) {
+        Some(archive) => PathBuf::from(archive),
+        None => path.to_path_buf(),
+    };
+    is_kept_archive(&archive)
+}
+
+/// Removes archives left behind by failed or interrupted installs.
+fn remove_orphaned_downloads() -> Result<u64> {
+    let dir = downloads_dir()?;
+    if !dir.exists() {
+        return Ok(0);
+    }
+
+    let mut freed = 0;
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        if is_recent(&path) {
+            info!("Skipping {} (may be in use)", path.display());
+            continue;
+        }
+        if is_kept(&path) {
+            debug!("Keeping {} (kept with --keep-downloads)", path.display());
+            continue;
+        }
+
+        freed += entry.metadata()?.len();
+        fs::remove_file(&path)?;
+        info!("Removed {}", path.display());
+    }
+
+    Ok(freed)
+}
+
+/// Drops cached metadata for versions that are no longer published, then trims the
+/// cache to `cache.max_bytes`.
+async fn prune_cache() -> Result<u64> {
+    let mut freed = 0;
+
+    match fetch_available_cuda_versions().await {
+        Ok(versions) => freed += cache::prune_metadata("CUDA", &versions)?,
+        Err(e) => warn!("Skipping CUDA cache pruning: {}", e),
+    }
+    match fetch_available_cudnn_versions().await {
+        Ok(versions) => freed += cache::prune_metadata("cuDNN", &versions)?,
+        Err(e) => warn!("Skipping cuDNN cache pruning: {}", e),
+    }
+    freed += cache::enforce_max_bytes()?;
+
+    Ok(freed)
+}
+
+/// Removes empty directories below `dir`, without descending into installed versions.
+/// Returns whether `dir` itself ended up empty.
+fn remove_empty_dirs(dir: &Path, versions: &Path) -> Result<bool> {
+    let mut empty = true;
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        let is_version_dir = path.parent() == Some(versions);
+        let removable = entry.file_type()?.is_dir()
+            && if is_version_dir {
+                fs::read_dir(&path)?.next().is_none()
+            } else {
+                remove_empty_dirs(&path, versions)?
+            };
+
+        if removable {
+            fs::remove_dir(&path)?;
+            info!("Removed empty directory {}", path.display());
+        } else {
+            empty = false;
+        }
+    }
+
+    Ok(empty)
+}
+
+pub async fn gc() -> Result<()> {
+    let home = cudup_home()?;
+    if !home.exists() {
+        println!("Nothing to clean up.");
+        return Ok(());
+    }
+
+    let downloads = remove_orphaned_downloads()?;
+    let cache = prune_cache().await?;
+    remove_empty_dirs(&home, &versions_dir()?)?;
+
+    println!("Removed {} of orphaned downloads", format_size(downloads));
+    println!("Removed {} of stale cache metadata", format_size(cache));
+    println!("Reclaimed {} in total", format_size(downloads + cache));
+
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+
+use crate::cuda::CudaVersion;
+use crate::cuda::discover::fetch_cuda_version_metadata;
+use crate::cuda::metadata::{CudaReleaseMetadata, PackageInfo};
+use crate::fetch::format_size;
+
+fn describe_platforms(package: &PackageInfo) -> String {
+    package
+        .available_platforms()
+        .into_iter()
+        .map(|platform| {
+            let variants = package
+                .get_platform(platform)
+                .map(|info| info.variants())
+                .unwrap_or_default();
+            if variants.is_empty() {
+                platform.to_string()
+            } else {
+                format!("{} [{}]", platform, variants.join(", "))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Prints a package-by-platform matrix of archive sizes, with `-` where a package isn't published.
+fn print_platform_matrix(metadata: &CudaReleaseMetadata) {
+    let package_names = metadata.package_names();
+    let platforms: BTreeSet<&str> = package_names
+        .iter()
+        .filter_map(|name| metadata.get_package(name))
+        .flat_map(|package| package.available_platforms())
+        .collect();
+
+    let name_width = package_names.iter().map(|n| n.len()).max().unwrap_or(0);
+    let column_width = platforms.iter().map(|p| p.len()).max().unwrap_or(0).max(10);
+
+    print!("  {:<name_width$}", "package");
+    for platform in &platforms {
+        print!("  {:>column_width$}", platform);
+    }
+    println!();
+
+    for name in package_names {
+        let Some(package) = metadata.get_package(name) else {
+            continue;
+        };
+
+        print!("  {:<name_width$}", name);
+        for platform in &platforms {
+            let cell = match package.get_platform(platform) {
+                Some(info) => info
+                    .size()
+                    .map(format_size)
+                    .unwrap_or_else(|| "?".to_string()),
+                None => "-".to_string(),
+            };
+            print!("  {:>column_width$}", cell);
+        }
+        println!();
+    }
+}
+
+pub async fn info(version: &CudaVersion, list_packages: bool, all_platforms: bool) -> Result<()> {
+    let metadata = fetch_cuda_version_metadata(version.as_str())
+        .await
+        .with_context(|| format!("Failed to fetch metadata for CUDA {}", version))?;
+
+    let package_names = metadata.package_names();
+
+    println!("CUDA {}", version);
+    if let Some(label) = &metadata.release_label {
+        println!("  Label:    {}", label);
+    }
+    if let Some(date) = &metadata.release_date {
+        println!("  Released: {}", date);
+    }
+    println!("  Packages: {}", package_names.len());
+
+    if all_platforms {
+        println!();
+        print_platform_matrix(&metadata);
+        return Ok(());
+    }
+
+    if !list_packages {
+        return Ok(());
+    }
+
+    let name_width = package_names.iter().map(|n| n.len()).max().unwrap_or(0);
+
+    println!();
+    for name in package_names {
+        let Some(package) = metadata.get_package(name) else {
+            continue;
+        };
+
+        print!(
+            "  {:<name_width$}  {:<12}  {}",
+            name,
+            package.version,
+            describe_platforms(package)
+        );
+        if let Some(cuda_variant) = &package.cuda_variant {
+            print!("  (cuda_variant: {})", cuda_variant.join(", "));
+        }
+        println!();
+    }
+
+    Ok(())
+}
@@ -1,7 +1,470 @@
+use crate::config;
 use crate::cuda::CudaVersion;
-use crate::fetch;
-use anyhow::Result;
+use crate::cuda::discover::fetch_available_cuda_versions;
+use crate::error::CudupError;
+use crate::fetch::{self, FreezeFile, InstallManifest, InstallOptions, InstallPlan, Lockfile};
+use crate::output;
+use anyhow::{Context, Result, bail};
+use log::{info, warn};
+use serde::Serialize;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::Command;
 
-pub async fn install(version: &CudaVersion) -> Result<()> {
-    fetch::install_cuda_version(version).await
+/// Machine-readable result printed by `install --json`.
+#[derive(Serialize)]
+struct InstallSummary {
+    cuda_version: String,
+    cudnn_version: Option<String>,
+    install_path: PathBuf,
+    total_bytes: u64,
+    package_count: usize,
+}
+
+fn print_json_summary(version: &CudaVersion, options: &InstallOptions) -> Result<()> {
+    let install_dir = fetch::install_dir_for(version, options)?;
+    let manifest = InstallManifest::load(&install_dir)?
+        .with_context(|| format!("No install manifest found in {}", install_dir.display()))?;
+
+    let summary = InstallSummary {
+        cuda_version: manifest.cuda_version,
+        cudnn_version: manifest.cudnn_version,
+        install_path: install_dir,
+        total_bytes: manifest.packages.iter().filter_map(|p| p.size).sum(),
+        package_count: manifest.packages.len(),
+    };
+    println!("{}", serde_json::to_string_pretty(&summary)?);
+
+    Ok(())
+}
+
+/// The hook from `--post-hook`, falling back to `hooks.post_install` in the config.
+fn post_install_hook(options: &InstallOptions) -> Option<&str> {
+    options
+        .post_hook
+        .as_deref()
+        .or(config::get().hooks.post_install.as_deref())
+}
+
+async fn run_post_install_hook(
+    hook: &str,
+    version: &CudaVersion,
+    options: &InstallOptions,
+) -> Result<()> {
+    let install_dir = fetch::install_dir_for(version, options)?;
+    let cudnn_version = InstallManifest::load(&install_dir)?.and_then(|m| m.cudnn_version);
+
+    info!("Running post-install hook: {}", hook);
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(hook)
+        .env("CUDA_HOME", &install_dir)
+        .env("CUDUP_VERSION", version.as_str());
+    if let Some(cudnn_version) = &cudnn_version {
+        command.env("CUDUP_CUDNN_VERSION", cudnn_version);
+    }
+    // Keep stdout clean for the JSON summary.
+    if options.json {
+        command.stdout(Stdio::from(std::io::stderr()));
+    }
+    let status = command
+        .status()
+        .await
+        .context("Failed to run post-install hook")?;
+
+    if !status.success() {
+        warn!("Post-install hook exited with {}", status);
+    }
+
+    Ok(())
+}
+
+/// Fails after a `--continue-on-error` install that skipped packages, so scripts can
+/// tell a partial install from a complete one.
+fn ensure_complete(version: &CudaVersion, options: &InstallOptions) -> Result<()> {
+    let install_dir = fetch::install_dir_for(version, options)?;
+    if let Some(manifest) = InstallManifest::load(&install_dir)?
+        && manifest.is_incomplete()
+    {
+        bail!(
+            "CUDA {} was installed without {} package(s): {}",
+            version,
+            manifest.failed_packages.len(),
+            manifest.failed_packages.join(", ")
+        );
+    }
+    Ok(())
+}
+
+pub async fn install(
+    version: &CudaVersion,
+    only_cudnn: bool,
+    options: &InstallOptions,
+) -> Result<()> {
+    if only_cudnn {
+        fetch::install_cudnn_only(version, options).await?;
+        if options.json {
+            print_json_summary(version, options)?;
+        }
+        return Ok(());
+    }
+
+    fetch::install_cuda_version(version, options).await?;
+    finish_install(version, options).await
+}
+
+/// Runs the post-install hook, prints the JSON summary and fails a partial install. The
+/// hook only runs after a complete install, never after `--continue-on-error` skipped packages.
+async fn finish_install(version: &CudaVersion, options: &InstallOptions) -> Result<()> {
+    let complete = ensure_complete(version, options);
+
+    if let Some(hook) = post_install_hook(options) {
+        if complete.is_ok() {
+            run_post_install_hook(hook, version, options).await?;
+        } else {
+            warn!(
+                "Skipping the post-install hook, as CUDA {} is incomplete",
+                version
+            );
+        }
+    }
+
+    if options.json {
+        print_json_summary(version, options)?;
+    }
+
+    complete
+}
+
+/// Installs several versions one after another. Versions that are already installed are
+/// skipped, and a failure doesn't stop the remaining versions unless `fail_fast` is set.
+pub async fn install_versions(
+    versions: &[CudaVersion],
+    only_cudnn: bool,
+    fail_fast: bool,
+    options: &InstallOptions,
+) -> Result<()> {
+    if let [version] = versions {
+        return install(version, only_cudnn, options).await;
+    }
+
+    let unique = unique_versions(versions);
+    let mut report = BatchReport::default();
+    for (i, version) in unique.iter().enumerate() {
+        info!("[{}/{}] CUDA {}", i + 1, unique.len(), version);
+        let result = install(version, only_cudnn, options).await;
+        report.record(version.as_str(), result, fail_fast)?;
+    }
+
+    report.finish()
+}
+
+/// `versions` in the order given, without repeats.
+fn unique_versions(versions: &[CudaVersion]) -> Vec<&CudaVersion> {
+    let mut unique: Vec<&CudaVersion> = Vec::new();
+    for version in versions {
+        if !unique.contains(&version) {
+            unique.push(version);
+        }
+    }
+    unique
+}
+
+/// Outcome of installing several versions in a row, reported once all have been tried.
+#[derive(Default)]
+struct BatchReport {
+    installed: Vec<String>,
+    skipped: Vec<String>,
+    failed: Vec<String>,
+}
+
+impl BatchReport {
+    /// Records how installing `version` went. Already installed versions count as skipped;
+    /// other failures are returned when `fail_fast` is set.
+    fn record(&mut self, version: &str, result: Result<()>, fail_fast: bool) -> Result<()> {
+        match result {
+            Ok(()) => self.installed.push(version.to_string()),
+            Err(e)
+                if matches!(
+                    e.downcast_ref::<CudupError>(),
+                    Some(CudupError::AlreadyInstalled { .. })
+                ) =>
+            {
+                info!("CUDA {} is already installed, skipping", version);
+                self.skipped.push(version.to_string());
+            }
+            Err(e) if fail_fast => return Err(e),
+            Err(e) => {
+                warn!("CUDA {} failed: {:#}", version, e);
+                self.failed.push(version.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints the rollup and fails if any version failed.
+    fn finish(self) -> Result<()> {
+        if !output::is_quiet() {
+            eprintln!("Installed: {}", list_or_none(&self.installed));
+            if !self.skipped.is_empty() {
+                eprintln!("Already installed: {}", self.skipped.join(", "));
+            }
+            if !self.failed.is_empty() {
+                eprintln!("Failed: {}", self.failed.join(", "));
+            }
+        }
+
+        if !self.failed.is_empty() {
+            bail!(
+                "{} of {} versions failed to install: {}",
+                self.failed.len(),
+                self.installed.len() + self.skipped.len() + self.failed.len(),
+                self.failed.join(", ")
+            );
+        }
+
+        Ok(())
+    }
+}
+
+fn list_or_none(versions: &[String]) -> String {
+    if versions.is_empty() {
+        "none".to_string()
+    } else {
+        versions.join(", ")
+    }
+}
+
+pub async fn install_from_lockfile(path: &Path, options: &InstallOptions) -> Result<()> {
+    let plan = Lockfile::read(path)?.into_plan(options.arch.as_deref())?;
+    info!(
+        "Installing CUDA {} from lockfile {}",
+        plan.version,
+        path.display()
+    );
+
+    install_plan(&plan, options).await
+}
+
+/// Installs every version pinned in a freeze file, skipping those already installed.
+pub async fn restore(path: &Path, fail_fast: bool, options: &InstallOptions) -> Result<()> {
+    let freeze = FreezeFile::read(path)?;
+    if freeze.versions.is_empty() {
+        bail!("{} doesn't list any versions", path.display());
+    }
+
+    let total = freeze.versions.len();
+    let mut report = BatchReport::default();
+    for (i, lockfile) in freeze.versions.into_iter().enumerate() {
+        let version = lockfile.cuda_version.clone();
+        info!(
+            "[{}/{}] CUDA {} from {}",
+            i + 1,
+            total,
+            version,
+            path.display()
+        );
+        let result = match lockfile.into_plan(options.arch.as_deref()) {
+            Ok(plan) => install_plan(&plan, options).await,
+            Err(e) => Err(e),
+        };
+        report.record(&version, result, fail_fast)?;
+    }
+
+    report.finish()
+}
+
+/// Installs a plan pinned by a lockfile, then runs the post-install steps.
+async fn install_plan(plan: &InstallPlan, options: &InstallOptions) -> Result<()> {
+    fetch::execute_install_plan(plan, options).await?;
+    finish_install(&plan.version, options).await
+}
+
+/// Restores the packages of an installed version whose files have gone missing.
+pub async fn repair_install(version: &CudaVersion, options: &InstallOptions) -> Result<()> {
+    fetch::repair_install(version, options).await?;
+
+    if options.json {
+        print_json_summary(version, options)?;
+    }
+
+    Ok(())
+}
+
+/// Records the install plan for `version` without downloading it.
+pub async fn install_manifest_only(version: &CudaVersion, options: &InstallOptions) -> Result<()> {
+    let install_dir = fetch::stage_install(version, options).await?;
+
+    if !output::is_quiet() {
+        eprintln!();
+        eprintln!("Staged CUDA {} in {}", version, install_dir.display());
+        eprintln!("To download it, run:");
+        eprintln!("  cudup install {} --resume", version);
+    }
+
+    Ok(())
+}
+
+/// Downloads a plan staged with `--manifest-only`.
+pub async fn resume_install(version: &CudaVersion, options: &InstallOptions) -> Result<()> {
+    fetch::resume_install(version, options).await?;
+
+    if let Some(hook) = post_install_hook(options) {
+        run_post_install_hook(hook, version, options).await?;
+    }
+
+    if options.json {
+        print_json_summary(version, options)?;
+    }
+
+    ensure_complete(version, options)
+}
+
+/// Number of versions offered by the interactive picker.
+const PICKER_CHOICES: usize = 15;
+
+/// Asks the user to pick one of the newest available versions.
+pub async fn pick_version() -> Result<CudaVersion> {
+    if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+        anyhow::bail!(
+            "No version given. Run `cudup install <VERSION>` (see `cudup list`) \
+             or `cudup install --from-lockfile <PATH>`."
+        );
+    }
+
+    let mut versions: Vec<CudaVersion> = fetch_available_cuda_versions()
+        .await?
+        .into_iter()
+        .filter_map(|v| CudaVersion::new(v).ok())
+        .collect();
+    versions.sort_by(|a, b| b.cmp(a));
+    versions.truncate(PICKER_CHOICES);
+
+    if versions.is_empty() {
+        anyhow::bail!("No CUDA versions available");
+    }
+
+    eprintln!("Available CUDA versions:");
+    for (i, version) in versions.iter().enumerate() {
+        eprintln!("  {:>2}) {}", i + 1, version);
+    }
+
+    loop {
+        eprint!("Select a version [1-{}] or type one: ", versions.len());
+        std::io::stderr().flush()?;
+
+        let mut input = String::new();
+        if std::io::stdin().read_line(&mut input)? == 0 {
+            anyhow::bail!("No version selected");
+        }
+        let input = input.trim();
+
+        if let Ok(choice) = input.parse::<usize>()
+            && let Some(version) = choice.checked_sub(1).and_then(|i| versions.get(i))
+        {
+            return Ok(version.clone());
+        }
+        match CudaVersion::new(input) {
+            Ok(version) => return Ok(version),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+}
+
+/// Prints the archive URLs an install would download, one per line, without downloading.
+pub async fn print_urls(
+    version: Option<&CudaVersion>,
+    lockfile: Option<&Path>,
+    with_sha256: bool,
+    options: &InstallOptions,
+) -> Result<()> {
+    let plan = match (version, lockfile) {
+        (_, Some(path)) => Lockfile::read(path)?.into_plan(options.arch.as_deref())?,
+        (Some(version), None) => fetch::resolve_install_plan(version, options).await?,
+        (None, None) => fetch::resolve_install_plan(&pick_version().await?, options).await?,
+    };
+
+    for task in plan.tasks() {
+        if with_sha256 {
+            println!("{}\t{}", task.url, task.sha256);
+        } else {
+            println!("{}", task.url);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    fn versions(names: &[&str]) -> Vec<CudaVersion> {
+        names
+            .iter()
+            .map(|v| CudaVersion::new(*v).unwrap())
+            .collect()
+    }
+
+    fn already_installed(version: &str) -> anyhow::Error {
+        CudupError::AlreadyInstalled {
+            version: version.to_string(),
+            path: PathBuf::from("/versions").join(version),
+        }
+        .into()
+    }
+
+    #[test]
+    fn unique_versions_drops_repeats_and_keeps_order() {
+        let requested = versions(&["12.6.0", "11.8.0", "12.6.0", "11.8.0", "12.4.1"]);
+
+        let unique: Vec<&str> = unique_versions(&requested)
+            .into_iter()
+            .map(CudaVersion::as_str)
+            .collect();
+
+        assert_eq!(unique, ["12.6.0", "11.8.0", "12.4.1"]);
+    }
+
+    #[test]
+    fn batch_report_skips_already_installed_versions() {
+        let mut report = BatchReport::default();
+        report.record("12.4.1", Ok(()), false).unwrap();
+        report
+            .record("12.6.0", Err(already_installed("12.6.0")), true)
+            .unwrap();
+
+        assert_eq!(report.installed, ["12.4.1"]);
+        assert_eq!(report.skipped, ["12.6.0"]);
+        assert!(report.failed.is_empty());
+        report.finish().unwrap();
+    }
+
+    #[test]
+    fn batch_report_continues_past_failures_but_fails_at_the_end() {
+        let mut report = BatchReport::default();
+        report
+            .record("11.8.0", Err(anyhow!("network down")), false)
+            .unwrap();
+        report.record("12.4.1", Ok(()), false).unwrap();
+
+        assert_eq!(report.failed, ["11.8.0"]);
+        let error = report.finish().unwrap_err().to_string();
+        assert_eq!(error, "1 of 2 versions failed to install: 11.8.0");
+    }
+
+    #[test]
+    fn batch_report_stops_at_the_first_failure_with_fail_fast() {
+        let mut report = BatchReport::default();
+
+        let error = report
+            .record("11.8.0", Err(anyhow!("network down")), true)
+            .unwrap_err();
+
+        assert_eq!(error.to_string(), "network down");
+        assert!(report.failed.is_empty());
+    }
 }
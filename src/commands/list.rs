@@ -1,20 +1,245 @@
 use anyhow::{Context, Result};
+use futures::{StreamExt, stream};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
 
-use crate::{config, cuda::discover::fetch_available_cuda_versions};
+use crate::config;
+use crate::cuda::CudaVersion;
+use crate::cuda::discover::{fetch_available_cuda_versions, fetch_cuda_version_metadata};
+use crate::cuda::metadata::ReleaseDate;
+use crate::fetch::format_size;
+use crate::output::status;
 
-pub async fn list_available_versions() -> Result<()> {
-    let versions = fetch_available_cuda_versions()
+use super::{dir_size, is_active_version, is_incomplete, is_staged};
+
+/// Version of the `list --json` output format. Bumped on any incompatible change.
+const LIST_SCHEMA_VERSION: u32 = 1;
+
+/// Top-level `list --json` document.
+#[derive(Serialize)]
+struct ListOutput {
+    /// Always present; consumers should check it before reading `versions`.
+    schema_version: u32,
+    /// Versions in ascending order.
+    versions: Vec<VersionEntry>,
+}
+
+/// One CUDA version in `list --json` output.
+#[derive(Serialize)]
+struct VersionEntry {
+    /// CUDA version, e.g. `12.4.1`.
+    version: String,
+    /// Whether the version is installed. Omitted with `--remote-only`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    installed: Option<bool>,
+    /// Install directory. Only present with `--installed-only`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<PathBuf>,
+    /// Bytes on disk. Only present with `--installed-only`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size_bytes: Option<u64>,
+}
+
+fn print_json(versions: Vec<VersionEntry>) -> Result<()> {
+    let output = ListOutput {
+        schema_version: LIST_SCHEMA_VERSION,
+        versions,
+    };
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    Ok(())
+}
+
+fn list_installed_versions(json: bool, show_size: bool) -> Result<()> {
+    let versions_dir = config::versions_dir()?;
+    let mut versions = config::get_installed_versions()?;
+    versions.sort();
+
+    let entries = versions
+        .into_iter()
+        .map(|version| {
+            let path = versions_dir.join(&version);
+            // Walking every install is slow, so only do it when the size is shown.
+            let size_bytes = if json || show_size {
+                Some(dir_size(&path)?)
+            } else {
+                None
+            };
+            Ok(VersionEntry {
+                size_bytes,
+                path: Some(path),
+                installed: Some(true),
+                version,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if json {
+        return print_json(entries);
+    }
+
+    if entries.is_empty() {
+        status!("No CUDA versions installed");
+        return Ok(());
+    }
+
+    status!("Installed CUDA versions:");
+    for entry in &entries {
+        let marker = match entry.path.as_deref() {
+            Some(path) if is_staged(path) => " (staged, not downloaded)",
+            Some(path) if is_incomplete(path) => " (incomplete)",
+            Some(path) if is_active_version(path) => " (active)",
+            _ => "",
+        };
+        if show_size {
+            println!(
+                "  {:>10}  {:>10}{}",
+                entry.version,
+                format_size(entry.size_bytes.unwrap_or(0)),
+                marker
+            );
+        } else {
+            println!("  {:>10}{}", entry.version, marker);
+        }
+    }
+
+    if show_size {
+        let total = entries.iter().filter_map(|entry| entry.size_bytes).sum();
+        status!();
+        status!("Total: {}", format_size(total));
+    }
+
+    Ok(())
+}
+
+/// Release metadata fetched at once when filtering by date.
+const DATE_FILTER_CONCURRENCY: usize = 8;
+
+/// Keeps the versions released within `since..=until`. Needs every version's metadata, so
+/// it's only done when a date bound is given. Versions without a usable release date are
+/// dropped with a warning.
+async fn filter_by_release_date(
+    versions: BTreeSet<String>,
+    since: Option<ReleaseDate>,
+    until: Option<ReleaseDate>,
+) -> BTreeSet<String> {
+    log::info!("Filtering by release date needs each version's metadata; this may take a while");
+
+    let dated: Vec<_> = stream::iter(versions)
+        .map(|version| async move {
+            let date = fetch_cuda_version_metadata(&version)
+                .await
+                .and_then(|metadata| metadata.parsed_release_date());
+            (version, date)
+        })
+        .buffered(DATE_FILTER_CONCURRENCY)
+        .collect()
+        .await;
+
+    dated
+        .into_iter()
+        .filter_map(|(version, date)| match date {
+            Ok(date) => (since.is_none_or(|since| date >= since)
+                && until.is_none_or(|until| date <= until))
+            .then_some(version),
+            Err(e) => {
+                log::warn!("Skipping CUDA {}: {:#}", version, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Keeps the versions of CUDA `major`, when given, skipping names that don't parse.
+fn filter_by_major(versions: BTreeSet<String>, major: Option<u32>) -> BTreeSet<String> {
+    versions
+        .into_iter()
+        .filter(|version| {
+            major.is_none_or(|major| {
+                CudaVersion::new(version.as_str()).is_ok_and(|v| v.major() == major)
+            })
+        })
+        .collect()
+}
+
+/// Sorts `versions` oldest first by version number (so 12.10 comes after 12.9) and keeps
+/// the newest `limit` of them.
+fn newest_versions(versions: BTreeSet<String>, limit: Option<usize>) -> Vec<String> {
+    let mut parsed: Vec<CudaVersion> = versions
+        .into_iter()
+        .filter_map(|version| CudaVersion::new(version).ok())
+        .collect();
+    parsed.sort();
+    let skip = limit.map_or(0, |limit| parsed.len().saturating_sub(limit));
+    parsed
+        .into_iter()
+        .skip(skip)
+        .map(|version| version.to_string())
+        .collect()
+}
+
+/// Narrows the remote version list; every field is optional.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ListFilters {
+    pub since: Option<ReleaseDate>,
+    pub until: Option<ReleaseDate>,
+    pub major: Option<u32>,
+    pub limit: Option<usize>,
+}
+
+pub async fn list_available_versions(
+    remote_only: bool,
+    installed_only: bool,
+    json: bool,
+    show_size: bool,
+    filters: ListFilters,
+) -> Result<()> {
+    if installed_only {
+        return list_installed_versions(json, show_size);
+    }
+
+    let mut versions = fetch_available_cuda_versions()
         .await
         .context("Failed to fetch available CUDA versions")?;
+    versions = filter_by_major(versions, filters.major);
+    if filters.since.is_some() || filters.until.is_some() {
+        versions = filter_by_release_date(versions, filters.since, filters.until).await;
+    }
+    let versions = newest_versions(versions, filters.limit);
+
+    if json {
+        let versions_dir = config::versions_dir().ok();
+        let entries = versions
+            .into_iter()
+            .map(|version| VersionEntry {
+                installed: (!remote_only).then(|| {
+                    versions_dir
+                        .as_ref()
+                        .is_some_and(|dir| dir.join(&version).exists())
+                }),
+                version,
+                path: None,
+                size_bytes: None,
+            })
+            .collect();
+        return print_json(entries);
+    }
 
     if versions.is_empty() {
-        println!("No CUDA versions available");
+        status!("No CUDA versions available");
+        return Ok(());
+    }
+
+    if remote_only {
+        for version in &versions {
+            println!("{}", version);
+        }
         return Ok(());
     }
 
     let versions_dir = config::versions_dir().ok();
 
-    println!("Available CUDA versions:");
+    status!("Available CUDA versions:");
     for version in &versions {
         let installed = versions_dir
             .as_ref()
@@ -22,8 +247,55 @@ pub async fn list_available_versions() -> Result<()> {
         println!("{} {:>10}", if installed { "*" } else { " " }, version);
     }
 
-    println!();
-    println!("* = installed");
+    status!();
+    status!("* = installed");
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn versions() -> BTreeSet<String> {
+        [
+            "11.8.0",
+            "12.0.1",
+            "12.4.1",
+            "12.9.0",
+            "12.10.0",
+            "13.0.0",
+            "not-a-version",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect()
+    }
+
+    #[test]
+    fn major_keeps_only_that_release_line() {
+        let twelve = filter_by_major(versions(), Some(12));
+        assert_eq!(
+            newest_versions(twelve, None),
+            ["12.0.1", "12.4.1", "12.9.0", "12.10.0"]
+        );
+        assert!(filter_by_major(versions(), Some(10)).is_empty());
+        assert_eq!(filter_by_major(versions(), None), versions());
+    }
+
+    #[test]
+    fn limit_keeps_the_newest_by_version_number() {
+        assert_eq!(
+            newest_versions(versions(), Some(3)),
+            ["12.9.0", "12.10.0", "13.0.0"]
+        );
+        assert_eq!(newest_versions(versions(), Some(100)).len(), 6);
+        assert!(newest_versions(versions(), Some(0)).is_empty());
+    }
+
+    #[test]
+    fn major_and_limit_combine() {
+        let twelve = filter_by_major(versions(), Some(12));
+        assert_eq!(newest_versions(twelve, Some(2)), ["12.9.0", "12.10.0"]);
+    }
+}
@@ -0,0 +1,40 @@
+use anyhow::{Context, Result};
+
+use crate::cuda::CudaVersion;
+use crate::cuda::discover::fetch_cuda_version_metadata;
+use crate::fetch;
+
+pub async fn list_packages(version: &CudaVersion, arch: Option<&str>) -> Result<()> {
+    let metadata = fetch_cuda_version_metadata(version.as_str())
+        .await
+        .with_context(|| format!("Failed to fetch metadata for CUDA {}", version))?;
+    let platform = fetch::target_platform(arch)?;
+
+    let package_names = metadata.package_names();
+    let name_width = package_names.iter().map(|n| n.len()).max().unwrap_or(0);
+
+    println!("Packages for CUDA {} ({}):", version, platform);
+    for name in package_names {
+        let Some(package) = metadata.get_package(name) else {
+            continue;
+        };
+
+        let Some(platform_info) = package.get_platform(platform) else {
+            println!("  {:<name_width$}  unavailable", name);
+            continue;
+        };
+
+        let variants = platform_info.variants();
+        if variants.is_empty() {
+            println!("  {:<name_width$}  available", name);
+        } else {
+            println!(
+                "  {:<name_width$}  available [{}]",
+                name,
+                variants.join(", ")
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -1,7 +1,8 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Result, bail};
 
+use crate::config::{self, prompt_confirmation};
 use crate::cuda::CudaVersion;
 use crate::fetch;
 
@@ -44,17 +45,52 @@ pub fn parse_cuda_version_file(contents: &str) -> Result<CudaVersionConfig> {
     })
 }
 
-pub fn find_version_file() -> Result<Option<PathBuf>> {
-    let mut dir = std::env::current_dir()?;
-    let home = dirs::home_dir();
+/// Parent directories searched for `.cuda-version` unless `local.max_depth` is set.
+const DEFAULT_MAX_DEPTH: usize = 32;
+
+/// Project root markers used unless `local.root_markers` is set.
+const DEFAULT_ROOT_MARKERS: &[&str] = &[".git", ".hg"];
+
+/// Looks for `.cuda-version` in the current directory and, when `ascend` is set, its
+/// parents, stopping after the project root (a directory containing one of
+/// `local.root_markers`), the home directory, the filesystem root, or `local.max_depth`
+/// parents.
+pub fn find_version_file(ascend: bool) -> Result<Option<PathBuf>> {
+    let home = config::home_dir().ok();
+    let local = &config::get().local;
+    let max_depth = if ascend {
+        local.max_depth.unwrap_or(DEFAULT_MAX_DEPTH)
+    } else {
+        0
+    };
+    let markers: Vec<&str> = match &local.root_markers {
+        Some(markers) => markers.iter().map(String::as_str).collect(),
+        None => DEFAULT_ROOT_MARKERS.to_vec(),
+    };
+
+    Ok(search_version_file(
+        std::env::current_dir()?,
+        home.as_deref(),
+        max_depth,
+        &markers,
+    ))
+}
 
-    loop {
+/// The search behind [`find_version_file`], starting at `dir`.
+fn search_version_file(
+    mut dir: PathBuf,
+    home: Option<&Path>,
+    max_depth: usize,
+    root_markers: &[&str],
+) -> Option<PathBuf> {
+    for _ in 0..=max_depth {
         let candidate = dir.join(VERSION_FILE_NAME);
         if candidate.is_file() {
-            return Ok(Some(candidate));
+            return Some(candidate);
         }
 
-        if home.as_deref() == Some(&dir) {
+        let is_project_root = root_markers.iter().any(|m| dir.join(m).exists());
+        if is_project_root || home == Some(dir.as_path()) {
             break;
         }
 
@@ -63,7 +99,7 @@ pub fn find_version_file() -> Result<Option<PathBuf>> {
         }
     }
 
-    Ok(None)
+    None
 }
 
 pub fn local_write(version: &CudaVersion) -> Result<()> {
@@ -82,8 +118,10 @@ pub fn local_write(version: &CudaVersion) -> Result<()> {
     Ok(())
 }
 
-pub fn local_activate() -> Result<()> {
-    let path = find_version_file()?.ok_or_else(|| {
+/// Activates the version from the nearest `.cuda-version`, or only the current
+/// directory's when `ascend` is false.
+pub fn local_activate(ascend: bool) -> Result<()> {
+    let path = find_version_file(ascend)?.ok_or_else(|| {
         anyhow::anyhow!("No .cuda-version file found. Run `cudup local <version>` to create one.")
     })?;
 
@@ -98,6 +136,7 @@ pub fn local_activate() -> Result<()> {
             config.cuda_version
         );
     }
+    super::ensure_downloaded(&install_dir, config.cuda_version.as_str())?;
 
     if config.cudnn_version.is_some() {
         log::warn!(
@@ -110,7 +149,87 @@ pub fn local_activate() -> Result<()> {
         config.cuda_version,
         path.display()
     );
-    super::print_shell_exports(&install_dir);
+    super::print_shell_exports(&install_dir, false);
+
+    Ok(())
+}
+
+/// Removes the nearest `.cuda-version`, or only the one in the current directory when
+/// `current_dir_only` is set.
+pub fn local_clear(current_dir_only: bool, force: bool) -> Result<()> {
+    let path = find_version_file(!current_dir_only)?;
+
+    let Some(path) = path else {
+        if current_dir_only {
+            println!("No .cuda-version file in the current directory.");
+        } else {
+            println!("No .cuda-version file found.");
+        }
+        return Ok(());
+    };
+
+    if !force && !prompt_confirmation(&format!("Remove {}?", path.display()))? {
+        println!("Cancelled.");
+        return Ok(());
+    }
+
+    let pinned = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|contents| parse_cuda_version_file(&contents).ok());
+    std::fs::remove_file(&path)?;
+    match pinned {
+        Some(config) => println!(
+            "Removed {} (pinned CUDA {})",
+            path.display(),
+            config.cuda_version
+        ),
+        None => println!("Removed {}", path.display()),
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const MARKERS: &[&str] = &[".git", ".hg"];
+
+    /// `root/a/b/c`, with a `.cuda-version` in `root`.
+    fn nested() -> (TempDir, PathBuf) {
+        let root = TempDir::new().unwrap();
+        let leaf = root.path().join("a/b/c");
+        std::fs::create_dir_all(&leaf).unwrap();
+        std::fs::write(root.path().join(VERSION_FILE_NAME), "12.4.1\n").unwrap();
+        (root, leaf)
+    }
+
+    #[test]
+    fn search_stops_at_a_git_directory() {
+        let (root, leaf) = nested();
+        std::fs::create_dir(root.path().join("a/.git")).unwrap();
+
+        assert_eq!(
+            search_version_file(leaf.clone(), None, DEFAULT_MAX_DEPTH, MARKERS),
+            None
+        );
+
+        std::fs::write(root.path().join("a/.cuda-version"), "12.6.0\n").unwrap();
+        assert_eq!(
+            search_version_file(leaf, None, DEFAULT_MAX_DEPTH, MARKERS),
+            Some(root.path().join("a/.cuda-version"))
+        );
+    }
+
+    #[test]
+    fn search_gives_up_after_max_depth_parents() {
+        let (root, leaf) = nested();
+
+        assert_eq!(search_version_file(leaf.clone(), None, 2, MARKERS), None);
+        assert_eq!(
+            search_version_file(leaf, None, 3, MARKERS),
+            Some(root.path().join(VERSION_FILE_NAME))
+        );
+    }
+}
@@ -0,0 +1,24 @@
+use anyhow::Result;
+use std::path::Path;
+
+use crate::cuda::CudaVersion;
+use crate::fetch::{self, InstallOptions, Lockfile};
+
+pub async fn lock(version: &CudaVersion, arch: Option<String>, output: &Path) -> Result<()> {
+    let options = InstallOptions {
+        arch,
+        ..InstallOptions::default()
+    };
+    let plan = fetch::resolve_install_plan(version, &options).await?;
+    let lockfile = Lockfile::from_plan(&plan);
+    lockfile.write(output)?;
+
+    println!(
+        "Locked {} packages for CUDA {} in {}",
+        lockfile.packages.len(),
+        version,
+        output.display()
+    );
+
+    Ok(())
+}
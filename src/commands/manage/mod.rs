@@ -1,15 +1,19 @@
 mod remove;
 mod setup;
+mod status;
 
 use anyhow::{Context, Result, bail};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::{env, fs};
 
-use crate::config::cudup_home;
 pub use crate::config::prompt_confirmation;
+use crate::config::{cudup_home, home_dir};
+use crate::output::status;
 
 pub use remove::remove;
 pub use setup::setup;
+pub use status::status;
 
 const BASH_ZSH_ENV: &str = r#"# cudup shell integration
 cudup() {
@@ -21,6 +25,11 @@ cudup() {
         command cudup "$@"
     fi
 }
+
+# Version persisted with `cudup use --export-only`
+if [ -f "${CUDUP_HOME:-$HOME/.cudup}/active.env" ]; then
+    . "${CUDUP_HOME:-$HOME/.cudup}/active.env"
+fi
 "#;
 
 const FISH_ENV: &str = r#"# cudup shell integration
@@ -45,22 +54,35 @@ pub enum Shell {
 }
 
 impl Shell {
+    pub const ALL: [Shell; 3] = [Shell::Bash, Shell::Zsh, Shell::Fish];
+
     pub fn detect() -> Result<Self> {
         let shell_path = env::var("SHELL").context("Could not detect shell from $SHELL")?;
-        let shell_name = Path::new(&shell_path)
+        Self::from_shell_path(&shell_path)
+    }
+
+    /// Identifies the shell named by a `$SHELL` value.
+    fn from_shell_path(shell_path: &str) -> Result<Self> {
+        let shell_name = Path::new(shell_path)
             .file_name()
             .and_then(|n| n.to_str())
             .context("Could not determine shell name from $SHELL")?;
 
-        match shell_name {
-            "fish" => Ok(Shell::Fish),
-            "zsh" => Ok(Shell::Zsh),
-            "bash" => Ok(Shell::Bash),
-            _ => bail!(
+        Self::from_login_name(shell_name).with_context(|| {
+            format!(
                 "Unsupported shell: {}. Supported shells: bash, zsh, fish.",
                 shell_path
-            ),
-        }
+            )
+        })
+    }
+
+    /// Matches a `$SHELL` file name loosely, accepting login shells (`-bash`) and
+    /// versioned binaries (`bash5`).
+    fn from_login_name(name: &str) -> Option<Self> {
+        let name = name.trim_start_matches('-');
+        Shell::ALL
+            .into_iter()
+            .find(|shell| name.starts_with(shell.name()))
     }
 
     pub fn env_content(&self) -> &'static str {
@@ -71,7 +93,7 @@ impl Shell {
     }
 
     pub fn rc_file(&self) -> Result<PathBuf> {
-        let home = dirs::home_dir().context("Could not determine home directory")?;
+        let home = home_dir()?;
         Ok(match self {
             Shell::Bash => home.join(".bashrc"),
             Shell::Zsh => home.join(".zshrc"),
@@ -94,6 +116,24 @@ impl Shell {
         }
     }
 
+    /// Location the shell loads completion scripts from.
+    pub fn completion_file(&self) -> Result<PathBuf> {
+        let home = home_dir()?;
+        Ok(match self {
+            Shell::Bash => home.join(".local/share/bash-completion/completions/cudup"),
+            Shell::Zsh => home.join(".zfunc/_cudup"),
+            Shell::Fish => home.join(".config/fish/completions/cudup.fish"),
+        })
+    }
+
+    pub fn completion_shell(&self) -> clap_complete::Shell {
+        match self {
+            Shell::Bash => clap_complete::Shell::Bash,
+            Shell::Zsh => clap_complete::Shell::Zsh,
+            Shell::Fish => clap_complete::Shell::Fish,
+        }
+    }
+
     pub fn name(&self) -> &'static str {
         match self {
             Shell::Bash => "bash",
@@ -103,8 +143,26 @@ impl Shell {
     }
 }
 
+impl FromStr for Shell {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "fish" => Ok(Shell::Fish),
+            "zsh" => Ok(Shell::Zsh),
+            "bash" => Ok(Shell::Bash),
+            _ => bail!(
+                "Unsupported shell: {}. Supported shells: bash, zsh, fish.",
+                s
+            ),
+        }
+    }
+}
+
 pub struct ManageContext {
     pub shell: Shell,
+    /// Whether the shell came from `$SHELL` rather than `--shell`.
+    pub detected: bool,
     pub env_path: PathBuf,
     pub rc_path: PathBuf,
     pub rc_configured: bool,
@@ -112,8 +170,12 @@ pub struct ManageContext {
 }
 
 impl ManageContext {
-    pub fn detect() -> Result<Self> {
-        let shell = Shell::detect()?;
+    /// Builds the context for `shell`, detecting it from `$SHELL` when not given.
+    pub fn detect(shell: Option<Shell>) -> Result<Self> {
+        let (shell, detected) = match shell {
+            Some(shell) => (shell, false),
+            None => (Shell::detect()?, true),
+        };
         let env_path = env_file_path(shell)?;
         let rc_path = shell.rc_file()?;
         let rc_configured = is_rc_configured(&rc_path)?;
@@ -121,6 +183,7 @@ impl ManageContext {
 
         Ok(Self {
             shell,
+            detected,
             env_path,
             rc_path,
             rc_configured,
@@ -129,8 +192,12 @@ impl ManageContext {
     }
 
     pub fn print_detected_shell(&self) {
-        println!("Detected shell: {}", self.shell.name());
-        println!();
+        if self.detected {
+            status!("Detected shell: {}", self.shell.name());
+        } else {
+            status!("Using shell: {}", self.shell.name());
+        }
+        status!();
     }
 }
 
@@ -150,6 +217,17 @@ pub fn is_rc_configured(rc_path: &Path) -> Result<bool> {
     }))
 }
 
+/// Strips the cudup source line from an rc file, if it has one. Returns whether it changed.
+pub fn unconfigure_rc(rc_path: &Path) -> Result<bool> {
+    if !is_rc_configured(rc_path)? {
+        return Ok(false);
+    }
+
+    let content = fs::read_to_string(rc_path)?;
+    fs::write(rc_path, remove_cudup_lines(&content))?;
+    Ok(true)
+}
+
 pub fn remove_cudup_lines(content: &str) -> String {
     let lines: Vec<&str> = content.lines().collect();
     let mut result = Vec::new();
@@ -188,3 +266,37 @@ pub fn remove_cudup_lines(content: &str) -> String {
         result.join("\n") + "\n"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shell_paths_are_matched_by_file_name() {
+        for (shell_path, shell) in [
+            ("/bin/bash", Shell::Bash),
+            ("/usr/local/bin/zsh", Shell::Zsh),
+            ("/opt/homebrew/bin/fish", Shell::Fish),
+        ] {
+            assert_eq!(Shell::from_shell_path(shell_path).unwrap(), shell);
+        }
+    }
+
+    #[test]
+    fn login_and_versioned_shells_are_recognized() {
+        assert_eq!(Shell::from_shell_path("-bash").unwrap(), Shell::Bash);
+        assert_eq!(Shell::from_shell_path("-zsh").unwrap(), Shell::Zsh);
+        assert_eq!(Shell::from_shell_path("/bin/bash5").unwrap(), Shell::Bash);
+    }
+
+    #[test]
+    fn unsupported_shells_are_rejected() {
+        let error = Shell::from_shell_path("/bin/tcsh").unwrap_err();
+        assert!(
+            error.to_string().contains("Unsupported shell: /bin/tcsh"),
+            "{error}"
+        );
+        assert!(Shell::from_shell_path("/bin/sh").is_err());
+        assert!(Shell::from_shell_path("").is_err());
+    }
+}
@@ -2,10 +2,12 @@ use anyhow::Result;
 use std::fs;
 use std::io::ErrorKind;
 
-use super::{ManageContext, prompt_confirmation, remove_cudup_lines};
+use crate::output::status;
 
-pub fn remove() -> Result<()> {
-    let ctx = ManageContext::detect()?;
+use super::{ManageContext, Shell, prompt_confirmation, unconfigure_rc};
+
+pub fn remove(shell: Option<Shell>) -> Result<()> {
+    let ctx = ManageContext::detect(shell)?;
     ctx.print_detected_shell();
 
     let ManageContext {
@@ -17,49 +19,46 @@ pub fn remove() -> Result<()> {
     } = ctx;
 
     if !rc_configured && !env_exists {
-        println!("cudup is not configured. Nothing to remove.");
+        status!("cudup is not configured. Nothing to remove.");
         return Ok(());
     }
 
-    println!("This will:");
+    status!("This will:");
     if env_exists {
-        println!("  - Delete: {}", env_path.display());
+        status!("  - Delete: {}", env_path.display());
     }
     if rc_configured {
-        println!("  - Remove cudup lines from: {}", rc_path.display());
+        status!("  - Remove cudup lines from: {}", rc_path.display());
     }
-    println!();
+    status!();
 
     if !prompt_confirmation("Proceed with removal?")? {
-        println!("Removal cancelled.");
+        status!("Removal cancelled.");
         return Ok(());
     }
 
     match fs::remove_file(&env_path) {
         Ok(()) => {
-            println!();
-            println!("Deleted {}", env_path.display());
+            status!();
+            status!("Deleted {}", env_path.display());
         }
         Err(e) if e.kind() == ErrorKind::NotFound => {
-            println!();
-            println!("{} was already removed", env_path.display());
+            status!();
+            status!("{} was already removed", env_path.display());
         }
         Err(e) => return Err(e.into()),
     }
 
-    if rc_configured {
-        let content = fs::read_to_string(&rc_path)?;
-        let new_content = remove_cudup_lines(&content);
-        fs::write(&rc_path, new_content)?;
-        println!("Updated {}", rc_path.display());
+    if unconfigure_rc(&rc_path)? {
+        status!("Updated {}", rc_path.display());
     }
 
-    println!();
-    println!("Removal complete!");
-    println!();
-    println!("To apply changes, either:");
-    println!("  - Restart your terminal, or");
-    println!("  - Run: source {}", rc_path.display());
+    status!();
+    status!("Removal complete!");
+    status!();
+    status!("To apply changes, either:");
+    status!("  - Restart your terminal, or");
+    status!("  - Run: source {}", rc_path.display());
 
     Ok(())
 }
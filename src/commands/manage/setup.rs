@@ -2,12 +2,34 @@ use anyhow::Result;
 use std::fs;
 use std::io::Write;
 
+use crate::completion_script;
 use crate::config::cudup_home;
+use crate::output::status;
 
-use super::{ManageContext, prompt_confirmation};
+use super::{ManageContext, Shell, prompt_confirmation};
 
-pub fn setup() -> Result<()> {
-    let ctx = ManageContext::detect()?;
+fn install_completions(shell: Shell) -> Result<()> {
+    let completion_path = shell.completion_file()?;
+    if let Some(parent) = completion_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::write(
+        &completion_path,
+        completion_script(shell.completion_shell()),
+    )?;
+    status!("Installed completions to {}", completion_path.display());
+
+    if shell == Shell::Zsh {
+        status!("  Make sure ~/.zfunc is in your fpath before compinit runs:");
+        status!("    fpath+=~/.zfunc");
+    }
+
+    Ok(())
+}
+
+pub fn setup(shell: Option<Shell>, completions: bool) -> Result<()> {
+    let ctx = ManageContext::detect(shell)?;
     ctx.print_detected_shell();
 
     let ManageContext {
@@ -16,69 +38,80 @@ pub fn setup() -> Result<()> {
         rc_path,
         rc_configured,
         env_exists,
+        ..
     } = ctx;
 
     match (rc_configured, env_exists) {
         (true, true) => {
-            println!("cudup is already configured:");
-            println!("  - {}", env_path.display());
-            println!("  - {} (contains source line)", rc_path.display());
-            println!();
+            status!("cudup is already configured:");
+            status!("  - {}", env_path.display());
+            status!("  - {} (contains source line)", rc_path.display());
+            status!();
 
             if !prompt_confirmation("Reconfigure anyway?")? {
-                println!("No changes made.");
+                status!("No changes made.");
                 return Ok(());
             }
-            println!();
+            status!();
         }
         (true, false) => {
-            println!(
+            status!(
                 "{} references cudup but {} is missing.",
                 rc_path.display(),
                 env_path.display()
             );
-            println!("This will recreate the env file.");
-            println!();
+            status!("This will recreate the env file.");
+            status!();
         }
         (false, true) => {
-            println!(
+            status!(
                 "{} exists but {} doesn't source it.",
                 env_path.display(),
                 rc_path.display()
             );
-            println!("This will update both files.");
-            println!();
+            status!("This will update both files.");
+            status!();
         }
         (false, false) => {}
     }
 
-    println!("This will:");
+    status!("This will:");
     if env_exists {
-        println!("  - Overwrite: {}", env_path.display());
+        status!("  - Overwrite: {}", env_path.display());
     } else {
-        println!("  - Create: {}", env_path.display());
+        status!("  - Create: {}", env_path.display());
     }
     if !rc_configured {
         if rc_path.exists() {
-            println!("  - Append to: {}", rc_path.display());
+            status!("  - Append to: {}", rc_path.display());
         } else {
-            println!("  - Create: {}", rc_path.display());
+            status!("  - Create: {}", rc_path.display());
         }
     }
-    println!();
+    if completions {
+        status!(
+            "  - Install completions: {}",
+            shell.completion_file()?.display()
+        );
+    }
+    status!();
 
     if !prompt_confirmation("Proceed with setup?")? {
-        println!("Setup cancelled.");
+        status!("Setup cancelled.");
         return Ok(());
     }
 
     fs::create_dir_all(cudup_home()?)?;
 
     fs::write(&env_path, shell.env_content())?;
-    println!();
-    println!("Created {}", env_path.display());
+    status!();
+    status!("Created {}", env_path.display());
 
     if !rc_configured {
+        // fish keeps its config under ~/.config/fish, which may not exist yet.
+        if let Some(parent) = rc_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
         let mut rc_file = fs::OpenOptions::new()
             .create(true)
             .append(true)
@@ -87,16 +120,20 @@ pub fn setup() -> Result<()> {
         writeln!(rc_file)?;
         writeln!(rc_file, "# cudup")?;
         writeln!(rc_file, "{}", shell.source_line())?;
-        println!("Updated {}", rc_path.display());
+        status!("Updated {}", rc_path.display());
+    }
+
+    if completions {
+        install_completions(shell)?;
     }
 
-    println!();
-    println!("Setup complete!");
+    status!();
+    status!("Setup complete!");
     if !rc_configured {
-        println!();
-        println!("To start using cudup, either:");
-        println!("  - Restart your terminal, or");
-        println!("  - Run: source {}", rc_path.display());
+        status!();
+        status!("To start using cudup, either:");
+        status!("  - Restart your terminal, or");
+        status!("  - Run: source {}", rc_path.display());
     }
 
     Ok(())
@@ -0,0 +1,50 @@
+use anyhow::Result;
+use std::fs;
+
+use crate::config::cudup_home;
+
+use super::{ManageContext, Shell};
+
+/// Reports the shell integration state without changing anything.
+pub fn status(shell: Option<Shell>) -> Result<()> {
+    let ctx = ManageContext::detect(shell)?;
+
+    let env_state = if !ctx.env_exists {
+        "missing"
+    } else if fs::read_to_string(&ctx.env_path)? == ctx.shell.env_content() {
+        "present"
+    } else {
+        "outdated; rerun `cudup manage setup` to refresh it"
+    };
+    let rc_state = if ctx.rc_configured {
+        "sources the env file"
+    } else if ctx.rc_path.exists() {
+        "does not source the env file"
+    } else {
+        "missing"
+    };
+
+    println!(
+        "Shell:      {} ({})",
+        ctx.shell.name(),
+        if ctx.detected {
+            "from $SHELL"
+        } else {
+            "from --shell"
+        }
+    );
+    println!("cudup home: {}", cudup_home()?.display());
+    println!("Env file:   {} ({})", ctx.env_path.display(), env_state);
+    println!("Shell rc:   {} ({})", ctx.rc_path.display(), rc_state);
+    println!();
+
+    if ctx.rc_configured && ctx.env_exists {
+        println!("Shell integration is configured.");
+        println!("If `cudup use` has no effect, open a new terminal so the wrapper is loaded.");
+    } else {
+        println!("Shell integration is not configured; `cudup use` only prints the exports.");
+        println!("Run `cudup manage setup` to configure it.");
+    }
+
+    Ok(())
+}
@@ -1,23 +1,206 @@
+pub mod cache;
 pub mod check;
+pub mod complete;
+pub mod config;
+pub mod env;
+pub mod freeze;
+pub mod gc;
+pub mod info;
 pub mod install;
 pub mod list;
+pub mod list_packages;
 pub mod local;
+pub mod lock;
 pub mod manage;
+pub mod platforms;
+pub mod prompt;
+pub mod self_uninstall;
 pub mod uninstall;
 pub mod use_version;
+pub mod verify;
 
+pub use cache::cache_stats;
 pub use check::check;
-pub use install::install;
-pub use list::list_available_versions;
-pub use local::{local_activate, local_write};
-pub use manage::{remove, setup};
+pub use complete::complete_versions;
+pub use config::{config_get, config_list, config_paths, config_set};
+pub use env::env;
+pub use freeze::freeze;
+pub use gc::gc;
+pub use info::info;
+pub use install::{
+    install, install_from_lockfile, install_manifest_only, install_versions, pick_version,
+    print_urls, repair_install, restore, resume_install,
+};
+pub use list::{ListFilters, list_available_versions};
+pub use list_packages::list_packages;
+pub use local::{local_activate, local_clear, local_write};
+pub use lock::lock;
+pub use manage::{remove, setup, status};
+pub use platforms::platforms;
+pub use prompt::prompt;
+pub use self_uninstall::self_uninstall;
 pub use uninstall::uninstall;
-pub use use_version::use_version;
+pub use use_version::{deactivate, use_version};
+pub use verify::verify;
 
-use std::path::Path;
+use anyhow::{Result, bail};
+use std::fs;
+use std::path::{Path, PathBuf};
 
-pub fn print_shell_exports(install_dir: &Path) {
-    println!("export CUDA_HOME=\"{}\"", install_dir.display());
-    println!("export PATH=\"$CUDA_HOME/bin${{PATH:+:$PATH}}\"");
-    println!("export LD_LIBRARY_PATH=\"$CUDA_HOME/lib64${{LD_LIBRARY_PATH:+:$LD_LIBRARY_PATH}}\"");
+use crate::fetch::InstallManifest;
+
+/// Total size of the files under `path`.
+fn dir_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.is_dir() {
+                size += dir_size(&path)?;
+            } else {
+                size += entry.metadata()?.len();
+            }
+        }
+    }
+    Ok(size)
+}
+
+fn get_active_version_path() -> Option<PathBuf> {
+    std::env::var("CUDA_HOME").ok().map(PathBuf::from)
+}
+
+/// Whether `CUDA_HOME` points at `version_path`.
+fn is_active_version(version_path: &Path) -> bool {
+    get_active_version_path().is_some_and(|cuda_path| {
+        match (cuda_path.canonicalize(), version_path.canonicalize()) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => cuda_path == version_path,
+        }
+    })
+}
+
+/// Whether `install_dir` only holds a plan staged by `install --manifest-only`.
+fn is_staged(install_dir: &Path) -> bool {
+    InstallManifest::load(install_dir)
+        .ok()
+        .flatten()
+        .is_some_and(|manifest| manifest.pending)
+}
+
+/// Whether `install_dir` holds an install that `install --continue-on-error` left incomplete.
+fn is_incomplete(install_dir: &Path) -> bool {
+    InstallManifest::load(install_dir)
+        .ok()
+        .flatten()
+        .is_some_and(|manifest| manifest.is_incomplete())
+}
+
+/// Fails for versions that are staged but not downloaded yet.
+fn ensure_downloaded(install_dir: &Path, version: &str) -> Result<()> {
+    if is_staged(install_dir) {
+        bail!(
+            "CUDA {} is staged but not downloaded. Run `cudup install {} --resume`.",
+            version,
+            version
+        );
+    }
+    Ok(())
+}
+
+/// Warns when `install_dir` is incomplete, naming the packages that failed.
+fn warn_if_incomplete(install_dir: &Path, version: &str) {
+    if let Ok(Some(manifest)) = InstallManifest::load(install_dir)
+        && manifest.is_incomplete()
+    {
+        log::warn!(
+            "CUDA {} is incomplete, these packages failed to install: {}",
+            version,
+            manifest.failed_packages.join(", ")
+        );
+    }
+}
+
+/// Library directories present in an install, falling back to `lib64` when neither exists.
+fn library_dirs(install_dir: &Path) -> Vec<&'static str> {
+    let dirs: Vec<&'static str> = ["lib64", "lib"]
+        .into_iter()
+        .filter(|dir| install_dir.join(dir).is_dir())
+        .collect();
+
+    if dirs.is_empty() { vec!["lib64"] } else { dirs }
+}
+
+/// Prints the activation exports. With `with_stubs`, the driver stub libraries
+/// (`lib64/stubs`) are appended to `LD_LIBRARY_PATH` for linking on machines without a driver.
+pub fn print_shell_exports(install_dir: &Path, with_stubs: bool) {
+    print!("{}", shell_exports(install_dir, with_stubs));
+}
+
+/// The lines [`print_shell_exports`] prints.
+fn shell_exports(install_dir: &Path, with_stubs: bool) -> String {
+    let dirs = library_dirs(install_dir);
+    let stub_dirs = dirs
+        .iter()
+        .map(|dir| format!("{}/stubs", dir))
+        .filter(|dir| with_stubs && install_dir.join(dir).is_dir());
+    let lib_path = dirs
+        .iter()
+        .map(|dir| dir.to_string())
+        .chain(stub_dirs)
+        .map(|dir| format!("$CUDA_HOME/{}", dir))
+        .collect::<Vec<_>>()
+        .join(":");
+
+    format!(
+        "export CUDA_HOME=\"{}\"\n\
+         export CUDA_PATH=\"$CUDA_HOME\"\n\
+         export PATH=\"$CUDA_HOME/bin${{PATH:+:$PATH}}\"\n\
+         export LD_LIBRARY_PATH=\"{lib_path}${{LD_LIBRARY_PATH:+:$LD_LIBRARY_PATH}}\"\n",
+        install_dir.display()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn library_path(exports: &str) -> &str {
+        exports
+            .lines()
+            .find_map(|line| line.strip_prefix("export LD_LIBRARY_PATH=\""))
+            .and_then(|value| value.split("${").next())
+            .expect("exports set LD_LIBRARY_PATH")
+    }
+
+    #[test]
+    fn lib_only_install_exports_lib() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("lib")).unwrap();
+
+        let exports = shell_exports(dir.path(), false);
+        assert_eq!(library_path(&exports), "$CUDA_HOME/lib");
+    }
+
+    #[test]
+    fn library_path_lists_each_present_dir() {
+        let dir = TempDir::new().unwrap();
+        assert_eq!(
+            library_path(&shell_exports(dir.path(), false)),
+            "$CUDA_HOME/lib64",
+            "lib64 is exported when neither exists"
+        );
+
+        std::fs::create_dir_all(dir.path().join("lib64/stubs")).unwrap();
+        std::fs::create_dir(dir.path().join("lib")).unwrap();
+        assert_eq!(
+            library_path(&shell_exports(dir.path(), false)),
+            "$CUDA_HOME/lib64:$CUDA_HOME/lib"
+        );
+        assert_eq!(
+            library_path(&shell_exports(dir.path(), true)),
+            "$CUDA_HOME/lib64:$CUDA_HOME/lib:$CUDA_HOME/lib64/stubs"
+        );
+    }
 }
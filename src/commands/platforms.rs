@@ -0,0 +1,55 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+
+use crate::cuda::CudaVersion;
+use crate::cuda::discover::fetch_cuda_version_metadata;
+
+/// Prints every platform key published for `version`, then groups the packages by the
+/// exact set of platforms they're available on. Meant for debugging metadata, e.g. why
+/// a release has nothing for a given architecture.
+pub async fn platforms(version: &CudaVersion) -> Result<()> {
+    let metadata = fetch_cuda_version_metadata(version.as_str())
+        .await
+        .with_context(|| format!("Failed to fetch metadata for CUDA {}", version))?;
+
+    let mut package_counts: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut groups: BTreeMap<Vec<&str>, Vec<&str>> = BTreeMap::new();
+    for name in metadata.package_names() {
+        let Some(package) = metadata.get_package(name) else {
+            continue;
+        };
+        let platforms = package.available_platforms();
+        for platform in &platforms {
+            *package_counts.entry(platform).or_default() += 1;
+        }
+        groups.entry(platforms).or_default().push(name);
+    }
+
+    let total = groups.values().map(Vec::len).sum::<usize>();
+    println!("CUDA {}: {} packages", version, total);
+    println!();
+    println!("Platforms:");
+    let width = package_counts.keys().map(|p| p.len()).max().unwrap_or(0);
+    for (platform, count) in &package_counts {
+        println!("  {:<width$}  {}/{} packages", platform, count, total);
+    }
+
+    // Widest availability first, so packages missing from some platforms stand out.
+    let mut groups: Vec<_> = groups.into_iter().collect();
+    groups.sort_by_key(|(platforms, _)| std::cmp::Reverse(platforms.len()));
+
+    for (platforms, packages) in groups {
+        println!();
+        let label = if platforms.is_empty() {
+            "(no platforms)".to_string()
+        } else {
+            platforms.join(", ")
+        };
+        println!("{} ({} packages)", label, packages.len());
+        for package in packages {
+            println!("  {}", package);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,31 @@
+use std::path::{Component, PathBuf};
+
+use super::local::{find_version_file, parse_cuda_version_file};
+use crate::config;
+
+/// Prints a short tag such as `cuda:12.4.1` for the active CUDA version, or nothing when
+/// none is active. It runs on every shell prompt, so it never touches the network and only
+/// looks at `CUDA_HOME` and the nearest `.cuda-version`.
+pub fn prompt() {
+    if let Some(version) = active_version() {
+        println!("cuda:{}", version);
+    }
+}
+
+/// The version `CUDA_HOME` points at inside the versions directory, falling back to the
+/// nearest `.cuda-version`.
+fn active_version() -> Option<String> {
+    if let Some(cuda_home) = std::env::var_os("CUDA_HOME").map(PathBuf::from)
+        && let Ok(versions_dir) = config::versions_dir()
+        && let Ok(relative) = cuda_home.strip_prefix(&versions_dir)
+        && let Some(Component::Normal(version)) = relative.components().next()
+    {
+        return Some(version.to_string_lossy().into_owned());
+    }
+
+    let path = find_version_file(true).ok()??;
+    let contents = std::fs::read_to_string(path).ok()?;
+    parse_cuda_version_file(&contents)
+        .ok()
+        .map(|config| config.cuda_version.to_string())
+}
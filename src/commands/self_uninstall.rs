@@ -0,0 +1,118 @@
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::config::cudup_home;
+use crate::fetch::format_size;
+use crate::output::status;
+
+use super::dir_size;
+use super::manage::{Shell, is_rc_configured, unconfigure_rc};
+
+/// Phrase the user must type to confirm, since there is no undo.
+const CONFIRMATION_PHRASE: &str = "yes";
+
+fn prompt_phrase(message: &str) -> Result<bool> {
+    print!("{} Type '{}' to confirm: ", message, CONFIRMATION_PHRASE);
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+
+    Ok(input.trim() == CONFIRMATION_PHRASE)
+}
+
+/// `CUDA_HOME`, when it points into the cudup home.
+fn active_managed_cuda(home: &Path) -> Option<PathBuf> {
+    let cuda_home = PathBuf::from(std::env::var_os("CUDA_HOME")?);
+    let resolved = cuda_home.canonicalize().unwrap_or(cuda_home);
+    let home = home.canonicalize().unwrap_or_else(|_| home.to_path_buf());
+    resolved.starts_with(&home).then_some(resolved)
+}
+
+/// Removes the shell integration, completions, and the whole cudup home directory.
+pub fn self_uninstall(force: bool) -> Result<()> {
+    let home = cudup_home()?;
+    let home_exists = home.exists();
+
+    let mut rc_files = Vec::new();
+    let mut completion_files = Vec::new();
+    for shell in Shell::ALL {
+        let rc_path = shell.rc_file()?;
+        if is_rc_configured(&rc_path)? {
+            rc_files.push(rc_path);
+        }
+        let completion_path = shell.completion_file()?;
+        if completion_path.exists() {
+            completion_files.push(completion_path);
+        }
+    }
+
+    if !home_exists && rc_files.is_empty() && completion_files.is_empty() {
+        status!("Nothing to remove: {} does not exist.", home.display());
+        return Ok(());
+    }
+
+    if let Some(active) = active_managed_cuda(&home)
+        && !force
+    {
+        bail!(
+            "CUDA_HOME points to {}, which would be deleted. \
+             Open a new shell without it, or use --force to remove anyway.",
+            active.display()
+        );
+    }
+
+    status!("This will permanently remove:");
+    if home_exists {
+        status!(
+            "  - {} ({}, including every installed CUDA version)",
+            home.display(),
+            format_size(dir_size(&home)?)
+        );
+    }
+    for rc_path in &rc_files {
+        status!("  - cudup lines from {}", rc_path.display());
+    }
+    for completion_path in &completion_files {
+        status!("  - {}", completion_path.display());
+    }
+    status!();
+
+    if !force && !prompt_phrase("Remove cudup completely?")? {
+        status!("Self-uninstall cancelled.");
+        return Ok(());
+    }
+
+    for rc_path in &rc_files {
+        unconfigure_rc(rc_path)?;
+        status!("Updated {}", rc_path.display());
+    }
+    for completion_path in &completion_files {
+        fs::remove_file(completion_path)
+            .with_context(|| format!("Failed to remove {}", completion_path.display()))?;
+        status!("Deleted {}", completion_path.display());
+    }
+    if home_exists {
+        fs::remove_dir_all(&home)
+            .with_context(|| format!("Failed to remove {}", home.display()))?;
+        status!("Deleted {}", home.display());
+    }
+
+    status!();
+    match std::env::current_exe() {
+        // Installers that put the binary under the cudup home have already removed it.
+        Ok(exe) if exe.exists() => {
+            status!("cudup's data has been removed. To finish, delete the binary itself:");
+            status!("  rm {}", exe.display());
+        }
+        Ok(_) => status!("cudup has been removed."),
+        Err(_) => {
+            status!("cudup's data has been removed. To finish, delete the binary itself:");
+            status!("  rm \"$(command -v cudup)\"");
+        }
+    }
+
+    Ok(())
+}
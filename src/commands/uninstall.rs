@@ -1,40 +1,28 @@
 use anyhow::{Context, Result, bail};
-use std::path::{Path, PathBuf};
-use std::{env, fs};
+use std::fs;
+use std::path::Path;
 
-use crate::config::{get_installed_versions, prompt_confirmation, versions_dir};
+use crate::config::{active_env_path, get_installed_versions, prompt_confirmation, versions_dir};
 use crate::fetch::format_size;
-
-fn dir_size(path: &Path) -> Result<u64> {
-    let mut size = 0;
-    if path.is_dir() {
-        for entry in fs::read_dir(path)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_dir() {
-                size += dir_size(&path)?;
-            } else {
-                size += entry.metadata()?.len();
-            }
-        }
+use crate::output::status;
+
+use super::{dir_size, is_active_version};
+
+/// Drops the activation persisted by `use --export-only` when it points at a removed
+/// version, so new shells don't start with a dangling `CUDA_HOME`.
+fn forget_persisted(version: &str, version_path: &Path) {
+    let Ok(path) = active_env_path() else {
+        return;
+    };
+    let target = format!("CUDA_HOME=\"{}\"", version_path.display());
+    if fs::read_to_string(&path).is_ok_and(|contents| contents.contains(&target))
+        && fs::remove_file(&path).is_ok()
+    {
+        status!("New shells will no longer activate CUDA {}", version);
     }
-    Ok(size)
 }
 
-fn get_active_version_path() -> Option<PathBuf> {
-    env::var("CUDA_HOME").ok().map(PathBuf::from)
-}
-
-fn is_active_version(version_path: &Path) -> bool {
-    get_active_version_path().is_some_and(|cuda_path| {
-        match (cuda_path.canonicalize(), version_path.canonicalize()) {
-            (Ok(a), Ok(b)) => a == b,
-            _ => cuda_path == version_path,
-        }
-    })
-}
-
-fn uninstall_single(version: &str, force: bool) -> Result<()> {
+fn uninstall_single(version: &str, force: bool, dry_run: bool) -> Result<()> {
     let versions_dir = versions_dir()?;
     let version_path = versions_dir.join(version);
 
@@ -46,16 +34,21 @@ fn uninstall_single(version: &str, force: bool) -> Result<()> {
 
     let size = dir_size(&version_path)?;
 
-    println!("This will remove CUDA {}:", version);
-    println!("  - {} ({})", version_path.display(), format_size(size));
+    status!("This will remove CUDA {}:", version);
+    status!("  - {} ({})", version_path.display(), format_size(size));
 
     if is_active {
-        println!();
-        println!("Warning: This version is currently active (CUDA_HOME points to it).");
-        println!("Your current shell environment will have invalid CUDA paths after removal.");
+        status!();
+        status!("Warning: This version is currently active (CUDA_HOME points to it).");
+        status!("Your current shell environment will have invalid CUDA paths after removal.");
     }
 
-    println!();
+    status!();
+
+    if dry_run {
+        status!("Dry run: nothing was removed.");
+        return Ok(());
+    }
 
     if !force {
         let prompt = if is_active {
@@ -65,15 +58,16 @@ fn uninstall_single(version: &str, force: bool) -> Result<()> {
         };
 
         if !prompt_confirmation(prompt)? {
-            println!("Uninstall cancelled.");
+            status!("Uninstall cancelled.");
             return Ok(());
         }
     }
 
     match fs::remove_dir_all(&version_path) {
         Ok(()) => {
-            println!();
-            println!("Removed CUDA {}", version);
+            status!();
+            status!("Removed CUDA {}", version);
+            forget_persisted(version, &version_path);
         }
         Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
             bail!("CUDA {} was already removed by another process", version);
@@ -84,20 +78,20 @@ fn uninstall_single(version: &str, force: bool) -> Result<()> {
     }
 
     if is_active {
-        println!();
-        println!("Run 'cudup use <version>' to activate a different version,");
-        println!("or start a new shell to clear the stale CUDA_HOME.");
+        status!();
+        status!("Run 'cudup use <version>' to activate a different version,");
+        status!("or start a new shell to clear the stale CUDA_HOME.");
     }
 
     Ok(())
 }
 
-fn uninstall_all(force: bool) -> Result<()> {
+fn uninstall_all(force: bool, dry_run: bool) -> Result<()> {
     let versions_dir = versions_dir()?;
     let versions = get_installed_versions()?;
 
     if versions.is_empty() {
-        println!("No CUDA versions installed.");
+        status!("No CUDA versions installed.");
         return Ok(());
     }
 
@@ -116,7 +110,7 @@ fn uninstall_all(force: bool) -> Result<()> {
     }
 
     let mut total_size = 0u64;
-    println!("This will remove {} CUDA version(s):", versions.len());
+    status!("This will remove {} CUDA version(s):", versions.len());
     for version in &versions {
         let version_path = versions_dir.join(version);
         let size = dir_size(&version_path)?;
@@ -127,21 +121,26 @@ fn uninstall_all(force: bool) -> Result<()> {
         } else {
             ""
         };
-        println!("  - {}{} ({})", version, active_marker, format_size(size));
+        status!("  - {}{} ({})", version, active_marker, format_size(size));
     }
-    println!();
-    println!("Total: {}", format_size(total_size));
+    status!();
+    status!("Total: {}", format_size(total_size));
 
     if active_version.is_some() {
-        println!();
-        println!("Warning: The active version will be removed.");
-        println!("Your current shell environment will have invalid CUDA paths after removal.");
+        status!();
+        status!("Warning: The active version will be removed.");
+        status!("Your current shell environment will have invalid CUDA paths after removal.");
     }
 
-    println!();
+    status!();
+
+    if dry_run {
+        status!("Dry run: nothing was removed.");
+        return Ok(());
+    }
 
     if !force && !prompt_confirmation("Proceed with uninstall?")? {
-        println!("Uninstall cancelled.");
+        status!("Uninstall cancelled.");
         return Ok(());
     }
 
@@ -150,11 +149,12 @@ fn uninstall_all(force: bool) -> Result<()> {
         let version_path = versions_dir.join(version);
         match fs::remove_dir_all(&version_path) {
             Ok(()) => {
-                println!("Removed CUDA {}", version);
+                status!("Removed CUDA {}", version);
+                forget_persisted(version, &version_path);
                 removed_count += 1;
             }
             Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-                println!("CUDA {} was already removed", version);
+                status!("CUDA {} was already removed", version);
             }
             Err(e) => {
                 return Err(e).context(format!("Failed to remove CUDA {}", version));
@@ -162,21 +162,23 @@ fn uninstall_all(force: bool) -> Result<()> {
         }
     }
 
-    println!();
-    println!("Removed {} version(s)", removed_count);
+    status!();
+    status!("Removed {} version(s)", removed_count);
 
     if active_version.is_some() {
-        println!();
-        println!("Start a new shell to clear the stale CUDA_HOME.");
+        status!();
+        status!("Start a new shell to clear the stale CUDA_HOME.");
     }
 
     Ok(())
 }
 
-pub fn uninstall(version: Option<&str>, force: bool, all: bool) -> Result<()> {
+/// Removes one or all installed versions. With `dry_run`, prints what would be removed
+/// and stops before prompting.
+pub fn uninstall(version: Option<&str>, force: bool, all: bool, dry_run: bool) -> Result<()> {
     match (all, version) {
-        (true, _) => uninstall_all(force),
-        (false, Some(v)) => uninstall_single(v, force),
+        (true, _) => uninstall_all(force, dry_run),
+        (false, Some(v)) => uninstall_single(v, force, dry_run),
         (false, None) => bail!("Please specify a version or use --all"),
     }
 }
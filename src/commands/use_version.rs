@@ -1,15 +1,145 @@
-use anyhow::{Result, bail};
+use anyhow::{Context, Result, bail};
+use std::io::ErrorKind;
+use std::path::Path;
+use std::{env, fs};
 
-use crate::fetch;
+use super::manage::Shell;
+use crate::cuda::CudaVersion;
+use crate::{config, fetch, output};
+
+/// Picks the version for a bare `cudup use`: `.cuda-version`, then
+/// `install.default_version`, then the newest installed version.
+fn resolve_version() -> Result<(CudaVersion, String)> {
+    if let Some((version, source)) = super::env::configured_version()? {
+        return Ok((version, format!("from {}", source)));
+    }
+
+    let versions_dir = config::versions_dir()?;
+    let newest = config::get_installed_versions()?
+        .into_iter()
+        .filter(|version| !super::is_staged(&versions_dir.join(version)))
+        .filter_map(|version| CudaVersion::new(version).ok())
+        .max();
+    match newest {
+        Some(version) => Ok((version, "newest installed".to_string())),
+        None => bail!("No CUDA versions installed. Run `cudup install <version>` first."),
+    }
+}
+
+/// Replaces the persisted activation with `contents`, writing a sibling file first so
+/// a shell starting mid-write never sources a truncated one.
+fn write_active_env(contents: &str) -> Result<()> {
+    let path = config::active_env_path()?;
+    let tmp_path = path.with_extension("env.tmp");
+    fs::write(&tmp_path, contents)?;
+    fs::rename(&tmp_path, &path).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+/// Activates `version`, or the one [`resolve_version`] picks when none is given. With
+/// `persist`, the exports are also saved for new shells to pick up.
+pub fn use_version(version: Option<&CudaVersion>, with_stubs: bool, persist: bool) -> Result<()> {
+    let version = match version {
+        Some(version) => version.clone(),
+        None => {
+            let (version, reason) = resolve_version()?;
+            if !output::is_quiet() {
+                eprintln!("Using CUDA {} ({})", version, reason);
+            }
+            version
+        }
+    };
+    let version = version.as_str();
 
-pub fn use_version(version: &str) -> Result<()> {
     let install_dir = fetch::version_install_dir(version)?;
     if !install_dir.exists() {
         bail!("CUDA {} is not installed", version);
     }
+    super::ensure_downloaded(&install_dir, version)?;
+    super::warn_if_incomplete(&install_dir, version);
+
+    let header = format!("# CUDA {} activated\n", version);
+    let exports = super::shell_exports(&install_dir, with_stubs);
+    if persist {
+        write_active_env(&format!("{}{}", header, exports))?;
+        if !output::is_quiet() {
+            eprintln!("CUDA {} will be active in new shells", version);
+        }
+    }
+    print!("{}{}", header, exports);
+
+    Ok(())
+}
+
+/// Quotes `value` so the shell takes it literally.
+fn quote(shell: Shell, value: &str) -> String {
+    match shell {
+        Shell::Bash | Shell::Zsh => format!("'{}'", value.replace('\'', r"'\''")),
+        Shell::Fish => format!("'{}'", value.replace('\\', r"\\").replace('\'', r"\'")),
+    }
+}
+
+fn print_unset(shell: Shell, name: &str) {
+    match shell {
+        Shell::Bash | Shell::Zsh => println!("unset {}", name),
+        Shell::Fish => println!("set -e {}", name),
+    }
+}
+
+/// Rewrites a colon-separated path variable without the entries under `versions_dir`,
+/// unsetting it when nothing else is left.
+fn print_filtered_path(shell: Shell, name: &str, versions_dir: &Path) {
+    let Ok(value) = env::var(name) else {
+        return;
+    };
+    let (removed, kept): (Vec<&str>, Vec<&str>) = value
+        .split(':')
+        .filter(|entry| !entry.is_empty())
+        .partition(|entry| Path::new(entry).starts_with(versions_dir));
+    if removed.is_empty() {
+        return;
+    }
 
-    println!("# CUDA {} activated", version);
-    super::print_shell_exports(&install_dir);
+    if kept.is_empty() {
+        print_unset(shell, name);
+        return;
+    }
+    match shell {
+        Shell::Bash | Shell::Zsh => println!("export {}={}", name, quote(shell, &kept.join(":"))),
+        // fish splits PATH into a list; other variables stay colon-joined strings.
+        Shell::Fish if name == "PATH" => {
+            let entries: Vec<String> = kept.iter().map(|entry| quote(shell, entry)).collect();
+            println!("set -gx PATH {}", entries.join(" "));
+        }
+        Shell::Fish => println!("set -gx {} {}", name, quote(shell, &kept.join(":"))),
+    }
+}
+
+/// Prints the commands that undo `cudup use` in the current shell: `CUDA_HOME` and
+/// `CUDA_PATH` are unset if they point into cudup, and cudup's entries are dropped from
+/// `PATH` and `LD_LIBRARY_PATH`. A system CUDA set up outside cudup is left alone.
+pub fn deactivate() -> Result<()> {
+    let shell = Shell::detect().unwrap_or(Shell::Bash);
+    let versions_dir = config::versions_dir()?;
+
+    match fs::remove_file(config::active_env_path()?) {
+        Ok(()) => {
+            if !output::is_quiet() {
+                eprintln!("New shells will no longer activate CUDA");
+            }
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e).context("Failed to remove the persisted CUDA version"),
+    }
+
+    println!("# CUDA deactivated");
+    for name in ["CUDA_HOME", "CUDA_PATH"] {
+        if env::var_os(name).is_some_and(|value| Path::new(&value).starts_with(&versions_dir)) {
+            print_unset(shell, name);
+        }
+    }
+    print_filtered_path(shell, "PATH", &versions_dir);
+    print_filtered_path(shell, "LD_LIBRARY_PATH", &versions_dir);
 
     Ok(())
 }
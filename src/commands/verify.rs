@@ -0,0 +1,54 @@
+use anyhow::{Result, bail};
+use log::info;
+
+use crate::cuda::CudaVersion;
+use crate::fetch::{self, InstallManifest};
+
+/// Re-hashes the files of an installed version against the hashes recorded at install time.
+pub async fn verify(version: &CudaVersion) -> Result<()> {
+    let install_dir = fetch::version_install_dir(version.as_str())?;
+    if !install_dir.exists() {
+        bail!("CUDA {} is not installed", version);
+    }
+
+    let manifest = InstallManifest::load(&install_dir)?
+        .filter(|manifest| !manifest.files.is_empty())
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "CUDA {} has no recorded file hashes. Reinstall it to enable verification.",
+                version
+            )
+        })?;
+
+    info!(
+        "Verifying {} files in {}",
+        manifest.files.len(),
+        install_dir.display()
+    );
+
+    let mut failures = 0;
+    for (file, expected) in &manifest.files {
+        let path = install_dir.join(file);
+        if !path.is_file() {
+            println!("[MISSING] {}", file);
+            failures += 1;
+            continue;
+        }
+
+        if fetch::sha256_file(&path).await? != *expected {
+            println!("[MODIFIED] {}", file);
+            failures += 1;
+        }
+    }
+
+    if failures > 0 {
+        bail!(
+            "{} of {} files failed verification",
+            failures,
+            manifest.files.len()
+        );
+    }
+
+    println!("All {} files verified", manifest.files.len());
+    Ok(())
+}
@@ -1,17 +1,66 @@
-use anyhow::{Context, Result};
+mod settings;
+
+use anyhow::{Result, bail};
 use std::fs;
 use std::io::{self, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+pub use settings::{CONFIG_KEYS, Config, get, init, load_config, save_config};
+
+/// The user's home directory: `$HOME`, else the passwd entry, else `/root` when running
+/// as root, as minimal containers often run without `HOME` or a passwd entry.
+pub fn home_dir() -> Result<PathBuf> {
+    resolve_home(dirs::home_dir(), rustix::process::geteuid().is_root())
+}
+
+/// Picks the home directory from what `dirs` found and whether cudup runs as root.
+fn resolve_home(found: Option<PathBuf>, is_root: bool) -> Result<PathBuf> {
+    if let Some(home) = found.filter(|home| !home.as_os_str().is_empty()) {
+        return Ok(home);
+    }
+    if is_root {
+        return Ok(PathBuf::from("/root"));
+    }
+    bail!(
+        "Could not determine the home directory. Set HOME, or set CUDUP_HOME to an absolute path."
+    )
+}
+
+/// Resolves a `CUDUP_HOME` value: a leading `~` expands to the home directory and
+/// relative paths are taken relative to it rather than the current directory. `home` is
+/// only looked up when needed, so an absolute value works without a home directory.
+fn resolve_custom_home(
+    custom_home: &str,
+    home: impl FnOnce() -> Result<PathBuf>,
+) -> Result<PathBuf> {
+    let path = Path::new(custom_home);
+    if path.is_absolute() {
+        return Ok(path.to_path_buf());
+    }
+
+    let home = home()?;
+    let relative = match custom_home.strip_prefix('~') {
+        Some("") => return Ok(home),
+        Some(rest) if rest.starts_with('/') => rest.trim_start_matches('/'),
+        _ => custom_home,
+    };
+
+    Ok(home.join(relative))
+}
 
 pub fn cudup_home() -> Result<PathBuf> {
     if let Ok(custom_home) = std::env::var("CUDUP_HOME") {
-        return Ok(PathBuf::from(custom_home));
+        return resolve_custom_home(&custom_home, home_dir);
     }
-    let home = dirs::home_dir().context("Could not determine home directory")?;
-    Ok(home.join(".cudup"))
+    Ok(home_dir()?.join(".cudup"))
 }
 
+/// Where versions are installed: `CUDUP_VERSIONS_DIR` if set, resolved like `CUDUP_HOME`,
+/// else `versions` under the cudup home.
 pub fn versions_dir() -> Result<PathBuf> {
+    if let Ok(custom_dir) = std::env::var("CUDUP_VERSIONS_DIR") {
+        return resolve_custom_home(&custom_dir, home_dir);
+    }
     Ok(cudup_home()?.join("versions"))
 }
 
@@ -19,6 +68,15 @@ pub fn downloads_dir() -> Result<PathBuf> {
     Ok(cudup_home()?.join("downloads"))
 }
 
+/// Exports written by `cudup use --export-only`, sourced by the shell wrapper at startup.
+pub fn active_env_path() -> Result<PathBuf> {
+    Ok(cudup_home()?.join("active.env"))
+}
+
+pub fn config_path() -> Result<PathBuf> {
+    Ok(cudup_home()?.join("config.toml"))
+}
+
 pub fn prompt_confirmation(message: &str) -> Result<bool> {
     print!("{} [y/N] ", message);
     io::stdout().flush()?;
@@ -36,9 +94,64 @@ pub fn get_installed_versions() -> Result<Vec<String>> {
         return Ok(vec![]);
     }
 
+    // Dot directories are in-progress installs (`.12.4.1.tmp`), not versions.
     Ok(fs::read_dir(versions_path)?
         .filter_map(|e| e.ok())
         .filter(|e| e.path().is_dir())
         .filter_map(|e| e.file_name().into_string().ok())
+        .filter(|name| !name.starts_with('.'))
         .collect())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn home_dir_prefers_the_detected_home() {
+        let home = resolve_home(Some(PathBuf::from("/home/dev")), true).unwrap();
+        assert_eq!(home, Path::new("/home/dev"));
+    }
+
+    #[test]
+    fn root_without_a_home_falls_back_to_slash_root() {
+        assert_eq!(resolve_home(None, true).unwrap(), Path::new("/root"));
+        assert_eq!(
+            resolve_home(Some(PathBuf::new()), true).unwrap(),
+            Path::new("/root")
+        );
+    }
+
+    #[test]
+    fn other_users_without_a_home_get_an_actionable_error() {
+        let error = resolve_home(None, false).unwrap_err();
+        assert!(error.to_string().contains("Set HOME"), "{error}");
+        assert!(resolve_home(Some(PathBuf::new()), false).is_err());
+    }
+
+    fn resolve(custom_home: &str) -> PathBuf {
+        resolve_custom_home(custom_home, || Ok(PathBuf::from("/home/dev"))).unwrap()
+    }
+
+    #[test]
+    fn custom_home_expands_a_leading_tilde() {
+        assert_eq!(resolve("~"), Path::new("/home/dev"));
+        assert_eq!(resolve("~/cuda"), Path::new("/home/dev/cuda"));
+        assert_eq!(resolve("~//cuda/sub"), Path::new("/home/dev/cuda/sub"));
+    }
+
+    #[test]
+    fn relative_custom_home_is_taken_from_the_home_dir() {
+        assert_eq!(resolve("cuda"), Path::new("/home/dev/cuda"));
+        assert_eq!(resolve("./cuda"), Path::new("/home/dev/./cuda"));
+        // Only a bare `~` prefix is the home directory; `~user` is a relative name.
+        assert_eq!(resolve("~other/cuda"), Path::new("/home/dev/~other/cuda"));
+    }
+
+    #[test]
+    fn absolute_custom_home_is_used_without_a_home_dir() {
+        let resolved =
+            resolve_custom_home("/opt/cudup", || anyhow::bail!("no home directory")).unwrap();
+        assert_eq!(resolved, Path::new("/opt/cudup"));
+    }
+}
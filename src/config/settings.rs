@@ -0,0 +1,245 @@
+use anyhow::{Context, Result, bail};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::sync::OnceLock;
+
+use super::{config_path, cudup_home};
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Keys accepted by `cudup config get/set`.
+pub const CONFIG_KEYS: &[&str] = &[
+    "mirror.cuda",
+    "mirror.cudnn",
+    "mirror.cuda_fallbacks",
+    "mirror.cudnn_fallbacks",
+    "download.concurrency",
+    "cache.max_bytes",
+    "install.default_arch",
+    "install.default_version",
+    "local.max_depth",
+    "local.root_markers",
+    "network.proxy",
+    "hooks.post_install",
+];
+
+/// User configuration loaded from `~/.cudup/config.toml`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    #[serde(skip_serializing_if = "is_default")]
+    pub mirror: MirrorConfig,
+    #[serde(skip_serializing_if = "is_default")]
+    pub download: DownloadConfig,
+    #[serde(skip_serializing_if = "is_default")]
+    pub cache: CacheConfig,
+    #[serde(skip_serializing_if = "is_default")]
+    pub install: InstallConfig,
+    #[serde(skip_serializing_if = "is_default")]
+    pub local: LocalConfig,
+    #[serde(skip_serializing_if = "is_default")]
+    pub network: NetworkConfig,
+    #[serde(skip_serializing_if = "is_default")]
+    pub hooks: HooksConfig,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MirrorConfig {
+    /// Base URL replacing the NVIDIA CUDA redist URL.
+    pub cuda: Option<String>,
+    /// Base URL replacing the NVIDIA cuDNN redist URL.
+    pub cudnn: Option<String>,
+    /// Base URLs tried in order when a CUDA download fails.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cuda_fallbacks: Vec<String>,
+    /// Base URLs tried in order when a cuDNN download fails.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cudnn_fallbacks: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DownloadConfig {
+    /// Number of packages downloaded at once.
+    pub concurrency: Option<usize>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Upper bound on the metadata cache size.
+    pub max_bytes: Option<u64>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InstallConfig {
+    /// Architecture to install for when `--arch` isn't given (`x86_64` or `aarch64`).
+    pub default_arch: Option<String>,
+    /// Version `cudup env` activates when no `.cuda-version` file is found.
+    pub default_version: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LocalConfig {
+    /// How many parent directories to search for `.cuda-version`.
+    pub max_depth: Option<usize>,
+    /// Files or directories marking a project root, where the `.cuda-version` search
+    /// stops. Defaults to `.git` and `.hg`.
+    pub root_markers: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkConfig {
+    /// Proxy URL applied to every request (e.g. `http://proxy.corp:3128`).
+    pub proxy: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    /// Shell command run after a successful install.
+    pub post_install: Option<String>,
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+fn join_list(values: &[String]) -> Option<String> {
+    (!values.is_empty()).then(|| values.join(","))
+}
+
+/// Parses a comma-separated list, dropping empty entries.
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Parses a comma-separated list of base URLs, dropping trailing slashes.
+fn parse_url_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|url| url.trim().trim_end_matches('/'))
+        .filter(|url| !url.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn parse_value<T: std::str::FromStr>(key: &str, value: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid value '{}' for {}", value, key))
+}
+
+impl Config {
+    pub fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(match key {
+            "mirror.cuda" => self.mirror.cuda.clone(),
+            "mirror.cudnn" => self.mirror.cudnn.clone(),
+            "mirror.cuda_fallbacks" => join_list(&self.mirror.cuda_fallbacks),
+            "mirror.cudnn_fallbacks" => join_list(&self.mirror.cudnn_fallbacks),
+            "download.concurrency" => self.download.concurrency.map(|v| v.to_string()),
+            "cache.max_bytes" => self.cache.max_bytes.map(|v| v.to_string()),
+            "install.default_arch" => self.install.default_arch.clone(),
+            "install.default_version" => self.install.default_version.clone(),
+            "local.max_depth" => self.local.max_depth.map(|v| v.to_string()),
+            "local.root_markers" => self.local.root_markers.as_ref().map(|m| m.join(",")),
+            "network.proxy" => self.network.proxy.clone(),
+            "hooks.post_install" => self.hooks.post_install.clone(),
+            _ => bail!(
+                "Unknown config key '{}'. Supported keys: {}",
+                key,
+                CONFIG_KEYS.join(", ")
+            ),
+        })
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "mirror.cuda" => self.mirror.cuda = Some(value.trim_end_matches('/').to_string()),
+            "mirror.cudnn" => self.mirror.cudnn = Some(value.trim_end_matches('/').to_string()),
+            "mirror.cuda_fallbacks" => self.mirror.cuda_fallbacks = parse_url_list(value),
+            "mirror.cudnn_fallbacks" => self.mirror.cudnn_fallbacks = parse_url_list(value),
+            "download.concurrency" => {
+                let concurrency: usize = parse_value(key, value)?;
+                if concurrency == 0 {
+                    bail!("download.concurrency must be at least 1");
+                }
+                self.download.concurrency = Some(concurrency);
+            }
+            "cache.max_bytes" => self.cache.max_bytes = Some(parse_value(key, value)?),
+            "install.default_arch" => match value {
+                "x86_64" | "aarch64" => self.install.default_arch = Some(value.to_string()),
+                _ => bail!(
+                    "Unsupported architecture '{}'. Supported: x86_64, aarch64",
+                    value
+                ),
+            },
+            "install.default_version" => {
+                let version = crate::cuda::CudaVersion::new(value)?;
+                self.install.default_version = Some(version.to_string());
+            }
+            "local.max_depth" => self.local.max_depth = Some(parse_value(key, value)?),
+            "local.root_markers" => self.local.root_markers = Some(parse_list(value)),
+            "network.proxy" => {
+                reqwest::Proxy::all(value)
+                    .with_context(|| format!("Invalid proxy URL '{}'", value))?;
+                self.network.proxy = Some(value.to_string());
+            }
+            "hooks.post_install" => self.hooks.post_install = Some(value.to_string()),
+            _ => bail!(
+                "Unknown config key '{}'. Supported keys: {}",
+                key,
+                CONFIG_KEYS.join(", ")
+            ),
+        }
+        Ok(())
+    }
+}
+
+/// Loads the config file, returning defaults when it doesn't exist.
+pub fn load_config() -> Result<Config> {
+    let path = config_path()?;
+
+    match fs::read_to_string(&path) {
+        Ok(contents) => {
+            toml::from_str(&contents).with_context(|| format!("Failed to parse {}", path.display()))
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Config::default()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+pub fn save_config(config: &Config) -> Result<()> {
+    let path = config_path()?;
+    fs::create_dir_all(cudup_home()?)?;
+
+    let contents = toml::to_string_pretty(config).context("Failed to serialize config")?;
+    fs::write(&path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Loads the config file once at startup so every command sees the same settings. A
+/// file that can't be read falls back to defaults with a warning rather than failing
+/// every command, including the `cudup config set` that would fix it.
+pub fn init() {
+    let config = load_config().unwrap_or_else(|e| {
+        warn!("{:#}; using default settings", e);
+        Config::default()
+    });
+    let _ = CONFIG.set(config);
+}
+
+/// Returns the startup config, or defaults if `init` was never called.
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
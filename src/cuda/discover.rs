@@ -1,68 +1,94 @@
+use crate::cache;
 use crate::cuda::metadata::CudaReleaseMetadata;
+use crate::cuda::source::{Source, cuda_source, cudnn_source};
+use crate::error::CudupError;
+use crate::fetch::http;
 use anyhow::{Context, Result};
-use reqwest::Client;
 use std::collections::BTreeSet;
 use std::sync::LazyLock;
-use std::time::Duration;
 
 static VERSION_REGEX: LazyLock<regex::Regex> = LazyLock::new(|| {
     regex::Regex::new(r"redistrib_(\d+\.\d+\.\d+)\.json").expect("invalid version regex pattern")
 });
 
-static HTTP_CLIENT: LazyLock<Client> = LazyLock::new(|| {
-    Client::builder()
-        .timeout(Duration::from_secs(30))
-        .connect_timeout(Duration::from_secs(10))
-        .build()
-        .expect("Failed to create HTTP client")
-});
-
-pub const CUDA_BASE_URL: &str = "https://developer.download.nvidia.com/compute/cuda/redist";
-pub const CUDNN_BASE_URL: &str = "https://developer.download.nvidia.com/compute/cudnn/redist";
+pub async fn fetch_available_versions(
+    source: &dyn Source,
+    product: &str,
+) -> Result<BTreeSet<String>> {
+    if let Some(versions) = cache::load_cached_versions(product, cache::refresh_requested())? {
+        return Ok(versions);
+    }
 
-async fn fetch_available_versions(base_url: &str, product: &str) -> Result<BTreeSet<String>> {
-    let response = HTTP_CLIENT
-        .get(format!("{}/", base_url))
+    let response = http::client()
+        .get(source.index_url())
+        .timeout(http::METADATA_TIMEOUT)
         .send()
         .await
-        .with_context(|| format!("Failed to fetch {} versions", product))?;
+        .map_err(|source| CudupError::Network {
+            context: format!("Failed to fetch {} versions", product),
+            source,
+        })?;
 
     let body = response.text().await?;
+    let versions = parse_available_versions(&body);
 
-    Ok(parse_available_versions(&body))
-}
+    if !versions.is_empty() {
+        cache::save_versions(product, &versions);
+    }
 
-async fn fetch_version_metadata(
-    base_url: &str,
-    product: &str,
-    version: &str,
-) -> Result<CudaReleaseMetadata> {
-    let url = format!("{}/redistrib_{}.json", base_url, version);
+    Ok(versions)
+}
 
-    let response = HTTP_CLIENT
-        .get(&url)
+async fn fetch_metadata_url(url: &str, context: &str) -> Result<CudaReleaseMetadata> {
+    let response = http::client()
+        .get(url)
+        .timeout(http::METADATA_TIMEOUT)
         .send()
         .await
-        .with_context(|| format!("Failed to fetch {} {} metadata", product, version))?;
+        .map_err(|source| CudupError::Network {
+            context: context.to_string(),
+            source,
+        })?;
 
     if !response.status().is_success() {
-        anyhow::bail!(
-            "Failed to fetch {} {} metadata: HTTP {}",
-            product,
-            version,
-            response.status()
-        );
+        return Err(CudupError::HttpStatus {
+            context: context.to_string(),
+            status: response.status(),
+        }
+        .into());
     }
 
     response.json().await.context("failed to parse metadata")
 }
 
+pub async fn fetch_version_metadata(
+    source: &dyn Source,
+    product: &str,
+    version: &str,
+) -> Result<CudaReleaseMetadata> {
+    if let Some(metadata) =
+        cache::load_cached_metadata(product, version, cache::refresh_requested())?
+    {
+        return Ok(metadata);
+    }
+
+    let url = source.metadata_url(version);
+    let metadata = fetch_metadata_url(
+        &url,
+        &format!("Failed to fetch {} {} metadata", product, version),
+    )
+    .await?;
+    cache::save_metadata(product, version, &metadata);
+
+    Ok(metadata)
+}
+
 pub async fn fetch_available_cuda_versions() -> Result<BTreeSet<String>> {
-    fetch_available_versions(CUDA_BASE_URL, "CUDA").await
+    fetch_available_versions(&*cuda_source(), "CUDA").await
 }
 
 pub async fn fetch_available_cudnn_versions() -> Result<BTreeSet<String>> {
-    fetch_available_versions(CUDNN_BASE_URL, "cuDNN").await
+    fetch_available_versions(&*cudnn_source(), "cuDNN").await
 }
 
 pub fn parse_available_versions(html: &str) -> BTreeSet<String> {
@@ -73,31 +99,73 @@ pub fn parse_available_versions(html: &str) -> BTreeSet<String> {
 }
 
 pub async fn fetch_cuda_version_metadata(version: &str) -> Result<CudaReleaseMetadata> {
-    fetch_version_metadata(CUDA_BASE_URL, "CUDA", version).await
+    fetch_version_metadata(&*cuda_source(), "CUDA", version).await
 }
 
-/// Finds the newest cuDNN version compatible with a given CUDA major version.
-pub async fn find_newest_compatible_cudnn(cuda_version: &str) -> Result<Option<String>> {
-    let cuda_major = cuda_version
+/// Fetches CUDA metadata from an explicit redist filename (e.g. a hotfix manifest),
+/// bypassing the cache since the file isn't tied to a listed version.
+pub async fn fetch_cuda_redist_file(filename: &str) -> Result<CudaReleaseMetadata> {
+    if filename.contains('/') || !filename.ends_with(".json") {
+        anyhow::bail!(
+            "Invalid redist file '{}': expected a filename like redistrib_12.4.1.json",
+            filename
+        );
+    }
+
+    let url = cuda_source().package_url(filename);
+    let context = format!("Failed to fetch redist file {}", filename);
+
+    match fetch_metadata_url(&url, &context).await {
+        Err(e)
+            if matches!(
+                e.downcast_ref::<CudupError>(),
+                Some(CudupError::HttpStatus { status, .. }) if *status == reqwest::StatusCode::NOT_FOUND
+            ) =>
+        {
+            anyhow::bail!("Redist file {} does not exist at {}", filename, url)
+        }
+        result => result.with_context(|| format!("Invalid redist file {}", filename)),
+    }
+}
+
+fn cuda_major(cuda_version: &str) -> Result<&str> {
+    cuda_version
         .split('.')
         .next()
-        .context("Invalid CUDA version format")?;
+        .filter(|major| !major.is_empty())
+        .context("Invalid CUDA version format")
+}
 
-    let cuda_major_str = cuda_major.to_string();
-    let all_cudnn_versions = fetch_available_cudnn_versions().await?;
+/// Fetches a cuDNN release's metadata and checks whether it has a build for `cuda_major`.
+/// Releases whose metadata can't be fetched count as incompatible.
+async fn cudnn_supports_cuda_major(cudnn_version: &str, cuda_major: &str) -> bool {
+    let metadata = match fetch_cudnn_version_metadata(cudnn_version).await {
+        Ok(m) => m,
+        Err(e) => {
+            log::debug!("Skipping cuDNN {}: {}", cudnn_version, e);
+            return false;
+        }
+    };
 
-    for cudnn_version in all_cudnn_versions.iter().rev() {
-        let metadata = match fetch_cudnn_version_metadata(cudnn_version).await {
-            Ok(m) => m,
-            Err(_) => continue,
-        };
+    metadata
+        .get_package("cudnn")
+        .and_then(|pkg| pkg.cuda_variant.as_ref())
+        .is_some_and(|variants| variants.iter().any(|v| v == cuda_major))
+}
 
-        let is_compatible = metadata
-            .get_package("cudnn")
-            .and_then(|pkg| pkg.cuda_variant.as_ref())
-            .is_some_and(|variants| variants.contains(&cuda_major_str));
+/// Finds the newest cuDNN version compatible with a given CUDA major version. The answer
+/// is cached per major, so installing another patch release skips the metadata probing.
+pub async fn find_newest_compatible_cudnn(cuda_version: &str) -> Result<Option<String>> {
+    let cuda_major = cuda_major(cuda_version)?;
+    if let Some(cudnn_version) =
+        cache::load_cached_compatible_cudnn(cuda_major, cache::refresh_requested())?
+    {
+        return Ok(Some(cudnn_version));
+    }
 
-        if is_compatible {
+    for cudnn_version in fetch_available_cudnn_versions().await?.iter().rev() {
+        if cudnn_supports_cuda_major(cudnn_version, cuda_major).await {
+            cache::save_compatible_cudnn(cuda_major, cudnn_version);
             return Ok(Some(cudnn_version.clone()));
         }
     }
@@ -105,6 +173,21 @@ pub async fn find_newest_compatible_cudnn(cuda_version: &str) -> Result<Option<S
     Ok(None)
 }
 
+/// Every cuDNN version with a build for the CUDA major version of `cuda_version`.
+/// Unlike [`find_newest_compatible_cudnn`], this fetches the metadata of every release.
+pub async fn fetch_compatible_cudnn_versions(cuda_version: &str) -> Result<BTreeSet<String>> {
+    let cuda_major = cuda_major(cuda_version)?;
+
+    let mut compatible = BTreeSet::new();
+    for cudnn_version in fetch_available_cudnn_versions().await? {
+        if cudnn_supports_cuda_major(&cudnn_version, cuda_major).await {
+            compatible.insert(cudnn_version);
+        }
+    }
+
+    Ok(compatible)
+}
+
 pub async fn fetch_cudnn_version_metadata(version: &str) -> Result<CudaReleaseMetadata> {
-    fetch_version_metadata(CUDNN_BASE_URL, "cuDNN", version).await
+    fetch_version_metadata(&*cudnn_source(), "cuDNN", version).await
 }
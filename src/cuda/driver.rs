@@ -0,0 +1,42 @@
+use std::process::Command;
+
+use super::CudaVersion;
+
+/// Minimum Linux driver for each CUDA major under minor version compatibility.
+const MINIMUM_DRIVERS: &[(u32, &str)] = &[(11, "450.80.02"), (12, "525.60.13"), (13, "580.65.06")];
+
+/// Minimum NVIDIA driver a toolkit needs at runtime, if known.
+pub fn minimum_driver_version(cuda: &CudaVersion) -> Option<&'static str> {
+    MINIMUM_DRIVERS
+        .iter()
+        .find(|(major, _)| *major == cuda.major())
+        .map(|(_, driver)| *driver)
+}
+
+/// Driver version reported by `nvidia-smi`, or `None` if it's absent or fails.
+pub fn installed_driver_version() -> Option<String> {
+    let output = Command::new("nvidia-smi")
+        .arg("--query-gpu=driver_version")
+        .arg("--format=csv,noheader")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+}
+
+fn parse_driver_version(version: &str) -> Vec<u32> {
+    version
+        .split('.')
+        .map(|part| part.parse().unwrap_or(0))
+        .collect()
+}
+
+/// Whether `installed` is older than `required` (e.g. `535.104.05` < `550.54.14`).
+pub fn is_older_driver(installed: &str, required: &str) -> bool {
+    parse_driver_version(installed) < parse_driver_version(required)
+}
@@ -1,5 +1,8 @@
+use anyhow::{Context, Result, bail};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CudaReleaseMetadata {
@@ -44,10 +47,106 @@ impl CudaReleaseMetadata {
     pub fn get_package(&self, name: &str) -> Option<&PackageInfo> {
         self.packages.get(name)
     }
+
+    /// Sorted names of all packages in the release, excluding `release_*` entries.
+    pub fn package_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .packages
+            .keys()
+            .map(String::as_str)
+            .filter(|name| !name.starts_with("release_"))
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// The parsed `release_date`, or an error when it's missing or malformed.
+    pub fn parsed_release_date(&self) -> Result<ReleaseDate> {
+        self.release_date
+            .as_deref()
+            .context("no release date")?
+            .parse()
+    }
+}
+
+/// A calendar date in the `YYYY-MM-DD` form NVIDIA uses for `release_date`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ReleaseDate {
+    year: u16,
+    month: u8,
+    day: u8,
+}
+
+impl FromStr for ReleaseDate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.trim().splitn(3, '-');
+        let mut next = |name: &str| {
+            parts
+                .next()
+                .filter(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+                .with_context(|| {
+                    format!(
+                        "Invalid date '{}': missing {} (expected YYYY-MM-DD)",
+                        s, name
+                    )
+                })
+        };
+        let (year, month, day) = (next("year")?, next("month")?, next("day")?);
+
+        let date = Self {
+            year: year.parse()?,
+            month: month.parse()?,
+            day: day.parse()?,
+        };
+        if !(1..=12).contains(&date.month) || !(1..=31).contains(&date.day) {
+            bail!("Invalid date '{}': expected YYYY-MM-DD", s);
+        }
+        Ok(date)
+    }
+}
+
+impl fmt::Display for ReleaseDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
 }
 
 impl PackageInfo {
     pub fn get_platform(&self, platform: &str) -> Option<&PlatformInfo> {
         self.platforms.get(platform)
     }
+
+    /// Sorted platform keys (e.g. `linux-x86_64`) this package is published for.
+    pub fn available_platforms(&self) -> Vec<&str> {
+        let mut platforms: Vec<&str> = self.platforms.keys().map(String::as_str).collect();
+        platforms.sort_unstable();
+        platforms
+    }
+}
+
+impl PlatformInfo {
+    /// Archive size in bytes; for per-CUDA variants, the largest variant's size.
+    pub fn size(&self) -> Option<u64> {
+        match self {
+            PlatformInfo::Simple(info) => info.size.parse().ok(),
+            PlatformInfo::Variants(variants) => variants
+                .values()
+                .filter_map(|info| info.size.parse().ok())
+                .max(),
+        }
+    }
+
+    /// Sorted CUDA variant keys (e.g. `cuda12`), empty for single-download platforms.
+    pub fn variants(&self) -> Vec<&str> {
+        match self {
+            PlatformInfo::Simple(_) => Vec::new(),
+            PlatformInfo::Variants(variants) => {
+                let mut keys: Vec<&str> = variants.keys().map(String::as_str).collect();
+                keys.sort_unstable();
+                keys
+            }
+        }
+    }
 }
@@ -1,5 +1,7 @@
 pub mod discover;
+pub mod driver;
 pub mod metadata;
+pub mod source;
 pub mod version;
 
 pub use version::CudaVersion;
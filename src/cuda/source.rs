@@ -0,0 +1,158 @@
+use crate::config;
+
+pub const CUDA_BASE_URL: &str = "https://developer.download.nvidia.com/compute/cuda/redist";
+pub const CUDNN_BASE_URL: &str = "https://developer.download.nvidia.com/compute/cudnn/redist";
+
+/// A redist repository: a directory listing of `redistrib_<version>.json` files and the
+/// archives they reference by relative path.
+pub trait Source: Send + Sync {
+    /// Base URL every other URL is built from, without a trailing slash.
+    fn base_url(&self) -> &str;
+
+    /// The directory listing the available versions are scraped from.
+    fn index_url(&self) -> String {
+        format!("{}/", self.base_url())
+    }
+
+    fn metadata_url(&self, version: &str) -> String {
+        self.package_url(&format!("redistrib_{}.json", version))
+    }
+
+    fn package_url(&self, relative_path: &str) -> String {
+        format!("{}/{}", self.base_url(), relative_path)
+    }
+}
+
+/// NVIDIA's own redist hosts.
+#[derive(Debug, Clone, Copy)]
+pub enum NvidiaSource {
+    Cuda,
+    Cudnn,
+}
+
+impl Source for NvidiaSource {
+    fn base_url(&self) -> &str {
+        match self {
+            NvidiaSource::Cuda => CUDA_BASE_URL,
+            NvidiaSource::Cudnn => CUDNN_BASE_URL,
+        }
+    }
+}
+
+/// A mirror laid out like NVIDIA's redist directory under another base URL.
+#[derive(Debug, Clone)]
+pub struct MirrorSource {
+    base_url: String,
+}
+
+impl MirrorSource {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        let base_url = base_url.into().trim_end_matches('/').to_string();
+        Self { base_url }
+    }
+}
+
+impl Source for MirrorSource {
+    fn base_url(&self) -> &str {
+        &self.base_url
+    }
+}
+
+fn configured(mirror: Option<&str>, default: NvidiaSource) -> Box<dyn Source> {
+    match mirror {
+        Some(base_url) => Box::new(MirrorSource::new(base_url)),
+        None => Box::new(default),
+    }
+}
+
+/// Source for CUDA packages, honoring the `mirror.cuda` config override.
+pub fn cuda_source() -> Box<dyn Source> {
+    configured(config::get().mirror.cuda.as_deref(), NvidiaSource::Cuda)
+}
+
+/// Source for cuDNN packages, honoring the `mirror.cudnn` config override.
+pub fn cudnn_source() -> Box<dyn Source> {
+    configured(config::get().mirror.cudnn.as_deref(), NvidiaSource::Cudnn)
+}
+
+/// Sources tried after the primary one when a CUDA download fails (`mirror.cuda_fallbacks`).
+pub fn cuda_fallback_sources() -> Vec<MirrorSource> {
+    config::get()
+        .mirror
+        .cuda_fallbacks
+        .iter()
+        .map(MirrorSource::new)
+        .collect()
+}
+
+/// Sources tried after the primary one when a cuDNN download fails (`mirror.cudnn_fallbacks`).
+pub fn cudnn_fallback_sources() -> Vec<MirrorSource> {
+    config::get()
+        .mirror
+        .cudnn_fallbacks
+        .iter()
+        .map(MirrorSource::new)
+        .collect()
+}
+
+/// A source whose URLs no real host produces, so tests can tell they were built through it.
+#[cfg(test)]
+pub(crate) struct FakeSource;
+
+#[cfg(test)]
+impl Source for FakeSource {
+    fn base_url(&self) -> &str {
+        "fake://redist"
+    }
+
+    fn package_url(&self, relative_path: &str) -> String {
+        format!("fake://redist/package?path={}", relative_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nvidia_sources_use_the_redist_hosts() {
+        assert_eq!(
+            NvidiaSource::Cuda.metadata_url("12.4.1"),
+            format!("{}/redistrib_12.4.1.json", CUDA_BASE_URL)
+        );
+        assert_eq!(
+            NvidiaSource::Cudnn.index_url(),
+            format!("{}/", CUDNN_BASE_URL)
+        );
+    }
+
+    #[test]
+    fn urls_are_built_through_the_source() {
+        assert_eq!(FakeSource.index_url(), "fake://redist/");
+        assert_eq!(
+            FakeSource.metadata_url("12.4.1"),
+            "fake://redist/package?path=redistrib_12.4.1.json"
+        );
+    }
+
+    #[test]
+    fn mirror_joins_urls_with_or_without_a_trailing_slash() {
+        for base_url in [
+            "https://mirror.example/cuda",
+            "https://mirror.example/cuda/",
+            "https://mirror.example/cuda//",
+        ] {
+            let mirror = MirrorSource::new(base_url);
+            assert_eq!(mirror.base_url(), "https://mirror.example/cuda");
+            assert_eq!(mirror.index_url(), "https://mirror.example/cuda/");
+            assert_eq!(
+                mirror.metadata_url("12.4.1"),
+                "https://mirror.example/cuda/redistrib_12.4.1.json"
+            );
+            assert_eq!(
+                mirror.package_url("cuda_nvcc/linux-x86_64/nvcc.tar.xz"),
+                "https://mirror.example/cuda/cuda_nvcc/linux-x86_64/nvcc.tar.xz"
+            );
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use std::path::PathBuf;
+
+use reqwest::StatusCode;
+use thiserror::Error;
+
+/// Failure modes of the install and fetch paths that callers may want to match on.
+#[derive(Debug, Error)]
+pub enum CudupError {
+    #[error("CUDA version {0} is not available")]
+    VersionNotAvailable(String),
+
+    #[error("CUDA {version} is already installed at {}", path.display())]
+    AlreadyInstalled { version: String, path: PathBuf },
+
+    #[error("Checksum mismatch for {}: expected {expected}, got {actual}", path.display())]
+    ChecksumMismatch {
+        path: PathBuf,
+        expected: String,
+        actual: String,
+    },
+
+    #[error(
+        "Unsupported checksum for {}: '{expected}' is not a SHA256 or SHA512 hex digest",
+        path.display()
+    )]
+    UnsupportedChecksum { path: PathBuf, expected: String },
+
+    #[error(
+        "Unsupported platform: {os}-{arch}. \
+         cudup supports linux-x86_64 and linux-sbsa (ARM64 server)."
+    )]
+    PlatformUnsupported { os: String, arch: String },
+
+    #[error("{context}")]
+    Network {
+        context: String,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    #[error("{context}: HTTP {status}")]
+    HttpStatus { context: String, status: StatusCode },
+
+    #[error("{context}: no data received for {seconds}s")]
+    Timeout { context: String, seconds: u64 },
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
@@ -1,10 +1,26 @@
-use anyhow::{Context, Result, bail};
 use futures::StreamExt;
 use indicatif::ProgressBar;
-use reqwest::Client;
-use std::path::Path;
+use reqwest::{Client, StatusCode};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::cuda::source::{Source, cuda_fallback_sources, cudnn_fallback_sources};
+use crate::error::CudupError;
+
+use super::utils::{format_rate, format_size};
+
+/// Bytes received by every download in this process, for the throughput summary.
+static BYTES_DOWNLOADED: AtomicU64 = AtomicU64::new(0);
+
+pub fn bytes_downloaded() -> u64 {
+    BYTES_DOWNLOADED.load(Ordering::Relaxed)
+}
 
 #[derive(Debug, Clone)]
 pub struct DownloadTask {
@@ -23,36 +39,456 @@ impl DownloadTask {
             .filter(|s| !s.is_empty())
             .unwrap_or("archive.tar.xz")
     }
+
+    /// The task's URL followed by the same archive on each configured fallback mirror.
+    pub fn candidate_urls(&self) -> Vec<String> {
+        let fallbacks = if self.package_name == "cudnn" {
+            cudnn_fallback_sources()
+        } else {
+            cuda_fallback_sources()
+        };
+
+        let mut urls = vec![self.url.clone()];
+        for source in fallbacks {
+            let url = source.package_url(&self.relative_path);
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+        urls
+    }
+}
+
+/// Reads `Content-Length` from a `HEAD` request, or `None` if the server doesn't report one.
+async fn head_content_length(client: &Client, url: &str) -> Option<u64> {
+    let response = client
+        .head(url)
+        .timeout(super::http::METADATA_TIMEOUT)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .inspect_err(|e| log::debug!("HEAD {} failed: {}", url, e))
+        .ok()?;
+
+    response
+        .headers()
+        .get(reqwest::header::CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+/// Fills in sizes missing from the metadata by asking the server, concurrently.
+pub async fn fill_unknown_sizes(client: &Client, tasks: &mut [DownloadTask]) {
+    let unknown: Vec<&mut DownloadTask> = tasks.iter_mut().filter(|t| t.size.is_none()).collect();
+    if unknown.is_empty() {
+        return;
+    }
+
+    let sizes = futures::future::join_all(
+        unknown
+            .iter()
+            .map(|task| head_content_length(client, &task.url)),
+    )
+    .await;
+
+    for (task, size) in unknown.into_iter().zip(sizes) {
+        task.size = size;
+    }
 }
 
+/// Caps how many downloads run against the same host at once, however many run overall.
+pub struct HostLimiter {
+    per_host: usize,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl HostLimiter {
+    pub fn new(per_host: usize) -> Self {
+        Self {
+            per_host: per_host.max(1),
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Waits for a connection slot on `url`'s host. The slot is freed when the permit drops.
+    pub async fn acquire(&self, url: &str) -> OwnedSemaphorePermit {
+        let host = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_string))
+            .unwrap_or_default();
+        let semaphore = self
+            .hosts
+            .lock()
+            .expect("host limiter lock poisoned")
+            .entry(host)
+            .or_insert_with(|| Arc::new(Semaphore::new(self.per_host)))
+            .clone();
+
+        semaphore
+            .acquire_owned()
+            .await
+            .expect("host semaphore is never closed")
+    }
+}
+
+/// Longest total time a download waits out `429 Too Many Requests` before giving up.
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(5 * 60);
+
+/// First wait after a 429 without `Retry-After`; doubled on each further 429.
+const RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(2);
+
+/// Where an in-progress download is written until it completes.
+pub fn part_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    dest.with_file_name(name)
+}
+
+/// Parses a `Retry-After` header given in seconds. HTTP dates aren't supported and
+/// fall back to the regular backoff.
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// Downloads `url` to `dest` through a `.part` file. Bytes already in the `.part` file
+/// from an interrupted attempt are resumed with a range request, and `429` responses
+/// are retried after the server's `Retry-After` delay (or an exponential backoff).
+///
+/// A connection slot on the host is held from `limiter` for the whole transfer, except
+/// while waiting out a 429, so other downloads can use the host in the meantime.
 pub async fn download_file(
     client: &Client,
     url: &str,
     dest: &Path,
     progress: Option<&ProgressBar>,
-) -> Result<()> {
-    let response = client.get(url).send().await.context("request failed")?;
+    idle_timeout: Duration,
+    limiter: &HostLimiter,
+) -> Result<(), CudupError> {
+    let timeout_error = |context: &str| CudupError::Timeout {
+        context: context.to_string(),
+        seconds: idle_timeout.as_secs(),
+    };
+    let network_error = |source| CudupError::Network {
+        context: format!("Download of {} failed", url),
+        source,
+    };
+
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    let part = part_path(dest);
+
+    let mut waited = Duration::ZERO;
+    let mut backoff = RATE_LIMIT_BACKOFF;
+    let (response, offset, _permit) = loop {
+        let permit = limiter.acquire(url).await;
+        let offset = fs::metadata(&part).await.map(|m| m.len()).unwrap_or(0);
+        let mut request = client.get(url);
+        if offset > 0 {
+            request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+        }
+
+        let response = tokio::time::timeout(idle_timeout, request.send())
+            .await
+            .map_err(|_| timeout_error("No response from server"))?
+            .map_err(network_error)?;
+
+        match response.status() {
+            StatusCode::TOO_MANY_REQUESTS => {
+                let delay = retry_after(&response).unwrap_or(backoff);
+                backoff *= 2;
+                if waited + delay > MAX_RATE_LIMIT_WAIT {
+                    return Err(CudupError::HttpStatus {
+                        context: format!(
+                            "Download still rate limited after waiting {}s",
+                            waited.as_secs()
+                        ),
+                        status: response.status(),
+                    });
+                }
+                log::warn!(
+                    "Rate limited by {}, retrying in {}s",
+                    response.url().host_str().unwrap_or(url),
+                    delay.as_secs()
+                );
+                drop(permit);
+                tokio::time::sleep(delay).await;
+                waited += delay;
+            }
+            // The partial file is already complete or longer than the archive; start over.
+            StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => {
+                fs::remove_file(&part).await?;
+            }
+            _ => break (response, offset, permit),
+        }
+    };
 
     if !response.status().is_success() {
-        bail!("Download failed: HTTP {}", response.status());
+        return Err(CudupError::HttpStatus {
+            context: "Download failed".to_string(),
+            status: response.status(),
+        });
     }
 
-    if let Some(parent) = dest.parent() {
-        fs::create_dir_all(parent).await?;
+    // Servers that ignore the range request send the whole archive again.
+    let resumed = offset > 0 && response.status() == StatusCode::PARTIAL_CONTENT;
+    let mut file = if resumed {
+        log::debug!("Resuming {} at byte {}", url, offset);
+        fs::OpenOptions::new().append(true).open(&part).await?
+    } else {
+        fs::File::create(&part).await?
+    };
+    if let Some(pb) = progress {
+        pb.set_position(if resumed { offset } else { 0 });
     }
 
-    let mut file = fs::File::create(dest).await?;
     let mut stream = response.bytes_stream();
+    let started = Instant::now();
+    let mut received = 0;
 
-    while let Some(chunk) = stream.next().await {
-        let chunk = chunk?;
+    // The stream is guarded by an idle timeout rather than a total one, so a slow but
+    // steady multi-GB download is fine while a stalled connection still fails.
+    loop {
+        let next = tokio::time::timeout(idle_timeout, stream.next())
+            .await
+            .map_err(|_| timeout_error("Download stalled"))?;
+        let Some(chunk) = next else {
+            break;
+        };
+        let chunk = chunk.map_err(network_error)?;
         file.write_all(&chunk).await?;
+        received += chunk.len() as u64;
+        BYTES_DOWNLOADED.fetch_add(chunk.len() as u64, Ordering::Relaxed);
         if let Some(pb) = progress {
             pb.inc(chunk.len() as u64);
         }
     }
 
     file.flush().await?;
+    drop(file);
+    fs::rename(&part, dest).await?;
+
+    let elapsed = started.elapsed();
+    log::debug!(
+        "Downloaded {} in {:.1}s ({}, {})",
+        url,
+        elapsed.as_secs_f64(),
+        format_size(received),
+        format_rate(received, elapsed)
+    );
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    /// Serves one response that announces `length` bytes but stops after sending `sent`,
+    /// then keeps the connection open without sending anything else.
+    async fn stalling_server(length: usize, sent: &'static [u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let _ = socket.read(&mut request).await;
+            let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {length}\r\n\r\n");
+            socket.write_all(head.as_bytes()).await.unwrap();
+            socket.write_all(sent).await.unwrap();
+            tokio::time::sleep(Duration::from_secs(30)).await;
+        });
+        format!("http://{address}/archive.tar.xz")
+    }
+
+    #[tokio::test]
+    async fn download_fails_when_the_body_stalls() {
+        let url = stalling_server(1024, b"the first few bytes").await;
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("archive.tar.xz");
+
+        let started = Instant::now();
+        let error = download_file(
+            &Client::new(),
+            &url,
+            &dest,
+            None,
+            Duration::from_millis(500),
+            &HostLimiter::new(1),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(
+            matches!(&error, CudupError::Timeout { context, .. } if context == "Download stalled"),
+            "expected a stall timeout, got: {error}"
+        );
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(!dest.exists(), "a stalled download must not be completed");
+        assert_eq!(
+            std::fs::read(part_path(&dest)).unwrap(),
+            b"the first few bytes",
+            "received bytes are kept for a resume"
+        );
+    }
+
+    #[tokio::test]
+    async fn host_limiter_caps_connections_per_host() {
+        let limiter = HostLimiter::new(2);
+        let slot = Duration::from_millis(100);
+        let first = limiter.acquire("https://example.com/a.tar.xz").await;
+        let _second = limiter.acquire("https://example.com/b.tar.xz").await;
+
+        let third = tokio::time::timeout(slot, limiter.acquire("https://example.com/c.tar.xz"));
+        assert!(
+            third.await.is_err(),
+            "a third connection to the host must wait"
+        );
+
+        let other_host = tokio::time::timeout(slot, limiter.acquire("https://mirror.test/a"));
+        assert!(other_host.await.is_ok(), "other hosts have their own slots");
+
+        drop(first);
+        let third = tokio::time::timeout(slot, limiter.acquire("https://example.com/c.tar.xz"));
+        assert!(third.await.is_ok(), "a dropped permit frees its slot");
+    }
+
+    #[tokio::test]
+    async fn rate_limited_download_frees_its_slot_while_waiting() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/archive.tar.xz"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/archive.tar.xz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes("archive contents"))
+            .mount(&server)
+            .await;
+
+        let url = format!("{}/archive.tar.xz", server.uri());
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("archive.tar.xz");
+        let limiter = HostLimiter::new(1);
+        let client = Client::new();
+
+        let download = download_file(
+            &client,
+            &url,
+            &dest,
+            None,
+            Duration::from_secs(10),
+            &limiter,
+        );
+        let during_wait = async {
+            while server
+                .received_requests()
+                .await
+                .unwrap_or_default()
+                .is_empty()
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            tokio::time::timeout(Duration::from_millis(500), limiter.acquire(&url))
+                .await
+                .is_ok()
+        };
+        let (downloaded, slot_was_free) = tokio::join!(download, during_wait);
+
+        downloaded.unwrap();
+        assert!(
+            slot_was_free,
+            "the host slot must be free during the Retry-After wait"
+        );
+        assert_eq!(std::fs::read(&dest).unwrap(), b"archive contents");
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn rate_limited_download_resumes_its_partial_file() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(header("Range", "bytes=8-"))
+            .respond_with(ResponseTemplate::new(206).set_body_bytes("contents"))
+            .mount(&server)
+            .await;
+
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("archive.tar.xz");
+        std::fs::write(part_path(&dest), "archive ").unwrap();
+
+        download_file(
+            &Client::new(),
+            &format!("{}/archive.tar.xz", server.uri()),
+            &dest,
+            None,
+            Duration::from_secs(10),
+            &HostLimiter::new(1),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(std::fs::read(&dest).unwrap(), b"archive contents");
+        assert!(!part_path(&dest).exists());
+    }
+
+    #[tokio::test]
+    async fn http_errors_and_refused_connections_are_typed_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("archive.tar.xz");
+        let download = |url: String| {
+            let dest = dest.clone();
+            async move {
+                download_file(
+                    &Client::new(),
+                    &url,
+                    &dest,
+                    None,
+                    Duration::from_secs(10),
+                    &HostLimiter::new(1),
+                )
+                .await
+                .unwrap_err()
+            }
+        };
+
+        let error = download(format!("{}/archive.tar.xz", server.uri())).await;
+        assert!(
+            matches!(error, CudupError::HttpStatus { status, .. } if status == StatusCode::NOT_FOUND),
+            "{error}"
+        );
+
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = closed.local_addr().unwrap();
+        drop(closed);
+        let error = download(format!("http://{address}/archive.tar.xz")).await;
+        assert!(matches!(error, CudupError::Network { .. }), "{error}");
+    }
+}
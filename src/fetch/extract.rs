@@ -1,21 +1,135 @@
 use anyhow::{Context, Result, bail};
-use std::path::Path;
+use indicatif::ProgressBar;
+use log::{debug, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use tokio::fs;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt};
 use tokio::process::Command;
 
-pub async fn extract_tarball(archive_path: &Path, dest_dir: &Path) -> Result<()> {
-    fs::create_dir_all(dest_dir).await?;
+use super::tar;
 
+/// Lists the entry names in an archive.
+async fn list_entries(archive_path: &Path) -> Result<Vec<String>> {
     let output = Command::new("tar")
-        .arg("xf")
+        .arg("-tf")
         .arg(archive_path)
-        .arg("-C")
-        .arg(dest_dir)
-        .arg("--strip-components=1")
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .context("Failed to run tar command")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("Failed to list {}: {}", archive_path.display(), stderr);
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Leading bytes of the compressed tarballs NVIDIA publishes: xz and gzip.
+const ARCHIVE_MAGICS: [&[u8]; 2] = [&[0xFD, b'7', b'z', b'X', b'Z'], &[0x1F, 0x8B]];
+
+/// Fails with a readable error when `archive_path` isn't an xz or gzip file, which usually
+/// means the server answered with an HTML error page instead of the archive.
+async fn check_archive_magic(archive_path: &Path) -> Result<()> {
+    let mut header = [0u8; 16];
+    let mut file = fs::File::open(archive_path)
+        .await
+        .with_context(|| format!("Failed to open {}", archive_path.display()))?;
+    let mut read = 0;
+    while read < header.len() {
+        match file.read(&mut header[read..]).await? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    let header = &header[..read];
+
+    if ARCHIVE_MAGICS.iter().any(|magic| header.starts_with(magic)) {
+        return Ok(());
+    }
+    bail!(
+        "Downloaded file {} is not a valid archive (server may have returned an error page); \
+         it starts with {:?}",
+        archive_path.display(),
+        String::from_utf8_lossy(header)
+    )
+}
+
+/// Returns the directory every entry lives under, if there is one.
+fn common_top_level_dir(entries: &[String]) -> Option<&str> {
+    let mut top_level = None;
+    let mut has_nested = false;
+    for entry in entries {
+        let (first, rest) = entry.split_once('/').unwrap_or((entry, ""));
+        match top_level {
+            None => top_level = Some(first),
+            Some(top) if top != first => return None,
+            Some(_) => {}
+        }
+        has_nested |= !rest.is_empty();
+    }
+
+    // A single plain file at the root isn't a directory to strip.
+    top_level.filter(|_| has_nested)
+}
+
+/// Extracts an archive into `dest_dir`, dropping its top-level directory when all
+/// entries share one, and keeping the layout as-is for flat archives.
+/// `progress` is sized to the number of entries and advanced as each one is extracted.
+pub async fn extract_tarball(
+    archive_path: &Path,
+    dest_dir: &Path,
+    progress: &ProgressBar,
+) -> Result<()> {
+    check_archive_magic(archive_path).await?;
+    let tar = tar::capabilities_for(archive_path)?;
+    fs::create_dir_all(dest_dir).await?;
+
+    let entries = list_entries(archive_path).await?;
+    let strip_top_level = common_top_level_dir(&entries).is_some();
+    if !strip_top_level {
+        warn!(
+            "{} has no single top-level directory; extracting it without stripping one",
+            archive_path.display()
+        );
+    }
+    progress.set_length(entries.len() as u64);
+
+    // Options go before the archive, since not every tar accepts them after operands.
+    let mut command = Command::new("tar");
+    command.arg(if tar.verbose_on_stdout() { "-xv" } else { "-x" });
+    command.arg("-C").arg(dest_dir);
+    if strip_top_level {
+        tar.ensure_strip_components()?;
+        command.arg("--strip-components=1");
+    }
+    command.arg("-f").arg(archive_path);
+
+    // Verbose mode prints one line per entry as it's extracted, which drives the progress bar.
+    let mut child = command
+        .stdin(Stdio::null())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
-        .output()
+        .spawn()
+        .context("Failed to run tar command")?;
+
+    let stdout = child.stdout.take().context("tar stdout not captured")?;
+    let mut lines = tokio::io::BufReader::new(stdout).lines();
+    while lines.next_line().await?.is_some() {
+        progress.inc(1);
+    }
+
+    let output = child
+        .wait_with_output()
         .await
         .context("Failed to run tar command")?;
 
@@ -23,6 +137,380 @@ pub async fn extract_tarball(archive_path: &Path, dest_dir: &Path) -> Result<()>
         let stderr = String::from_utf8_lossy(&output.stderr);
         bail!("Failed to extract {}: {}", archive_path.display(), stderr);
     }
+    progress.set_position(entries.len() as u64);
 
     Ok(())
 }
+
+/// Tracks which package wrote each extracted file so overlapping archives can be detected.
+#[derive(Debug, Default)]
+pub struct ExtractTracker {
+    owners: HashMap<PathBuf, String>,
+    /// Files of packages a repair leaves alone, which re-extracted packages must not replace.
+    preserved: HashMap<PathBuf, String>,
+    strict: bool,
+}
+
+impl ExtractTracker {
+    pub fn new(strict: bool) -> Self {
+        Self {
+            owners: HashMap::new(),
+            preserved: HashMap::new(),
+            strict,
+        }
+    }
+
+    /// A tracker for re-extracting some packages into an existing install, keeping the
+    /// files recorded for the `intact` packages as they are.
+    pub fn preserving<'a>(
+        strict: bool,
+        intact: impl IntoIterator<Item = (&'a String, &'a Vec<String>)>,
+    ) -> Self {
+        let preserved = intact
+            .into_iter()
+            .flat_map(|(package, files)| {
+                files
+                    .iter()
+                    .map(move |file| (PathBuf::from(file), package.clone()))
+            })
+            .collect();
+        Self {
+            preserved,
+            ..Self::new(strict)
+        }
+    }
+
+    /// The intact package whose copy of `path` has to be kept, if it isn't `package`.
+    fn preserved_owner(&self, path: &Path, package: &str) -> Option<&str> {
+        self.preserved
+            .get(path)
+            .map(String::as_str)
+            .filter(|owner| *owner != package)
+    }
+
+    /// The package that extracted `path`, if any. Files that were already installed
+    /// before this run have no owner.
+    fn owner(&self, path: &Path) -> Option<&str> {
+        self.owners.get(path).map(String::as_str)
+    }
+
+    fn record(&mut self, path: PathBuf, package: &str) {
+        self.owners.insert(path, package.to_string());
+    }
+
+    /// Paths each package extracted, relative to the install directory.
+    pub fn files_by_package(&self) -> BTreeMap<String, Vec<String>> {
+        let mut files: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (path, package) in &self.owners {
+            files
+                .entry(package.clone())
+                .or_default()
+                .push(path.to_string_lossy().into_owned());
+        }
+        files
+    }
+
+    /// Warns (or fails in strict mode) when `package` is about to replace a different
+    /// file that an earlier package extracted.
+    fn check_overwrite(&self, path: &Path, package: &str) -> Result<()> {
+        let Some(owner) = self.owner(path).filter(|owner| *owner != package) else {
+            return Ok(());
+        };
+
+        if self.strict {
+            bail!(
+                "{} from {} conflicts with a different file from {}",
+                path.display(),
+                package,
+                owner
+            );
+        }
+
+        warn!(
+            "{} from {} overwrites a different file from {}",
+            path.display(),
+            package,
+            owner
+        );
+        Ok(())
+    }
+}
+
+fn same_file_contents(a: &Path, b: &Path) -> Result<bool> {
+    let (meta_a, meta_b) = (a.symlink_metadata()?, b.symlink_metadata()?);
+
+    if meta_a.is_symlink() || meta_b.is_symlink() {
+        return Ok(meta_a.is_symlink()
+            && meta_b.is_symlink()
+            && std::fs::read_link(a)? == std::fs::read_link(b)?);
+    }
+    if !meta_a.is_file() || !meta_b.is_file() || meta_a.len() != meta_b.len() {
+        return Ok(false);
+    }
+
+    let mut reader_a = BufReader::new(File::open(a)?);
+    let mut reader_b = BufReader::new(File::open(b)?);
+    let mut buf_a = vec![0u8; 64 * 1024];
+    let mut buf_b = vec![0u8; 64 * 1024];
+
+    loop {
+        let read = reader_a.read(&mut buf_a)?;
+        if read == 0 {
+            return Ok(true);
+        }
+        reader_b.read_exact(&mut buf_b[..read])?;
+        if buf_a[..read] != buf_b[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+fn merge_tree(
+    src: &Path,
+    dest: &Path,
+    relative: &Path,
+    package: &str,
+    tracker: &mut ExtractTracker,
+) -> Result<()> {
+    std::fs::create_dir_all(dest)?;
+
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let src_path = entry.path();
+        let dest_path = dest.join(entry.file_name());
+        let relative_path = relative.join(entry.file_name());
+        let existing = dest_path.symlink_metadata().ok();
+
+        if entry.file_type()?.is_dir() {
+            if existing.is_some_and(|m| !m.is_dir()) {
+                std::fs::remove_file(&dest_path)?;
+            }
+            merge_tree(&src_path, &dest_path, &relative_path, package, tracker)?;
+            continue;
+        }
+
+        if let Some(existing) = existing {
+            if same_file_contents(&src_path, &dest_path)? {
+                debug!(
+                    "{} from {} is identical to the copy from {}, skipping",
+                    relative_path.display(),
+                    package,
+                    tracker
+                        .owner(&relative_path)
+                        .unwrap_or("the existing install")
+                );
+                continue;
+            }
+            if let Some(owner) = tracker.preserved_owner(&relative_path, package) {
+                debug!(
+                    "Keeping {} from {} instead of the copy from {}",
+                    relative_path.display(),
+                    owner,
+                    package
+                );
+                continue;
+            }
+
+            tracker.check_overwrite(&relative_path, package)?;
+            if existing.is_dir() {
+                std::fs::remove_dir_all(&dest_path)?;
+            }
+        }
+
+        std::fs::rename(&src_path, &dest_path)?;
+        tracker.record(relative_path, package);
+    }
+
+    Ok(())
+}
+
+/// Extracts a package into a staging directory, then merges it into `install_dir`,
+/// de-duplicating identical files and flagging conflicting ones.
+pub async fn extract_package(
+    archive_path: &Path,
+    install_dir: &Path,
+    package: &str,
+    tracker: &mut ExtractTracker,
+    progress: &ProgressBar,
+) -> Result<()> {
+    let staging_dir = install_dir.join(format!(".extract-{}", package));
+    if staging_dir.exists() {
+        fs::remove_dir_all(&staging_dir).await?;
+    }
+
+    let result = async {
+        extract_tarball(archive_path, &staging_dir, progress).await?;
+        merge_tree(&staging_dir, install_dir, Path::new(""), package, tracker)
+    }
+    .await;
+
+    fs::remove_dir_all(&staging_dir).await.ok();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn entries(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    /// Builds a `.tar.xz` of `files`, adding each of `roots` (relative to the staging
+    /// dir) as a top-level entry.
+    fn archive(dir: &Path, roots: &[&str], files: &[(&str, &str)]) -> PathBuf {
+        let staging = dir.join("staging");
+        for (relative, contents) in files {
+            let file = staging.join(relative);
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            std::fs::write(file, contents).unwrap();
+        }
+
+        let archive = dir.join("archive.tar.xz");
+        let status = std::process::Command::new("tar")
+            .arg("cJf")
+            .arg(&archive)
+            .arg("-C")
+            .arg(&staging)
+            .args(roots)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        archive
+    }
+
+    #[test]
+    fn common_top_level_dir_finds_the_wrapping_directory() {
+        let wrapped = entries(&[
+            "cuda_cudart-linux-x86_64-12.4.127-archive/",
+            "cuda_cudart-linux-x86_64-12.4.127-archive/lib/",
+            "cuda_cudart-linux-x86_64-12.4.127-archive/lib/libcudart.so",
+        ]);
+
+        assert_eq!(
+            common_top_level_dir(&wrapped),
+            Some("cuda_cudart-linux-x86_64-12.4.127-archive")
+        );
+    }
+
+    #[test]
+    fn common_top_level_dir_is_none_for_flat_archives() {
+        assert_eq!(
+            common_top_level_dir(&entries(&["lib/libcudart.so", "bin/nvcc"])),
+            None
+        );
+        assert_eq!(common_top_level_dir(&entries(&["LICENSE"])), None);
+        assert_eq!(common_top_level_dir(&entries(&[])), None);
+    }
+
+    #[tokio::test]
+    async fn flat_archive_is_extracted_without_stripping() {
+        let dir = TempDir::new().unwrap();
+        let archive = archive(
+            dir.path(),
+            &["lib", "LICENSE"],
+            &[("lib/libcudart.so", "cudart"), ("LICENSE", "license")],
+        );
+        let dest = dir.path().join("dest");
+
+        extract_tarball(&archive, &dest, &ProgressBar::hidden())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(dest.join("lib/libcudart.so")).unwrap(),
+            "cudart"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dest.join("LICENSE")).unwrap(),
+            "license"
+        );
+    }
+
+    #[tokio::test]
+    async fn wrapped_archive_drops_its_top_level_directory() {
+        let dir = TempDir::new().unwrap();
+        let archive = archive(
+            dir.path(),
+            &["cudart-archive"],
+            &[("cudart-archive/lib/libcudart.so", "cudart")],
+        );
+        let dest = dir.path().join("dest");
+
+        extract_tarball(&archive, &dest, &ProgressBar::hidden())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(dest.join("lib/libcudart.so")).unwrap(),
+            "cudart"
+        );
+        assert!(!dest.join("cudart-archive").exists());
+    }
+
+    /// Extracts a cudart and an nvcc archive that share an identical `LICENSE` and a
+    /// differing `version.json`.
+    async fn extract_overlapping(dir: &Path, tracker: &mut ExtractTracker) -> Result<()> {
+        let cudart = archive(
+            &dir.join("cudart"),
+            &["cudart"],
+            &[
+                ("cudart/lib/libcudart.so", "cudart"),
+                ("cudart/LICENSE", "license"),
+                ("cudart/version.json", "cudart version"),
+            ],
+        );
+        let nvcc = archive(
+            &dir.join("nvcc"),
+            &["nvcc"],
+            &[
+                ("nvcc/bin/nvcc", "nvcc"),
+                ("nvcc/LICENSE", "license"),
+                ("nvcc/version.json", "nvcc version"),
+            ],
+        );
+        let install_dir = dir.join("install");
+        let progress = ProgressBar::hidden();
+
+        extract_package(&cudart, &install_dir, "cuda_cudart", tracker, &progress).await?;
+        extract_package(&nvcc, &install_dir, "cuda_nvcc", tracker, &progress).await
+    }
+
+    #[tokio::test]
+    async fn overlapping_packages_share_identical_files_and_overwrite_others() {
+        let dir = TempDir::new().unwrap();
+        let mut tracker = ExtractTracker::new(false);
+
+        extract_overlapping(dir.path(), &mut tracker).await.unwrap();
+
+        let install_dir = dir.path().join("install");
+        assert_eq!(
+            std::fs::read_to_string(install_dir.join("version.json")).unwrap(),
+            "nvcc version"
+        );
+        assert!(install_dir.join("lib/libcudart.so").exists());
+        assert!(install_dir.join("bin/nvcc").exists());
+        assert_eq!(tracker.owner(Path::new("LICENSE")), Some("cuda_cudart"));
+        assert_eq!(tracker.owner(Path::new("version.json")), Some("cuda_nvcc"));
+    }
+
+    #[tokio::test]
+    async fn strict_extract_fails_on_conflicting_files() {
+        let dir = TempDir::new().unwrap();
+        let mut tracker = ExtractTracker::new(true);
+
+        let error = extract_overlapping(dir.path(), &mut tracker)
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "version.json from cuda_nvcc conflicts with a different file from cuda_cudart"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("install/version.json")).unwrap(),
+            "cudart version"
+        );
+    }
+}
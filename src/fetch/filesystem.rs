@@ -0,0 +1,45 @@
+use std::path::Path;
+
+/// Free inodes below which extracting a toolkit, tens of thousands of files, may fail.
+const MIN_FREE_INODES: u64 = 200_000;
+
+const OVERLAYFS_SUPER_MAGIC: u64 = 0x794c_7630;
+const TMPFS_MAGIC: u64 = 0x0102_1994;
+
+/// Problems with the filesystem holding `path` that make a large install fail or crawl:
+/// overlayfs and tmpfs mounts, and too few free inodes. `path` doesn't need to exist yet;
+/// its nearest existing ancestor is checked.
+pub fn filesystem_warnings(path: &Path) -> Vec<String> {
+    let Some(existing) = path.ancestors().find(|p| p.exists()) else {
+        return Vec::new();
+    };
+
+    let mut warnings = Vec::new();
+    if let Ok(stat) = rustix::fs::statfs(existing) {
+        match stat.f_type as u64 {
+            OVERLAYFS_SUPER_MAGIC => warnings.push(format!(
+                "{} is on overlayfs, where extracting many small files is slow and fills the container layer",
+                existing.display()
+            )),
+            TMPFS_MAGIC => warnings.push(format!(
+                "{} is on tmpfs, so the install is held in memory and lost on reboot",
+                existing.display()
+            )),
+            _ => {}
+        }
+    }
+
+    // Filesystems that allocate inodes dynamically (e.g. btrfs) report no total.
+    if let Ok(stat) = rustix::fs::statvfs(existing)
+        && stat.f_files > 0
+        && stat.f_favail < MIN_FREE_INODES
+    {
+        warnings.push(format!(
+            "only {} free inodes left on the filesystem holding {}",
+            stat.f_favail,
+            existing.display()
+        ));
+    }
+
+    warnings
+}
@@ -0,0 +1,42 @@
+use log::warn;
+use reqwest::{Client, ClientBuilder, Proxy};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use crate::config;
+
+/// Sent on every request so CDNs and proxies can identify the tool.
+pub const USER_AGENT: &str = concat!(
+    "cudup/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/zocrit/cudup)"
+);
+
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Timeout for small metadata requests; package downloads rely on idle detection instead.
+pub const METADATA_TIMEOUT: Duration = Duration::from_secs(30);
+
+static HTTP_CLIENT: LazyLock<Client> =
+    LazyLock::new(|| builder().build().expect("Failed to create HTTP client"));
+
+/// Client builder with cudup's user-agent, connect timeout, and proxy settings applied.
+pub fn builder() -> ClientBuilder {
+    let mut builder = Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(CONNECT_TIMEOUT);
+
+    if let Some(proxy) = &config::get().network.proxy {
+        match Proxy::all(proxy) {
+            Ok(proxy) => builder = builder.proxy(proxy),
+            Err(e) => warn!("Ignoring invalid network.proxy '{}': {}", proxy, e),
+        }
+    }
+
+    builder
+}
+
+/// Shared async client used for all metadata discovery and package downloads.
+pub fn client() -> &'static Client {
+    &HTTP_CLIENT
+}
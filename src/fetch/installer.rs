@@ -1,32 +1,135 @@
-use anyhow::{Result, bail};
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use log::{info, warn};
+use anyhow::{Context, Result, anyhow, bail};
+use futures::{StreamExt, stream};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use log::{debug, info, warn};
 use reqwest::Client;
-use std::path::Path;
-use std::sync::LazyLock;
-use std::time::Duration;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use tokio::fs;
-
-static DOWNLOAD_CLIENT: LazyLock<Client> = LazyLock::new(|| {
-    Client::builder()
-        .connect_timeout(Duration::from_secs(10))
-        .build()
-        .expect("Failed to create HTTP client")
-});
+use tokio::sync::mpsc;
 
 use crate::cuda::discover::{
-    fetch_available_cuda_versions, fetch_cuda_version_metadata, fetch_cudnn_version_metadata,
+    fetch_available_cuda_versions, fetch_compatible_cudnn_versions, fetch_cuda_redist_file,
+    fetch_cuda_version_metadata, fetch_cudnn_version_metadata,
 };
+use crate::cuda::driver;
+use crate::cuda::source::{cuda_source, cudnn_source};
 use crate::cuda::version::CudaVersion;
 
-use super::download::{DownloadTask, download_file};
-use super::extract::extract_tarball;
+use super::download::{
+    DownloadTask, HostLimiter, bytes_downloaded, download_file, fill_unknown_sizes, part_path,
+};
+use super::extract::{ExtractTracker, extract_package};
+use super::filesystem::filesystem_warnings;
+use super::http;
+use super::introspect::{same_release, toolkit_version};
+use super::lockfile::{DEFAULT_LOCKFILE_NAME, Lockfile};
+use super::manifest::InstallManifest;
+use super::progress::{PlainProgress, ProgressMode, with_reports};
 use super::tasks::{
-    collect_cuda_download_tasks, collect_cudnn_download_task, find_compatible_cudnn,
+    Components, CudnnLookup, collect_cuda_download_tasks, collect_cudnn_download_task,
+    cudnn_variants, find_compatible_cudnn,
 };
-use super::utils::{format_size, target_platform, version_install_dir};
-use super::verify::verify_checksum;
+use super::utils::{format_rate, format_size, target_platform, version_install_dir};
+use super::verify::{record_verified, stamp_path, verify_checksum, verify_checksum_cached};
 use crate::config;
+use crate::error::CudupError;
+use crate::output;
+
+/// Default time a download may go without receiving data before it is aborted.
+pub const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 60;
+
+/// Default number of packages downloaded at once.
+pub const DEFAULT_JOBS: u16 = 4;
+
+/// Number of packages downloaded at once when `--jobs` isn't given: `download.concurrency`
+/// from the config file, else `DEFAULT_JOBS`.
+pub fn default_jobs() -> usize {
+    config::get()
+        .download
+        .concurrency
+        .unwrap_or(usize::from(DEFAULT_JOBS))
+}
+
+/// Default number of simultaneous downloads from a single host, kept low so parallel
+/// installs don't get throttled by NVIDIA's CDN.
+pub const DEFAULT_CONCURRENCY_PER_HOST: u16 = 4;
+
+/// Options controlling how `install_cuda_version` downloads packages.
+#[derive(Debug, Clone)]
+pub struct InstallOptions {
+    /// Maximum time without receiving data before a download is considered stalled.
+    pub idle_timeout: Duration,
+    /// Metadata filename used instead of `redistrib_<version>.json`.
+    pub redist_file: Option<String>,
+    /// Install the CUDA packages without cuDNN.
+    pub skip_cudnn: bool,
+    /// cuDNN version to install instead of the newest compatible one.
+    pub cudnn_version: Option<String>,
+    /// Fail instead of warning when two packages extract different files to the same path.
+    pub strict_extract: bool,
+    /// Print a JSON summary of the install to stdout.
+    pub json: bool,
+    /// Architecture to install for instead of the host's (`x86_64` or `aarch64`).
+    pub arch: Option<String>,
+    /// Skip the warning about an NVIDIA driver too old for the toolkit.
+    pub ignore_driver: bool,
+    /// Command run after a successful install, overriding `hooks.post_install`.
+    pub post_hook: Option<String>,
+    /// Keep archives in the downloads directory after extraction and reuse them later.
+    pub keep_downloads: bool,
+    /// Number of packages downloaded at once.
+    pub jobs: usize,
+    /// Maximum simultaneous downloads from one host, regardless of `jobs`.
+    pub concurrency_per_host: usize,
+    /// Skip packages that fail to download or extract instead of aborting the install.
+    pub continue_on_error: bool,
+    /// Which CUDA packages to install.
+    pub components: Components,
+    /// Directory to install into instead of the managed versions directory.
+    pub dest: Option<PathBuf>,
+    /// Directory archives are downloaded to instead of `~/.cudup/downloads`.
+    pub archive_dir: Option<PathBuf>,
+    /// Progress bars, or plain log lines for build logs.
+    pub progress: ProgressMode,
+}
+
+impl Default for InstallOptions {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(DEFAULT_IDLE_TIMEOUT_SECS),
+            redist_file: None,
+            skip_cudnn: false,
+            cudnn_version: None,
+            strict_extract: false,
+            json: false,
+            arch: None,
+            ignore_driver: false,
+            post_hook: None,
+            keep_downloads: false,
+            jobs: default_jobs(),
+            concurrency_per_host: usize::from(DEFAULT_CONCURRENCY_PER_HOST),
+            continue_on_error: false,
+            components: Components::Full,
+            dest: None,
+            archive_dir: None,
+            progress: ProgressMode::Auto,
+        }
+    }
+}
+
+/// Draws progress to stderr when it's a terminal, and hides it when output is piped
+/// (so redirected logs don't fill up with cursor-control sequences) or `--quiet` is set.
+fn new_multi_progress(mode: ProgressMode) -> MultiProgress {
+    let target =
+        if std::io::stderr().is_terminal() && !output::is_quiet() && mode == ProgressMode::Auto {
+            ProgressDrawTarget::stderr()
+        } else {
+            ProgressDrawTarget::hidden()
+        };
+    MultiProgress::with_draw_target(target)
+}
 
 fn create_progress_bar(mp: &MultiProgress, size: Option<u64>, prefix: String) -> ProgressBar {
     match size {
@@ -55,6 +158,20 @@ fn create_progress_bar(mp: &MultiProgress, size: Option<u64>, prefix: String) ->
     }
 }
 
+/// A bar counting extracted archive entries; its length is set once the archive is listed.
+fn create_extract_bar(mp: &MultiProgress, package: &str) -> ProgressBar {
+    let pb = mp.add(ProgressBar::new(0));
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{prefix:>12.green.bold} [{bar:30.green/dim}] {pos:>6}/{len:<6} files {msg}")
+            .expect("invalid progress bar template")
+            .progress_chars("━━╸"),
+    );
+    pb.set_prefix("Extracting");
+    pb.set_message(package.to_string());
+    pb
+}
+
 fn create_spinner(mp: &MultiProgress, message: String) -> ProgressBar {
     let spinner = mp.add(ProgressBar::new_spinner());
     spinner.set_style(
@@ -89,67 +206,451 @@ impl SizeStats {
     }
 }
 
-async fn process_download_task(
+/// Downloads of the same URL attempted before a checksum mismatch is treated as real
+/// rather than transient corruption on the wire.
+const CHECKSUM_ATTEMPTS: u32 = 2;
+
+/// Downloads and verifies `task`, falling back to the next mirror on a failed download or
+/// checksum mismatch. With `keep_downloads`, an archive kept from an earlier install is
+/// reused when it still matches the checksum.
+async fn download_verified(
     client: &Client,
     task: &DownloadTask,
-    downloads_dir: &Path,
-    install_dir: &Path,
+    archive_path: &Path,
     mp: &MultiProgress,
+    options: &InstallOptions,
+    limiter: &HostLimiter,
+    plain: Option<&PlainProgress>,
 ) -> Result<()> {
+    if options.keep_downloads && archive_path.exists() {
+        match verify_checksum_cached(archive_path, &task.sha256).await {
+            Ok(()) => {
+                info!("Using previously downloaded {}", task.package_name);
+                return Ok(());
+            }
+            Err(e) => {
+                debug!("Discarding kept archive for {}: {}", task.package_name, e);
+                remove_archive(archive_path).await;
+            }
+        }
+    }
+
+    let urls = task.candidate_urls();
+    let mut last_error = None;
+    if plain.is_some() {
+        info!(
+            "Downloading {} ({})",
+            task.package_name,
+            task.size
+                .map(format_size)
+                .as_deref()
+                .unwrap_or("unknown size")
+        );
+    }
+
+    for url in &urls {
+        if let Some(e) = last_error.take() {
+            warn!("{}: {}; trying {}", task.package_name, e, url);
+        }
+
+        for attempt in 1..=CHECKSUM_ATTEMPTS {
+            let pb = create_progress_bar(mp, task.size, task.package_name.clone());
+            if let Some(plain) = plain {
+                plain.track(&task.package_name, &pb);
+            }
+            let downloaded = download_file(
+                client,
+                url,
+                archive_path,
+                Some(&pb),
+                options.idle_timeout,
+                limiter,
+            )
+            .await;
+            pb.finish_and_clear();
+            if let Err(e) = downloaded {
+                last_error = Some(anyhow::Error::from(e));
+                break;
+            }
+
+            let verify_spinner = create_spinner(mp, format!("Verifying {}...", task.package_name));
+            if let Err(e) = verify_checksum(archive_path, &task.sha256).await {
+                verify_spinner
+                    .finish_with_message(format!("[FAIL] {} checksum mismatch", task.package_name));
+                let diagnosis = match fs::metadata(archive_path).await {
+                    Ok(metadata) => mismatch_diagnosis(metadata.len(), task.size),
+                    Err(_) => None,
+                };
+                remove_archive(archive_path).await;
+                if attempt < CHECKSUM_ATTEMPTS {
+                    warn!(
+                        "{}: checksum mismatch, downloading it again",
+                        task.package_name
+                    );
+                }
+                last_error = Some(match diagnosis {
+                    Some(diagnosis) => anyhow!("{}; {}", e, diagnosis),
+                    None => e.into(),
+                });
+                continue;
+            }
+            verify_spinner.finish_and_clear();
+            if options.keep_downloads {
+                record_verified(archive_path, &task.sha256).await;
+            }
+
+            if urls.len() > 1 {
+                info!("{} served by {}", task.package_name, url);
+            }
+            if plain.is_some() {
+                info!("Downloaded {}", task.package_name);
+            }
+            return Ok(());
+        }
+    }
+
+    Err(last_error.expect("candidate_urls always includes the task URL"))
+}
+
+/// Explains a checksum mismatch from the downloaded size: a short file was cut off, one of
+/// the right size was corrupted or is a different file than the metadata describes.
+fn mismatch_diagnosis(actual: u64, expected: Option<u64>) -> Option<String> {
+    let expected = expected?;
+    let diagnosis = if actual < expected {
+        format!(
+            "got {} of {} bytes, so the download was likely truncated",
+            actual, expected
+        )
+    } else if actual > expected {
+        format!(
+            "got {} bytes instead of {}, so the server likely sent a different file",
+            actual, expected
+        )
+    } else {
+        format!(
+            "the size matches ({} bytes) but the content differs, so the file was likely \
+             corrupted in transit or the mirror serves a different build",
+            expected
+        )
+    };
+    Some(diagnosis)
+}
+
+/// Deletes an archive along with its verification stamp and any partial download, so a
+/// bad archive isn't resumed from its own bytes.
+async fn remove_archive(archive_path: &Path) {
+    fs::remove_file(archive_path).await.ok();
+    fs::remove_file(part_path(archive_path)).await.ok();
+    fs::remove_file(stamp_path(archive_path)).await.ok();
+}
+
+/// Creates the directory archives are downloaded to, `--archive-dir` or the downloads
+/// directory, and checks that it's writable before anything is fetched.
+async fn prepare_downloads_dir(options: &InstallOptions) -> Result<PathBuf> {
+    let Some(archive_dir) = &options.archive_dir else {
+        let downloads = config::downloads_dir()?;
+        fs::create_dir_all(&downloads).await?;
+        return Ok(downloads);
+    };
+
+    let archive_dir = std::path::absolute(archive_dir)?;
+    fs::create_dir_all(&archive_dir)
+        .await
+        .with_context(|| format!("Failed to create {}", archive_dir.display()))?;
+    let probe = archive_dir.join(format!(".cudup-write-test.{}", std::process::id()));
+    fs::write(&probe, b"")
+        .await
+        .with_context(|| format!("--archive-dir {} is not writable", archive_dir.display()))?;
+    fs::remove_file(&probe).await.ok();
+
+    Ok(archive_dir)
+}
+
+/// Downloads and verifies `task` into `downloads_dir`, returning the archive path.
+async fn download_archive(
+    task: &DownloadTask,
+    downloads_dir: &Path,
+    mp: &MultiProgress,
+    options: &InstallOptions,
+    limiter: &HostLimiter,
+    plain: Option<&PlainProgress>,
+) -> Result<PathBuf> {
     let archive_path = downloads_dir.join(task.archive_name());
+    download_verified(
+        http::client(),
+        task,
+        &archive_path,
+        mp,
+        options,
+        limiter,
+        plain,
+    )
+    .await?;
+    Ok(archive_path)
+}
 
-    let pb = create_progress_bar(mp, task.size, task.package_name.clone());
-    download_file(client, &task.url, &archive_path, Some(&pb)).await?;
-    pb.finish_and_clear();
+/// Extracts a downloaded archive into `install_dir`, then removes it unless
+/// `keep_downloads` is set.
+async fn extract_archive(
+    task: &DownloadTask,
+    archive_path: &Path,
+    install_dir: &Path,
+    mp: &MultiProgress,
+    options: &InstallOptions,
+    tracker: &mut ExtractTracker,
+) -> Result<()> {
+    let extract_bar = create_extract_bar(mp, &task.package_name);
+    let extracted = extract_package(
+        archive_path,
+        install_dir,
+        &task.package_name,
+        tracker,
+        &extract_bar,
+    )
+    .await;
+    extract_bar.finish_and_clear();
+    if extracted.is_ok() && options.progress == ProgressMode::Plain {
+        info!("Extracted {}", task.package_name);
+    }
 
-    let verify_spinner = create_spinner(mp, format!("Verifying {}...", task.package_name));
-    if let Err(e) = verify_checksum(&archive_path, &task.sha256).await {
-        verify_spinner
-            .finish_with_message(format!("[FAIL] {} checksum mismatch", task.package_name));
-        fs::remove_file(&archive_path).await.ok();
-        return Err(e);
+    if !options.keep_downloads {
+        remove_archive(archive_path).await;
     }
-    verify_spinner.finish_and_clear();
+    extracted
+}
 
-    let extract_spinner = create_spinner(mp, format!("Extracting {}...", task.package_name));
-    extract_tarball(&archive_path, install_dir).await?;
-    extract_spinner.finish_and_clear();
+/// Packages resolved for one CUDA version on one platform.
+#[derive(Debug, Clone)]
+pub struct InstallPlan {
+    pub version: CudaVersion,
+    pub platform: String,
+    pub cuda_tasks: Vec<DownloadTask>,
+    pub cudnn_version: Option<String>,
+    /// Why this cuDNN was picked, e.g. "selected for CUDA 12 via cuda12 variant".
+    pub cudnn_selection: Option<String>,
+    pub cudnn_task: Option<DownloadTask>,
+}
 
-    fs::remove_file(&archive_path).await.ok();
+impl InstallPlan {
+    pub fn tasks(&self) -> impl Iterator<Item = &DownloadTask> {
+        self.cuda_tasks.iter().chain(&self.cudnn_task)
+    }
+}
 
-    Ok(())
+/// A cuDNN release picked for a CUDA version. `task` is `None` when the release has no
+/// build for the target platform.
+struct CudnnChoice {
+    version: String,
+    selection: String,
+    task: Option<DownloadTask>,
 }
 
-pub async fn install_cuda_version(version: &CudaVersion) -> Result<()> {
-    let mp = MultiProgress::new();
+/// Describes how `cuda_variant` was chosen for `version`, for the install summary.
+fn describe_cudnn_selection(version: &CudaVersion, cuda_variant: &str, explicit: bool) -> String {
+    let requested = format!("cuda{}", version.major());
+    if explicit {
+        format!("requested with --cudnn-version, {} variant", cuda_variant)
+    } else if cuda_variant == requested {
+        format!(
+            "selected for CUDA {} via {} variant",
+            version.major(),
+            cuda_variant
+        )
+    } else {
+        format!(
+            "selected for CUDA {} via {} variant, as no {} build exists",
+            version.major(),
+            cuda_variant,
+            requested
+        )
+    }
+}
 
-    let platform = target_platform()?;
-    info!("Detected platform: {}", platform);
+/// Picks the cuDNN to install, either the explicit `--cudnn-version` or the newest compatible one.
+async fn resolve_cudnn(
+    version: &CudaVersion,
+    platform: &str,
+    options: &InstallOptions,
+    mp: &MultiProgress,
+) -> Result<Option<CudnnChoice>> {
+    let lookup = match &options.cudnn_version {
+        Some(cudnn_version) => CudnnLookup::Compatible {
+            cudnn_version: cudnn_version.clone(),
+            cuda_variant: format!("cuda{}", version.major()),
+        },
+        None => {
+            let cudnn_spinner =
+                create_spinner(mp, "Finding compatible cuDNN version...".to_string());
+            let result = find_compatible_cudnn(version).await?;
+            cudnn_spinner.finish_and_clear();
+            result
+        }
+    };
 
-    let check_spinner = create_spinner(&mp, "Checking available versions...".to_string());
-    let available_versions = fetch_available_cuda_versions().await?;
-    check_spinner.finish_and_clear();
+    match lookup {
+        CudnnLookup::Compatible {
+            cudnn_version,
+            cuda_variant,
+        } => {
+            let selection =
+                describe_cudnn_selection(version, &cuda_variant, options.cudnn_version.is_some());
+            info!("Found cuDNN {} ({})", cudnn_version, selection);
+            let cudnn_metadata = fetch_cudnn_version_metadata(&cudnn_version).await?;
+            let task = collect_cudnn_download_task(
+                &*cudnn_source(),
+                &cudnn_metadata,
+                &cuda_variant,
+                platform,
+            );
+            if task.is_none() {
+                warn!(
+                    "cuDNN {} has no {} build for {} (available: {})",
+                    cudnn_version,
+                    cuda_variant,
+                    platform,
+                    format_variants(&cudnn_variants(&cudnn_metadata))
+                );
+                if options.cudnn_version.is_some() {
+                    suggest_compatible_cudnn(version).await;
+                }
+            }
+            Ok(Some(CudnnChoice {
+                version: cudnn_version,
+                selection,
+                task,
+            }))
+        }
+        CudnnLookup::NoMatchingVariant {
+            newest_version,
+            available_variants,
+        } => {
+            warn!(
+                "No cuDNN is published for CUDA {} (cuda{}); the newest cuDNN {} supports {}",
+                version,
+                version.major(),
+                newest_version,
+                format_variants(&available_variants)
+            );
+            Ok(None)
+        }
+        CudnnLookup::Unavailable => {
+            warn!(
+                "No cuDNN releases found; skipping cuDNN for CUDA {}",
+                version
+            );
+            Ok(None)
+        }
+    }
+}
 
-    if !available_versions.contains(version.as_str()) {
-        bail!("CUDA version {} is not available", version);
+/// Lists the cuDNN versions that do support `version`, after an explicit `--cudnn-version` didn't.
+async fn suggest_compatible_cudnn(version: &CudaVersion) {
+    match fetch_compatible_cudnn_versions(version.as_str()).await {
+        Ok(compatible) if !compatible.is_empty() => warn!(
+            "cuDNN versions built for CUDA {}: {}",
+            version.major(),
+            compatible.into_iter().collect::<Vec<_>>().join(", ")
+        ),
+        Ok(_) => warn!("No cuDNN versions are built for CUDA {}", version.major()),
+        Err(e) => debug!("Could not list compatible cuDNN versions: {}", e),
     }
-    info!("Version {} available", version);
+}
 
+fn format_variants(variants: &[String]) -> String {
+    if variants.is_empty() {
+        "no CUDA variants".to_string()
+    } else {
+        variants.join(", ")
+    }
+}
+
+fn ensure_not_installed(version: &CudaVersion) -> Result<PathBuf> {
     let install_dir = version_install_dir(version.as_str())?;
-    if install_dir.exists() {
+    if InstallManifest::load(&install_dir)?.is_some_and(|m| m.pending) {
         bail!(
-            "CUDA {} is already installed at {}",
+            "CUDA {} is staged but not downloaded. Run `cudup install {} --resume`.",
             version,
+            version
+        );
+    }
+    if install_dir.exists() {
+        return Err(CudupError::AlreadyInstalled {
+            version: version.to_string(),
+            path: install_dir,
+        }
+        .into());
+    }
+    Ok(install_dir)
+}
+
+/// Where `version` gets installed: `--dest` if given, otherwise its managed version directory.
+pub fn install_dir_for(version: &CudaVersion, options: &InstallOptions) -> Result<PathBuf> {
+    match &options.dest {
+        Some(dest) => Ok(std::path::absolute(dest)?),
+        None => version_install_dir(version.as_str()),
+    }
+}
+
+/// Checks that `version` can be installed and returns the directory to install into.
+/// A `--dest` directory only has to be empty; it isn't checked against the managed tree.
+fn prepare_install_dir(version: &CudaVersion, options: &InstallOptions) -> Result<PathBuf> {
+    if options.dest.is_none() {
+        return ensure_not_installed(version);
+    }
+
+    let install_dir = install_dir_for(version, options)?;
+    if install_dir.exists() && std::fs::read_dir(&install_dir)?.next().is_some() {
+        bail!(
+            "{} is not empty; --dest needs a new or empty directory",
             install_dir.display()
         );
     }
+    Ok(install_dir)
+}
 
-    info!("Installing CUDA {} to {}", version, install_dir.display());
+/// Resolves the CUDA packages and compatible cuDNN for `version` from live metadata.
+pub async fn resolve_install_plan(
+    version: &CudaVersion,
+    options: &InstallOptions,
+) -> Result<InstallPlan> {
+    let mp = new_multi_progress(options.progress);
+
+    let platform = target_platform(options.arch.as_deref())?;
+    info!("Detected platform: {}", platform);
+
+    let cuda_metadata = match &options.redist_file {
+        Some(filename) => {
+            info!("Using redist file override {}", filename);
+            let meta_spinner = create_spinner(&mp, format!("Fetching {}...", filename));
+            let metadata = fetch_cuda_redist_file(filename).await?;
+            meta_spinner.finish_and_clear();
+            metadata
+        }
+        None => {
+            let check_spinner = create_spinner(&mp, "Checking available versions...".to_string());
+            let available_versions = fetch_available_cuda_versions().await?;
+            check_spinner.finish_and_clear();
+
+            if !available_versions.contains(version.as_str()) {
+                return Err(CudupError::VersionNotAvailable(version.to_string()).into());
+            }
+            info!("Version {} available", version);
+
+            let meta_spinner =
+                create_spinner(&mp, format!("Fetching CUDA {} metadata...", version));
+            let metadata = fetch_cuda_version_metadata(version.as_str()).await?;
+            meta_spinner.finish_and_clear();
+            metadata
+        }
+    };
 
-    let meta_spinner = create_spinner(&mp, format!("Fetching CUDA {} metadata...", version));
-    let cuda_metadata = fetch_cuda_version_metadata(version.as_str()).await?;
-    let cuda_tasks = collect_cuda_download_tasks(&cuda_metadata, version, platform);
-    meta_spinner.finish_and_clear();
+    let cuda_tasks = collect_cuda_download_tasks(
+        &*cuda_source(),
+        &cuda_metadata,
+        version,
+        platform,
+        options.components,
+    );
 
     if cuda_tasks.is_empty() {
         bail!("CUDA {} has no packages for platform {}", version, platform);
@@ -162,65 +663,603 @@ pub async fn install_cuda_version(version: &CudaVersion) -> Result<()> {
         cuda_stats.format()
     );
 
-    let cudnn_spinner = create_spinner(&mp, "Finding compatible cuDNN version...".to_string());
-    let cudnn_result = find_compatible_cudnn(version).await?;
-    cudnn_spinner.finish_and_clear();
+    let cudnn = if options.skip_cudnn {
+        info!("Skipping cuDNN");
+        None
+    } else {
+        resolve_cudnn(version, platform, options, &mp).await?
+    };
+    let (cudnn_version, cudnn_selection, cudnn_task) = match cudnn {
+        Some(choice) => (Some(choice.version), Some(choice.selection), choice.task),
+        None => (None, None, None),
+    };
 
-    let cudnn_task = match cudnn_result {
-        Some((cudnn_version, cuda_variant)) => {
-            info!("Found cuDNN {} ({})", cudnn_version, cuda_variant);
-            let cudnn_metadata = fetch_cudnn_version_metadata(&cudnn_version).await?;
-            collect_cudnn_download_task(&cudnn_metadata, &cuda_variant, platform)
-        }
-        None => {
-            warn!("No compatible cuDNN found for CUDA {}", version);
-            None
+    Ok(InstallPlan {
+        version: version.clone(),
+        platform: platform.to_string(),
+        cuda_tasks,
+        cudnn_version,
+        cudnn_selection,
+        cudnn_task,
+    })
+}
+
+/// Reports where the key binaries and libraries landed, based on the manifest's file list.
+fn print_install_summary(install_dir: &Path, manifest: &InstallManifest) {
+    let file_name = |path: &str| {
+        Path::new(path)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+    };
+    let libraries: Vec<String> = manifest
+        .files
+        .keys()
+        .filter(|path| path.starts_with("lib64/") || path.starts_with("lib/"))
+        .filter_map(|path| file_name(path))
+        .filter(|name| name.starts_with("lib"))
+        .collect();
+    let has_nvcc = manifest.files.contains_key("bin/nvcc");
+    let has_cudnn = libraries.iter().any(|name| name.starts_with("libcudnn"));
+
+    eprintln!();
+    eprintln!("  CUDA_HOME: {}", install_dir.display());
+    eprintln!(
+        "  nvcc:      {}",
+        if has_nvcc { "found" } else { "missing" }
+    );
+    if let Some(toolkit_version) = &manifest.toolkit_version {
+        eprintln!("  Toolkit:   {}", toolkit_version);
+    }
+    eprintln!("  Libraries: {}", libraries.len());
+    match (&manifest.cudnn_version, &manifest.cudnn_selection) {
+        (Some(cudnn_version), Some(selection)) if has_cudnn => {
+            eprintln!("  cuDNN:     {} ({})", cudnn_version, selection)
         }
+        _ => eprintln!(
+            "  cuDNN:     {}",
+            if has_cudnn { "found" } else { "not installed" }
+        ),
+    }
+    if let Some(stubs) = ["lib64/stubs", "lib/stubs"]
+        .into_iter()
+        .find(|dir| install_dir.join(dir).is_dir())
+    {
+        eprintln!(
+            "  Stubs:     {} (for linking without a driver; see `cudup use --with-stubs`)",
+            stubs
+        );
+    }
+}
+
+/// File in a temporary install directory naming the process that's writing it.
+const TEMP_OWNER_FILE: &str = ".cudup-install.pid";
+
+/// Age after which an abandoned temporary install directory is swept up by any install.
+const STALE_TEMP_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Sibling of `install_dir` that an install is extracted into before being renamed into
+/// place, e.g. `versions/.12.4.1.tmp`.
+fn temp_install_dir(install_dir: &Path) -> PathBuf {
+    let name = install_dir
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    install_dir.with_file_name(format!(".{}.tmp", name))
+}
+
+/// The still-running process writing `temp_dir`, if any.
+fn temp_dir_owner(temp_dir: &Path) -> Option<u32> {
+    let pid: u32 = std::fs::read_to_string(temp_dir.join(TEMP_OWNER_FILE))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    (pid != std::process::id() && Path::new(&format!("/proc/{}", pid)).exists()).then_some(pid)
+}
+
+/// Removes temporary install directories left in `parent` by crashed installs once they
+/// are older than [`STALE_TEMP_AGE`] and no running process owns them.
+async fn sweep_stale_temp_dirs(parent: &Path) {
+    let Ok(entries) = std::fs::read_dir(parent) else {
+        return;
     };
 
-    let mut total_stats = SizeStats::from_tasks(&cuda_tasks);
-    if let Some(ref task) = cudnn_task {
-        if let Some(s) = task.size {
-            total_stats.known_size += s;
-        } else {
-            total_stats.unknown_count += 1;
+    for entry in entries.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !(name.starts_with('.') && name.ends_with(".tmp")) {
+            continue;
+        }
+        let path = entry.path();
+        let age = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok());
+        if age.is_some_and(|age| age > STALE_TEMP_AGE) && temp_dir_owner(&path).is_none() {
+            info!("Removing stale temporary install {}", path.display());
+            fs::remove_dir_all(&path).await.ok();
         }
     }
-    let total_packages = cuda_tasks.len() + usize::from(cudnn_task.is_some());
+}
+
+/// Creates the temporary directory for installing into `install_dir`. A leftover one
+/// from an interrupted install is cleared first, unless another process is using it.
+/// Outside the managed tree, only directories cudup marked as its own are removed.
+async fn create_temp_install_dir(
+    install_dir: &Path,
+    version: &CudaVersion,
+    managed: bool,
+) -> Result<PathBuf> {
+    let temp_dir = temp_install_dir(install_dir);
+    if let Some(parent) = temp_dir.parent() {
+        fs::create_dir_all(parent).await?;
+        if managed {
+            sweep_stale_temp_dirs(parent).await;
+        }
+    }
+
+    if temp_dir.exists() {
+        if !managed && !temp_dir.join(TEMP_OWNER_FILE).exists() {
+            bail!(
+                "{} already exists and wasn't created by cudup; remove it to install here",
+                temp_dir.display()
+            );
+        }
+        if let Some(pid) = temp_dir_owner(&temp_dir) {
+            bail!(
+                "CUDA {} is already being installed by another cudup process (pid {}); \
+                 wait for it to finish or remove {}",
+                version,
+                pid,
+                temp_dir.display()
+            );
+        }
+        warn!(
+            "Found files from an interrupted install of CUDA {} in {}; removing them",
+            version,
+            temp_dir.display()
+        );
+        fs::remove_dir_all(&temp_dir).await?;
+    }
+
+    fs::create_dir_all(&temp_dir).await?;
+    fs::write(
+        temp_dir.join(TEMP_OWNER_FILE),
+        std::process::id().to_string(),
+    )
+    .await?;
+    Ok(temp_dir)
+}
+
+/// Downloads, verifies, and extracts every package in `plan` into a temporary directory,
+/// then moves it into the version directory, so a crash never leaves a half-installed
+/// version behind.
+pub async fn execute_install_plan(plan: &InstallPlan, options: &InstallOptions) -> Result<()> {
+    let mp = new_multi_progress(options.progress);
+    let version = &plan.version;
+    let install_dir = prepare_install_dir(version, options)?;
+
+    info!("Installing CUDA {} to {}", version, install_dir.display());
+    for warning in filesystem_warnings(&install_dir) {
+        warn!(
+            "{}; consider installing elsewhere with --dest or CUDUP_HOME",
+            warning
+        );
+    }
+
+    let mut tasks: Vec<DownloadTask> = plan.tasks().cloned().collect();
+    fill_unknown_sizes(http::client(), &mut tasks).await;
+    let total_stats = SizeStats::from_tasks(&tasks);
+    let started = Instant::now();
+    let downloaded_before = bytes_downloaded();
 
     info!(
         "Downloading {} packages ({})",
-        total_packages,
+        tasks.len(),
         total_stats.format()
     );
 
-    let downloads = config::downloads_dir()?;
-    fs::create_dir_all(&downloads).await?;
-    fs::create_dir_all(&install_dir).await?;
+    let downloads = prepare_downloads_dir(options).await?;
+    let temp_dir = create_temp_install_dir(&install_dir, version, options.dest.is_none()).await?;
 
-    let install_result = async {
-        for task in &cuda_tasks {
-            process_download_task(&DOWNLOAD_CLIENT, task, &downloads, &install_dir, &mp).await?;
+    let mut tracker = ExtractTracker::new(options.strict_extract);
+    let limiter = HostLimiter::new(options.concurrency_per_host);
+    let plain = (options.progress == ProgressMode::Plain)
+        .then(|| PlainProgress::new(total_stats.known_size));
+    let install_result = with_reports(plain.as_ref(), async {
+        // Downloads run concurrently, but archives are extracted one at a time in plan
+        // order so overlapping files always resolve the same way.
+        let (tasks, downloads, mp, limiter, plain) =
+            (&tasks, &downloads, &mp, &limiter, plain.as_ref());
+        let (downloaded_tx, mut downloaded_rx) = mpsc::unbounded_channel();
+
+        let download_all = async move {
+            let mut pending = stream::iter(tasks)
+                .map(|task| async move {
+                    let downloaded =
+                        download_archive(task, downloads, mp, options, limiter, plain).await;
+                    (task, downloaded)
+                })
+                .buffered(options.jobs.max(1));
+
+            while let Some(downloaded) = pending.next().await {
+                if downloaded_tx.send(downloaded).is_err() {
+                    break;
+                }
+            }
+            Ok(())
+        };
+
+        // Returns the packages that failed, which is only ever non-empty with
+        // `continue_on_error`; otherwise the first failure aborts the install.
+        let extract_all = async {
+            let mut failed = Vec::new();
+            while let Some((task, downloaded)) = downloaded_rx.recv().await {
+                let result = match downloaded {
+                    Ok(archive_path) => {
+                        extract_archive(task, &archive_path, &temp_dir, mp, options, &mut tracker)
+                            .await
+                    }
+                    Err(e) => Err(e),
+                };
+
+                if let Err(e) = result {
+                    if !options.continue_on_error {
+                        return Err(e);
+                    }
+                    warn!("{}: {:#}; continuing without it", task.package_name, e);
+                    // Even a verified archive is dropped, so a retry starts from scratch.
+                    remove_archive(&downloads.join(task.archive_name())).await;
+                    failed.push(task.package_name.clone());
+                }
+            }
+            Ok(failed)
+        };
+
+        let ((), failed) = futures::future::try_join(download_all, extract_all).await?;
+        if failed.len() == tasks.len() {
+            bail!("None of the {} packages could be installed", tasks.len());
         }
+        Ok(failed)
+    })
+    .await;
 
-        if let Some(task) = &cudnn_task {
-            process_download_task(&DOWNLOAD_CLIENT, task, &downloads, &install_dir, &mp).await?;
+    let failed = match install_result {
+        Ok(failed) => failed,
+        Err(e) => {
+            let _ = fs::remove_dir_all(&temp_dir).await;
+            return Err(e);
         }
+    };
 
-        Ok::<_, anyhow::Error>(())
+    let mut manifest = InstallManifest::from_plan(plan);
+    manifest.mark_failed(&failed);
+    manifest.record_package_files(&tracker);
+    let finished = async {
+        fs::remove_file(temp_dir.join(TEMP_OWNER_FILE)).await?;
+        manifest.record_files(&temp_dir).await?;
+        manifest.toolkit_version = toolkit_version(&temp_dir);
+        if let Some(reported) = &manifest.toolkit_version
+            && !same_release(version.as_str(), reported)
+        {
+            warn!(
+                "CUDA {} reports its toolkit version as {}",
+                version, reported
+            );
+        }
+        manifest.save(&temp_dir)?;
+        // Pins the archives of a complete install, so it can be replayed elsewhere.
+        if failed.is_empty() {
+            Lockfile::from_plan(plan).write(&temp_dir.join(DEFAULT_LOCKFILE_NAME))?;
+        }
+        // Replaces an empty `--dest` directory; managed version directories don't exist yet.
+        fs::rename(&temp_dir, &install_dir)
+            .await
+            .with_context(|| format!("Failed to move the install into {}", install_dir.display()))
     }
     .await;
-
-    if let Err(e) = install_result {
-        let _ = fs::remove_dir_all(&install_dir).await;
+    if let Err(e) = finished {
+        let _ = fs::remove_dir_all(&temp_dir).await;
         return Err(e);
     }
 
-    info!("CUDA {} installed successfully!", version);
-    println!();
-    println!("To use this version, run:");
-    println!("  cudup use {}", version);
-    println!();
+    if failed.is_empty() {
+        info!("CUDA {} installed successfully!", version);
+    } else {
+        warn!(
+            "CUDA {} is incomplete: {} of {} packages installed, failed: {}",
+            version,
+            tasks.len() - failed.len(),
+            tasks.len(),
+            failed.join(", ")
+        );
+    }
+    let downloaded = bytes_downloaded() - downloaded_before;
+    if downloaded > 0 {
+        let elapsed = started.elapsed();
+        info!(
+            "Downloaded {} in {:.1}s ({})",
+            format_size(downloaded),
+            elapsed.as_secs_f64(),
+            format_rate(downloaded, elapsed)
+        );
+    }
+    if options.dest.is_some() {
+        warn!(
+            "{} is outside cudup's versions directory, so `cudup use` won't manage it. \
+             Point CUDA_HOME at it to use it.",
+            install_dir.display()
+        );
+    }
+    if !output::is_quiet() {
+        print_install_summary(&install_dir, &manifest);
+        if options.dest.is_none() {
+            eprintln!();
+            eprintln!("To use this version, run:");
+            eprintln!("  cudup use {}", version);
+        }
+        eprintln!();
+    }
+
+    Ok(())
+}
+
+/// Warns when the installed NVIDIA driver is too old to run programs built with `version`.
+fn warn_if_driver_too_old(version: &CudaVersion) {
+    let Some(required) = driver::minimum_driver_version(version) else {
+        return;
+    };
+    let Some(installed) = driver::installed_driver_version() else {
+        debug!("nvidia-smi not available, skipping driver check");
+        return;
+    };
+
+    if driver::is_older_driver(&installed, required) {
+        warn!(
+            "CUDA {} requires NVIDIA driver {} or newer, but {} is installed. \
+             The toolkit will install, but programs built with it won't run until the driver \
+             is updated. Pass --ignore-driver to skip this check.",
+            version, required, installed
+        );
+    }
+}
+
+pub async fn install_cuda_version(version: &CudaVersion, options: &InstallOptions) -> Result<()> {
+    prepare_install_dir(version, options)?;
+    if !options.ignore_driver {
+        warn_if_driver_too_old(version);
+    }
+
+    let plan = resolve_install_plan(version, options).await?;
+    execute_install_plan(&plan, options).await
+}
+
+/// Resolves the plan for `version` and records it as a pending manifest in the version
+/// directory, without downloading anything. Returns the install directory.
+pub async fn stage_install(version: &CudaVersion, options: &InstallOptions) -> Result<PathBuf> {
+    let install_dir = ensure_not_installed(version)?;
+    let plan = resolve_install_plan(version, options).await?;
+
+    let mut manifest = InstallManifest::from_plan(&plan);
+    manifest.pending = true;
+    fs::create_dir_all(&install_dir).await?;
+    manifest.save(&install_dir)?;
+
+    info!(
+        "Staged CUDA {} ({} packages) in {}",
+        version,
+        manifest.packages.len(),
+        install_dir.display()
+    );
+    Ok(install_dir)
+}
+
+/// Downloads and installs a plan staged by [`stage_install`]. The staged manifest is
+/// restored if the install fails, so it can be resumed again.
+pub async fn resume_install(version: &CudaVersion, options: &InstallOptions) -> Result<()> {
+    let install_dir = version_install_dir(version.as_str())?;
+    let staged = match InstallManifest::load(&install_dir)? {
+        Some(manifest) if manifest.pending => manifest,
+        Some(_) => bail!("CUDA {} is already installed", version),
+        None => bail!(
+            "No staged install for CUDA {}. Run `cudup install {} --manifest-only` first.",
+            version,
+            version
+        ),
+    };
+
+    let plan = staged.clone().into_plan()?;
+    fs::remove_dir_all(&install_dir).await?;
+
+    let result = execute_install_plan(&plan, options).await;
+    if result.is_err() && !install_dir.exists() {
+        fs::create_dir_all(&install_dir).await?;
+        staged.save(&install_dir)?;
+    }
+    result
+}
+
+/// Adds cuDNN to an already installed CUDA version without touching its CUDA packages.
+pub async fn install_cudnn_only(version: &CudaVersion, options: &InstallOptions) -> Result<()> {
+    let install_dir = version_install_dir(version.as_str())?;
+    if !install_dir.exists() {
+        bail!(
+            "CUDA {} is not installed. Run `cudup install {}` first.",
+            version,
+            version
+        );
+    }
+
+    let mp = new_multi_progress(options.progress);
+    let platform = target_platform(options.arch.as_deref())?;
+
+    let Some(CudnnChoice {
+        version: cudnn_version,
+        selection,
+        task: Some(task),
+    }) = resolve_cudnn(version, platform, options, &mp).await?
+    else {
+        bail!(
+            "No cuDNN package available for CUDA {} on {}",
+            version,
+            platform
+        );
+    };
+
+    let mut manifest = InstallManifest::load(&install_dir)?
+        .unwrap_or_else(|| InstallManifest::new(version.as_str(), platform));
+    if let Some(previous) = &manifest.cudnn_version {
+        info!("Replacing cuDNN {} with {}", previous, cudnn_version);
+    }
+
+    let downloads = prepare_downloads_dir(options).await?;
+    let mut tracker = ExtractTracker::new(options.strict_extract);
+    let limiter = HostLimiter::new(options.concurrency_per_host);
+    let plain = (options.progress == ProgressMode::Plain)
+        .then(|| PlainProgress::new(task.size.unwrap_or(0)));
+    let archive_path = with_reports(
+        plain.as_ref(),
+        download_archive(&task, &downloads, &mp, options, &limiter, plain.as_ref()),
+    )
+    .await?;
+    extract_archive(
+        &task,
+        &archive_path,
+        &install_dir,
+        &mp,
+        options,
+        &mut tracker,
+    )
+    .await?;
+
+    manifest.set_cudnn(&cudnn_version, &selection, &task);
+    manifest.record_package_files(&tracker);
+    manifest.record_files(&install_dir).await?;
+    manifest.save(&install_dir)?;
+
+    info!(
+        "cuDNN {} installed into CUDA {} ({})",
+        cudnn_version, version, selection
+    );
 
     Ok(())
 }
+
+/// Re-downloads and extracts the packages of an installed version that are missing files,
+/// leaving the rest of the install in place.
+pub async fn repair_install(version: &CudaVersion, options: &InstallOptions) -> Result<()> {
+    let install_dir = version_install_dir(version.as_str())?;
+    let mut manifest = match InstallManifest::load(&install_dir)? {
+        Some(manifest) if manifest.pending => bail!(
+            "CUDA {} is staged but not downloaded. Run `cudup install {} --resume`.",
+            version,
+            version
+        ),
+        Some(manifest) => manifest,
+        None if install_dir.exists() => bail!(
+            "CUDA {} has no install manifest to check against. Reinstall it with \
+             `cudup uninstall {} && cudup install {}`.",
+            version,
+            version,
+            version
+        ),
+        None => bail!(
+            "CUDA {} is not installed. Run `cudup install {}` first.",
+            version,
+            version
+        ),
+    };
+    if manifest.package_files.is_empty() {
+        bail!(
+            "CUDA {} was installed before cudup recorded each package's files, so it can't \
+             be repaired. Reinstall it with `cudup uninstall {} && cudup install {}`.",
+            version,
+            version,
+            version
+        );
+    }
+
+    let missing: Vec<DownloadTask> = manifest
+        .missing_packages(&install_dir)
+        .into_iter()
+        .map(DownloadTask::from)
+        .collect();
+    if missing.is_empty() {
+        info!(
+            "All {} packages of CUDA {} are present",
+            manifest.packages.len(),
+            version
+        );
+        return Ok(());
+    }
+    let names: Vec<&str> = missing.iter().map(|t| t.package_name.as_str()).collect();
+    info!(
+        "Repairing {} of {} packages of CUDA {}: {}",
+        missing.len(),
+        manifest.packages.len(),
+        version,
+        names.join(", ")
+    );
+
+    let mp = new_multi_progress(options.progress);
+    let downloads = prepare_downloads_dir(options).await?;
+    let intact = manifest
+        .package_files
+        .iter()
+        .filter(|(package, _)| !names.contains(&package.as_str()));
+    let mut tracker = ExtractTracker::preserving(options.strict_extract, intact);
+    let limiter = HostLimiter::new(options.concurrency_per_host);
+    for task in &missing {
+        let archive_path = download_archive(task, &downloads, &mp, options, &limiter, None).await?;
+        extract_archive(
+            task,
+            &archive_path,
+            &install_dir,
+            &mp,
+            options,
+            &mut tracker,
+        )
+        .await?;
+    }
+
+    manifest.record_package_files(&tracker);
+    manifest.record_files(&install_dir).await?;
+    manifest.save(&install_dir)?;
+
+    info!("Repaired CUDA {} ({})", version, names.join(", "));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_download_is_diagnosed_as_truncated() {
+        assert_eq!(
+            mismatch_diagnosis(512, Some(1024)).as_deref(),
+            Some("got 512 of 1024 bytes, so the download was likely truncated")
+        );
+    }
+
+    #[test]
+    fn same_size_download_is_diagnosed_as_corrupted() {
+        let diagnosis = mismatch_diagnosis(1024, Some(1024)).unwrap();
+        assert!(
+            diagnosis.starts_with("the size matches (1024 bytes) but the content differs"),
+            "{diagnosis}"
+        );
+    }
+
+    #[test]
+    fn long_download_is_diagnosed_as_a_different_file() {
+        let diagnosis = mismatch_diagnosis(2048, Some(1024)).unwrap();
+        assert!(diagnosis.contains("different file"), "{diagnosis}");
+    }
+
+    #[test]
+    fn unknown_size_gives_no_diagnosis() {
+        assert_eq!(mismatch_diagnosis(1024, None), None);
+    }
+}
@@ -0,0 +1,37 @@
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+#[derive(Deserialize)]
+struct VersionJson {
+    cuda: VersionJsonEntry,
+}
+
+#[derive(Deserialize)]
+struct VersionJsonEntry {
+    version: String,
+}
+
+/// The toolkit version a CUDA install reports about itself, read from `version.json`
+/// (CUDA 11.1 and later) or the older `version.txt` ("CUDA Version 10.2.89"). This can
+/// differ from the redist tag the install directory is named after.
+pub fn toolkit_version(install_dir: &Path) -> Option<String> {
+    if let Ok(contents) = fs::read_to_string(install_dir.join("version.json"))
+        && let Ok(parsed) = serde_json::from_str::<VersionJson>(&contents)
+    {
+        return Some(parsed.cuda.version);
+    }
+
+    let contents = fs::read_to_string(install_dir.join("version.txt")).ok()?;
+    contents
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("CUDA Version "))
+        .map(|version| version.trim().to_string())
+}
+
+/// Whether `toolkit_version` belongs to the release `version` names, comparing only the
+/// major and minor components since patch numbers differ between the two schemes.
+pub fn same_release(version: &str, toolkit_version: &str) -> bool {
+    let major_minor = |v: &str| v.split('.').take(2).collect::<Vec<_>>().join(".");
+    major_minor(version) == major_minor(toolkit_version)
+}
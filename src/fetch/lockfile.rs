@@ -0,0 +1,264 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::cuda::version::CudaVersion;
+
+use super::download::DownloadTask;
+use super::installer::InstallPlan;
+use super::manifest::InstallManifest;
+use super::utils::target_platform;
+
+pub const LOCKFILE_VERSION: u32 = 1;
+pub const DEFAULT_LOCKFILE_NAME: &str = "cudup.lock";
+pub const DEFAULT_FREEZE_FILE_NAME: &str = "cudup-freeze.lock";
+
+/// Exact set of archives for one CUDA install, replayable without live metadata.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lockfile {
+    pub lockfile_version: u32,
+    pub cuda_version: String,
+    pub platform: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cudnn_version: Option<String>,
+    #[serde(default, rename = "package")]
+    pub packages: Vec<LockedPackage>,
+}
+
+/// Every installed version, pinned to the archives recorded when it was installed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FreezeFile {
+    #[serde(default, rename = "version")]
+    pub versions: Vec<Lockfile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockedPackage {
+    pub name: String,
+    pub url: String,
+    pub sha256: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    pub relative_path: String,
+}
+
+impl From<&DownloadTask> for LockedPackage {
+    fn from(task: &DownloadTask) -> Self {
+        Self {
+            name: task.package_name.clone(),
+            url: task.url.clone(),
+            sha256: task.sha256.clone(),
+            size: task.size,
+            relative_path: task.relative_path.clone(),
+        }
+    }
+}
+
+impl From<LockedPackage> for DownloadTask {
+    fn from(package: LockedPackage) -> Self {
+        Self {
+            package_name: package.name,
+            url: package.url,
+            sha256: package.sha256,
+            size: package.size,
+            relative_path: package.relative_path,
+        }
+    }
+}
+
+impl Lockfile {
+    pub fn from_plan(plan: &InstallPlan) -> Self {
+        Self {
+            lockfile_version: LOCKFILE_VERSION,
+            cuda_version: plan.version.to_string(),
+            platform: plan.platform.clone(),
+            cudnn_version: plan.cudnn_version.clone(),
+            packages: plan.tasks().map(LockedPackage::from).collect(),
+        }
+    }
+
+    /// Pins the archives an install's manifest records.
+    pub fn from_manifest(manifest: &InstallManifest) -> Self {
+        Self {
+            lockfile_version: LOCKFILE_VERSION,
+            cuda_version: manifest.cuda_version.clone(),
+            platform: manifest.platform.clone(),
+            cudnn_version: manifest.cudnn_version.clone(),
+            packages: manifest.packages.clone(),
+        }
+    }
+
+    /// Converts the lockfile back into an install plan for the current platform.
+    pub fn into_plan(self, arch: Option<&str>) -> Result<InstallPlan> {
+        if self.lockfile_version != LOCKFILE_VERSION {
+            bail!(
+                "Unsupported lockfile version {} (expected {})",
+                self.lockfile_version,
+                LOCKFILE_VERSION
+            );
+        }
+
+        let platform = target_platform(arch)?;
+        if self.platform != platform {
+            bail!(
+                "Lockfile was created for {}, but this machine is {}",
+                self.platform,
+                platform
+            );
+        }
+
+        let version = CudaVersion::new(self.cuda_version)?;
+        let (cudnn, cuda): (Vec<_>, Vec<_>) = self
+            .packages
+            .into_iter()
+            .map(DownloadTask::from)
+            .partition(|task| task.package_name == "cudnn");
+
+        Ok(InstallPlan {
+            version,
+            platform: self.platform,
+            cuda_tasks: cuda,
+            cudnn_selection: self
+                .cudnn_version
+                .as_ref()
+                .map(|_| "pinned by lockfile".to_string()),
+            cudnn_version: self.cudnn_version,
+            cudnn_task: cudnn.into_iter().next(),
+        })
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read lockfile {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse lockfile {}", path.display()))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let contents = toml::to_string_pretty(self).context("Failed to serialize lockfile")?;
+        fs::write(path, contents)
+            .with_context(|| format!("Failed to write lockfile {}", path.display()))
+    }
+}
+
+impl FreezeFile {
+    pub fn read(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read freeze file {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse freeze file {}", path.display()))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let contents = toml::to_string_pretty(self).context("Failed to serialize freeze file")?;
+        fs::write(path, contents)
+            .with_context(|| format!("Failed to write freeze file {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn task(name: &str, sha256: &str, size: Option<u64>) -> DownloadTask {
+        let relative_path = format!("{name}/linux-x86_64/{name}-linux-x86_64-archive.tar.xz");
+        DownloadTask {
+            package_name: name.to_string(),
+            url: format!("https://mirror.example.com/{relative_path}"),
+            sha256: sha256.to_string(),
+            size,
+            relative_path,
+        }
+    }
+
+    fn plan() -> InstallPlan {
+        InstallPlan {
+            version: CudaVersion::new("12.4.1").unwrap(),
+            platform: "linux-x86_64".to_string(),
+            cuda_tasks: vec![
+                task("cuda_cudart", &"a".repeat(64), Some(1024)),
+                task("cuda_nvcc", &"b".repeat(64), None),
+            ],
+            cudnn_version: Some("9.1.0".to_string()),
+            cudnn_selection: Some("selected for CUDA 12 via cuda12 variant".to_string()),
+            cudnn_task: Some(task("cudnn", &"c".repeat(64), Some(4096))),
+        }
+    }
+
+    fn pinned(plan: &InstallPlan) -> Vec<(String, String, String, Option<u64>)> {
+        plan.tasks()
+            .map(|t| {
+                (
+                    t.package_name.clone(),
+                    t.url.clone(),
+                    t.sha256.clone(),
+                    t.size,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lockfile_round_trips_through_toml() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(DEFAULT_LOCKFILE_NAME);
+        let original = plan();
+
+        Lockfile::from_plan(&original).write(&path).unwrap();
+        let replayed = Lockfile::read(&path)
+            .unwrap()
+            .into_plan(Some("x86_64"))
+            .unwrap();
+
+        assert_eq!(replayed.version, original.version);
+        assert_eq!(replayed.cudnn_version.as_deref(), Some("9.1.0"));
+        assert_eq!(pinned(&replayed), pinned(&original));
+        assert_eq!(
+            replayed.cudnn_task.map(|t| t.package_name).as_deref(),
+            Some("cudnn")
+        );
+    }
+
+    #[test]
+    fn lockfile_refuses_another_platform() {
+        let error = Lockfile::from_plan(&plan())
+            .into_plan(Some("aarch64"))
+            .unwrap_err();
+
+        assert!(
+            error.to_string().contains("created for linux-x86_64"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn freeze_file_round_trips_every_version() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(DEFAULT_FREEZE_FILE_NAME);
+        let mut older = plan();
+        older.version = CudaVersion::new("11.8.0").unwrap();
+        older.cudnn_version = None;
+        older.cudnn_task = None;
+
+        FreezeFile {
+            versions: vec![Lockfile::from_plan(&older), Lockfile::from_plan(&plan())],
+        }
+        .write(&path)
+        .unwrap();
+        let plans: Vec<InstallPlan> = FreezeFile::read(&path)
+            .unwrap()
+            .versions
+            .into_iter()
+            .map(|lockfile| lockfile.into_plan(Some("x86_64")).unwrap())
+            .collect();
+
+        assert_eq!(plans.len(), 2);
+        assert_eq!(plans[0].version, older.version);
+        assert_eq!(plans[0].cudnn_version, None);
+        assert_eq!(pinned(&plans[0]), pinned(&older));
+        assert_eq!(plans[1].version, plan().version);
+        assert_eq!(pinned(&plans[1]), pinned(&plan()));
+    }
+}
@@ -0,0 +1,279 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use crate::cuda::version::CudaVersion;
+
+use super::download::DownloadTask;
+use super::extract::ExtractTracker;
+use super::installer::InstallPlan;
+use super::lockfile::{DEFAULT_LOCKFILE_NAME, LockedPackage};
+use super::verify::sha256_file;
+
+pub const MANIFEST_FILE_NAME: &str = ".cudup-manifest.json";
+
+/// Record of what was installed into a version directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstallManifest {
+    pub cuda_version: String,
+    pub platform: String,
+    #[serde(default)]
+    pub cudnn_version: Option<String>,
+    /// How the cuDNN was paired with this CUDA version, e.g. "selected for CUDA 12 via
+    /// cuda12 variant".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cudnn_selection: Option<String>,
+    /// Version the toolkit reports in its `version.json` or `version.txt`, when it ships one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub toolkit_version: Option<String>,
+    #[serde(default)]
+    pub packages: Vec<LockedPackage>,
+    /// SHA256 of every extracted file, keyed by path relative to the install directory.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub files: BTreeMap<String, String>,
+    /// Paths each package extracted, so `install --verify-existing` can tell which
+    /// packages lost files.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub package_files: BTreeMap<String, Vec<String>>,
+    /// Set by `install --manifest-only`: the plan is recorded but nothing is downloaded yet.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pending: bool,
+    /// Packages skipped by `install --continue-on-error` after failing; the install is
+    /// incomplete while any are listed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failed_packages: Vec<String>,
+}
+
+pub fn manifest_path(install_dir: &Path) -> PathBuf {
+    install_dir.join(MANIFEST_FILE_NAME)
+}
+
+/// Regular files under `dir` as paths relative to `root`, skipping symlinks and the files
+/// cudup itself writes there (the manifest and lockfile).
+fn installed_files(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let path = entry.path();
+
+        if file_type.is_dir() {
+            installed_files(root, &path, files)?;
+        } else if file_type.is_file()
+            && entry.file_name() != MANIFEST_FILE_NAME
+            && path != root.join(DEFAULT_LOCKFILE_NAME)
+        {
+            files.push(path.strip_prefix(root)?.to_path_buf());
+        }
+    }
+
+    Ok(())
+}
+
+impl InstallManifest {
+    pub fn new(cuda_version: impl Into<String>, platform: impl Into<String>) -> Self {
+        Self {
+            cuda_version: cuda_version.into(),
+            platform: platform.into(),
+            cudnn_version: None,
+            cudnn_selection: None,
+            toolkit_version: None,
+            packages: Vec::new(),
+            files: BTreeMap::new(),
+            package_files: BTreeMap::new(),
+            pending: false,
+            failed_packages: Vec::new(),
+        }
+    }
+
+    pub fn from_plan(plan: &InstallPlan) -> Self {
+        Self {
+            cuda_version: plan.version.to_string(),
+            platform: plan.platform.clone(),
+            cudnn_version: plan.cudnn_version.clone(),
+            cudnn_selection: plan.cudnn_selection.clone(),
+            toolkit_version: None,
+            packages: plan.tasks().map(LockedPackage::from).collect(),
+            files: BTreeMap::new(),
+            package_files: BTreeMap::new(),
+            pending: false,
+            failed_packages: Vec::new(),
+        }
+    }
+
+    /// Rebuilds the install plan recorded in the manifest.
+    pub fn into_plan(self) -> Result<InstallPlan> {
+        let version = CudaVersion::new(self.cuda_version)?;
+        let (cudnn, cuda): (Vec<_>, Vec<_>) = self
+            .packages
+            .into_iter()
+            .map(DownloadTask::from)
+            .partition(|task| task.package_name == "cudnn");
+
+        Ok(InstallPlan {
+            version,
+            platform: self.platform,
+            cuda_tasks: cuda,
+            cudnn_version: self.cudnn_version,
+            cudnn_selection: self.cudnn_selection,
+            cudnn_task: cudnn.into_iter().next(),
+        })
+    }
+
+    /// Moves `failed` packages out of the installed list and records them as failed.
+    pub fn mark_failed(&mut self, failed: &[String]) {
+        self.packages.retain(|p| !failed.contains(&p.name));
+        if failed.iter().any(|name| name == "cudnn") {
+            self.cudnn_version = None;
+            self.cudnn_selection = None;
+        }
+        self.failed_packages = failed.to_vec();
+    }
+
+    pub fn is_incomplete(&self) -> bool {
+        !self.failed_packages.is_empty()
+    }
+
+    /// Records `task` as the installed cuDNN, replacing any previous cuDNN entry.
+    pub fn set_cudnn(&mut self, cudnn_version: &str, selection: &str, task: &DownloadTask) {
+        self.cudnn_version = Some(cudnn_version.to_string());
+        self.cudnn_selection = Some(selection.to_string());
+        self.packages.retain(|p| p.name != task.package_name);
+        self.packages.push(LockedPackage::from(task));
+    }
+
+    /// Adds the paths each package extracted in this run to the recorded ones.
+    pub fn record_package_files(&mut self, tracker: &ExtractTracker) {
+        for (package, files) in tracker.files_by_package() {
+            let recorded = self.package_files.entry(package).or_default();
+            recorded.extend(files);
+            recorded.sort();
+            recorded.dedup();
+        }
+    }
+
+    /// Installed packages with at least one recorded path missing from `install_dir`.
+    pub fn missing_packages(&self, install_dir: &Path) -> Vec<LockedPackage> {
+        self.packages
+            .iter()
+            .filter(|package| {
+                self.package_files.get(&package.name).is_some_and(|files| {
+                    files
+                        .iter()
+                        .any(|file| install_dir.join(file).symlink_metadata().is_err())
+                })
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Hashes every file currently in `install_dir`, replacing any previous record.
+    pub async fn record_files(&mut self, install_dir: &Path) -> Result<()> {
+        let mut files = Vec::new();
+        installed_files(install_dir, install_dir, &mut files)?;
+
+        self.files.clear();
+        for file in files {
+            let hash = sha256_file(&install_dir.join(&file)).await?;
+            self.files.insert(file.to_string_lossy().into_owned(), hash);
+        }
+
+        Ok(())
+    }
+
+    /// Loads the manifest from `install_dir`, or `None` for installs that predate manifests.
+    pub fn load(install_dir: &Path) -> Result<Option<Self>> {
+        let path = manifest_path(install_dir);
+        match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map(Some)
+                .with_context(|| format!("Failed to parse {}", path.display())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    pub fn save(&self, install_dir: &Path) -> Result<()> {
+        let path = manifest_path(install_dir);
+        let contents = serde_json::to_string_pretty(self)?;
+        fs::write(&path, contents).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn package(name: &str) -> LockedPackage {
+        LockedPackage {
+            name: name.to_string(),
+            url: format!("https://mirror.example.com/{name}.tar.xz"),
+            sha256: "a".repeat(64),
+            size: Some(1024),
+            relative_path: format!("{name}.tar.xz"),
+        }
+    }
+
+    fn manifest() -> InstallManifest {
+        let mut manifest = InstallManifest::new("12.4.1", "linux-x86_64");
+        manifest.packages = vec![package("cuda_cudart"), package("cuda_nvcc")];
+        manifest
+    }
+
+    #[test]
+    fn mark_failed_moves_packages_to_the_failed_list() {
+        let mut manifest = manifest();
+        assert!(!manifest.is_incomplete());
+
+        manifest.mark_failed(&["cuda_nvcc".to_string()]);
+
+        assert!(manifest.is_incomplete());
+        assert_eq!(manifest.failed_packages, ["cuda_nvcc"]);
+        let installed: Vec<&str> = manifest.packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(installed, ["cuda_cudart"]);
+    }
+
+    #[test]
+    fn failed_cudnn_is_no_longer_recorded_as_installed() {
+        let mut manifest = manifest();
+        manifest.set_cudnn(
+            "9.1.0",
+            "selected for CUDA 12",
+            &DownloadTask::from(package("cudnn")),
+        );
+
+        manifest.mark_failed(&["cudnn".to_string()]);
+
+        assert_eq!(manifest.cudnn_version, None);
+        assert_eq!(manifest.cudnn_selection, None);
+        assert_eq!(manifest.packages.len(), 2);
+    }
+
+    #[test]
+    fn pending_and_failed_state_survive_a_save() {
+        let dir = TempDir::new().unwrap();
+        let mut manifest = manifest();
+        manifest.pending = true;
+        manifest.mark_failed(&["cuda_nvcc".to_string()]);
+        manifest.save(dir.path()).unwrap();
+
+        let loaded = InstallManifest::load(dir.path()).unwrap().unwrap();
+        assert!(loaded.pending);
+        assert_eq!(loaded.failed_packages, ["cuda_nvcc"]);
+    }
+
+    #[test]
+    fn complete_installs_omit_pending_and_failed_fields() {
+        let dir = TempDir::new().unwrap();
+        manifest().save(dir.path()).unwrap();
+
+        let contents = fs::read_to_string(manifest_path(dir.path())).unwrap();
+        assert!(!contents.contains("pending"), "{contents}");
+        assert!(!contents.contains("failed_packages"), "{contents}");
+        let loaded = InstallManifest::load(dir.path()).unwrap().unwrap();
+        assert!(!loaded.pending && !loaded.is_incomplete());
+    }
+}
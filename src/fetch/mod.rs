@@ -1,9 +1,27 @@
 mod download;
 mod extract;
+mod filesystem;
+pub mod http;
 mod installer;
+mod introspect;
+mod lockfile;
+mod manifest;
+mod progress;
+mod tar;
 mod tasks;
 mod utils;
 mod verify;
 
-pub use installer::install_cuda_version;
-pub use utils::{format_size, version_install_dir};
+pub use filesystem::filesystem_warnings;
+pub use installer::{
+    DEFAULT_CONCURRENCY_PER_HOST, DEFAULT_IDLE_TIMEOUT_SECS, InstallOptions, InstallPlan,
+    default_jobs, execute_install_plan, install_cuda_version, install_cudnn_only, install_dir_for,
+    repair_install, resolve_install_plan, resume_install, stage_install,
+};
+pub use introspect::{same_release, toolkit_version};
+pub use lockfile::{DEFAULT_FREEZE_FILE_NAME, DEFAULT_LOCKFILE_NAME, FreezeFile, Lockfile};
+pub use manifest::InstallManifest;
+pub use progress::ProgressMode;
+pub use tasks::Components;
+pub use utils::{format_size, target_platform, version_install_dir};
+pub use verify::{is_kept_archive, sha256_file};
@@ -0,0 +1,136 @@
+use anyhow::{Result, bail};
+use indicatif::ProgressBar;
+use log::info;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::utils::format_size;
+
+/// How often plain mode logs the overall download percentage.
+const PLAIN_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How install progress is rendered, chosen with `install --progress`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProgressMode {
+    /// Progress bars when stderr is a terminal, nothing otherwise.
+    #[default]
+    Auto,
+    /// No bars: a log line per package and a periodic overall percentage, for build logs.
+    Plain,
+}
+
+impl FromStr for ProgressMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(ProgressMode::Auto),
+            "plain" => Ok(ProgressMode::Plain),
+            _ => bail!("Unknown progress mode: {}. Modes: auto, plain.", s),
+        }
+    }
+}
+
+/// Overall download progress for plain mode, summed from the (hidden) per-package bars.
+pub struct PlainProgress {
+    total: u64,
+    bars: Mutex<HashMap<String, ProgressBar>>,
+}
+
+impl PlainProgress {
+    pub fn new(total: u64) -> Self {
+        Self {
+            total,
+            bars: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Counts `bar` as the download of `package`, replacing the bar of an earlier attempt.
+    pub fn track(&self, package: &str, bar: &ProgressBar) {
+        self.bars
+            .lock()
+            .expect("progress lock poisoned")
+            .insert(package.to_string(), bar.clone());
+    }
+
+    fn downloaded(&self) -> u64 {
+        self.bars
+            .lock()
+            .expect("progress lock poisoned")
+            .values()
+            .map(ProgressBar::position)
+            .sum()
+    }
+
+    fn report(&self) {
+        if let Some(line) = plain_progress_line(self.downloaded(), self.total) {
+            info!("{}", line);
+        }
+    }
+
+    /// Logs the overall percentage every few seconds until dropped.
+    pub async fn report_periodically(&self) {
+        let mut interval = tokio::time::interval(PLAIN_REPORT_INTERVAL);
+        // The first tick completes immediately, before anything is downloaded.
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            self.report();
+        }
+    }
+}
+
+/// Formats the overall progress as `"42% (1.00 GB/2.38 GB)"`, or `None` when nothing
+/// with a known size is being downloaded.
+fn plain_progress_line(downloaded: u64, total: u64) -> Option<String> {
+    if total == 0 {
+        return None;
+    }
+    let downloaded = downloaded.min(total);
+    Some(format!(
+        "{}% ({}/{})",
+        downloaded * 100 / total,
+        format_size(downloaded),
+        format_size(total)
+    ))
+}
+
+/// Runs `future`, logging the overall percentage alongside it in plain mode.
+pub async fn with_reports<T>(plain: Option<&PlainProgress>, future: impl Future<Output = T>) -> T {
+    match plain {
+        Some(plain) => tokio::select! {
+            output = future => output,
+            _ = plain.report_periodically() => unreachable!("reports never finish"),
+        },
+        None => future.await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_progress_line_reports_percentage_and_sizes() {
+        const MB: u64 = 1024 * 1024;
+        assert_eq!(
+            plain_progress_line(512 * MB, 2048 * MB).as_deref(),
+            Some("25% (512.00 MB/2.00 GB)")
+        );
+        assert_eq!(
+            plain_progress_line(0, 1536).as_deref(),
+            Some("0% (0 B/1.50 KB)")
+        );
+    }
+
+    #[test]
+    fn plain_progress_line_is_capped_and_skipped_without_sizes() {
+        assert_eq!(
+            plain_progress_line(4096, 1024).as_deref(),
+            Some("100% (1.00 KB/1.00 KB)")
+        );
+        assert_eq!(plain_progress_line(4096, 0), None);
+    }
+}
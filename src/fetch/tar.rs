@@ -0,0 +1,157 @@
+use anyhow::{Result, bail};
+use log::debug;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::LazyLock;
+
+/// Which `tar` implementation is on `PATH`; their flags and verbose output differ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TarFlavor {
+    Gnu,
+    Bsd,
+    Busybox,
+    Unknown,
+}
+
+/// What the `tar` on `PATH` can do, probed once per process.
+#[derive(Debug, Clone)]
+pub struct TarCapabilities {
+    flavor: TarFlavor,
+    strip_components: bool,
+    xz: bool,
+}
+
+static TAR: LazyLock<Option<TarCapabilities>> = LazyLock::new(probe);
+
+/// Runs `command --version`, returning its stdout and stderr combined, even when it
+/// exits with an error (busybox prints its usage to stderr instead).
+fn version_output(command: &str) -> Option<String> {
+    let output = Command::new(command)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .output()
+        .inspect_err(|e| debug!("Failed to run {} --version: {}", command, e))
+        .ok()?;
+
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    Some(text)
+}
+
+fn probe() -> Option<TarCapabilities> {
+    let version = version_output("tar")?;
+    let capabilities = parse_capabilities(&version, version_output("xz").is_some());
+    debug!("Detected tar: {:?}", capabilities);
+
+    Some(capabilities)
+}
+
+/// Works out what a `tar` can do from its `--version` output and whether an `xz` binary
+/// is on `PATH`.
+fn parse_capabilities(version: &str, xz_binary: bool) -> TarCapabilities {
+    let flavor = if version.contains("GNU tar") {
+        TarFlavor::Gnu
+    } else if version.contains("bsdtar") || version.contains("libarchive") {
+        TarFlavor::Bsd
+    } else if version.contains("BusyBox") {
+        TarFlavor::Busybox
+    } else {
+        TarFlavor::Unknown
+    };
+
+    match flavor {
+        // GNU tar shells out to `xz` for .xz archives.
+        TarFlavor::Gnu => TarCapabilities {
+            flavor,
+            strip_components: true,
+            xz: xz_binary,
+        },
+        // libarchive decompresses xz itself.
+        TarFlavor::Bsd | TarFlavor::Unknown => TarCapabilities {
+            flavor,
+            strip_components: true,
+            xz: true,
+        },
+        // Both are compile-time options; its usage text lists the ones built in.
+        TarFlavor::Busybox => TarCapabilities {
+            flavor,
+            strip_components: version.contains("strip-components"),
+            xz: xz_binary || version.contains("xz"),
+        },
+    }
+}
+
+/// Returns the probed `tar` capabilities, failing with an actionable message when there's
+/// no `tar` or it can't decompress `archive_path`.
+pub fn capabilities_for(archive_path: &Path) -> Result<&'static TarCapabilities> {
+    let Some(tar) = TAR.as_ref() else {
+        bail!("tar was not found on PATH; install GNU tar to extract CUDA packages");
+    };
+
+    if archive_path.extension().is_some_and(|ext| ext == "xz") && !tar.xz {
+        bail!(
+            "tar can't decompress {}: install xz-utils (or GNU tar with xz support)",
+            archive_path.display()
+        );
+    }
+
+    Ok(tar)
+}
+
+impl TarCapabilities {
+    pub fn ensure_strip_components(&self) -> Result<()> {
+        if !self.strip_components {
+            bail!("The tar on PATH doesn't support --strip-components; install GNU tar");
+        }
+        Ok(())
+    }
+
+    /// Whether `tar -v` lists extracted entries on stdout; bsdtar writes them to stderr.
+    pub fn verbose_on_stdout(&self) -> bool {
+        self.flavor != TarFlavor::Bsd
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GNU: &str = "tar (GNU tar) 1.35\nCopyright (C) 2023 Free Software Foundation, Inc.\n";
+    const BSDTAR: &str = "bsdtar 3.7.2 - libarchive 3.7.2 zlib/1.2.12 liblzma/5.4.3 bz2lib/1.0.8\n";
+    const BUSYBOX_FULL: &str = "BusyBox v1.36.1 (2023-11-07 18:53:09 UTC) multi-call binary.\n\n\
+        Usage: tar c|x|t [-ZzJjahmvokO] [-f TARFILE] [-C DIR] [-T FILE] [-X FILE] \
+        [LONGOPT]... [FILE]...\n\n\
+        \t-J\t(De)compress using xz\n\
+        \t--strip-components NUM\tStrip NUM leading components from file names\n";
+    const BUSYBOX_MINIMAL: &str = "BusyBox v1.36.1 (2023-11-07 18:53:09 UTC) multi-call binary.\n\n\
+        Usage: tar c|x|t [-zvokO] [-f TARFILE] [-C DIR] [FILE]...\n";
+
+    #[test]
+    fn gnu_tar_needs_an_xz_binary() {
+        let tar = parse_capabilities(GNU, true);
+        assert_eq!(tar.flavor, TarFlavor::Gnu);
+        assert!(tar.strip_components && tar.xz && tar.verbose_on_stdout());
+
+        assert!(!parse_capabilities(GNU, false).xz);
+    }
+
+    #[test]
+    fn bsdtar_decompresses_xz_itself() {
+        let tar = parse_capabilities(BSDTAR, false);
+        assert_eq!(tar.flavor, TarFlavor::Bsd);
+        assert!(tar.strip_components && tar.xz);
+        assert!(!tar.verbose_on_stdout());
+    }
+
+    #[test]
+    fn busybox_features_come_from_its_usage_text() {
+        let full = parse_capabilities(BUSYBOX_FULL, false);
+        assert_eq!(full.flavor, TarFlavor::Busybox);
+        assert!(full.strip_components && full.xz);
+
+        let minimal = parse_capabilities(BUSYBOX_MINIMAL, false);
+        assert!(!minimal.strip_components && !minimal.xz);
+        assert!(minimal.ensure_strip_components().is_err());
+        assert!(parse_capabilities(BUSYBOX_MINIMAL, true).xz);
+    }
+}
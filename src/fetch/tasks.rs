@@ -1,9 +1,13 @@
 use std::cmp::Reverse;
+use std::str::FromStr;
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 
-use crate::cuda::discover::{CUDA_BASE_URL, CUDNN_BASE_URL, find_newest_compatible_cudnn};
+use crate::cuda::discover::{
+    fetch_available_cudnn_versions, fetch_cudnn_version_metadata, find_newest_compatible_cudnn,
+};
 use crate::cuda::metadata::{CudaReleaseMetadata, PlatformInfo};
+use crate::cuda::source::Source;
 use crate::cuda::version::CudaVersion;
 
 use super::download::DownloadTask;
@@ -15,25 +19,142 @@ fn parse_size(size_str: &str, package_name: &str) -> Option<u64> {
         .ok()
 }
 
-pub async fn find_compatible_cudnn(cuda_version: &CudaVersion) -> Result<Option<(String, String)>> {
+/// Outcome of looking up a cuDNN release for a CUDA version.
+pub enum CudnnLookup {
+    Compatible {
+        cudnn_version: String,
+        cuda_variant: String,
+    },
+    /// cuDNN is published, but not for this CUDA major version.
+    NoMatchingVariant {
+        newest_version: String,
+        available_variants: Vec<String>,
+    },
+    /// No cuDNN releases were found at all.
+    Unavailable,
+}
+
+/// CUDA variants (e.g. `cuda12`) a cuDNN release is published for.
+pub fn cudnn_variants(metadata: &CudaReleaseMetadata) -> Vec<String> {
+    metadata
+        .get_package("cudnn")
+        .and_then(|pkg| pkg.cuda_variant.as_ref())
+        .map(|variants| variants.iter().map(|v| format!("cuda{}", v)).collect())
+        .unwrap_or_default()
+}
+
+fn variant_major(variant: &str) -> Option<u32> {
+    variant.strip_prefix("cuda")?.parse().ok()
+}
+
+/// The closest variant built for an older CUDA major than `cuda_variant`, e.g. `cuda12`
+/// for `cuda13` when no `cuda13` build exists.
+fn fallback_variant<'a>(
+    cuda_variant: &str,
+    available: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let requested = variant_major(cuda_variant)?;
+    available
+        .into_iter()
+        .filter_map(|variant| Some((variant_major(variant)?, variant)))
+        .filter(|(major, _)| *major < requested)
+        .max_by_key(|(major, _)| *major)
+        .map(|(_, variant)| variant)
+}
+
+pub async fn find_compatible_cudnn(cuda_version: &CudaVersion) -> Result<CudnnLookup> {
     if let Some(cudnn_version) = find_newest_compatible_cudnn(cuda_version.as_str()).await? {
         let cuda_variant = format!("cuda{}", cuda_version.major());
-        return Ok(Some((cudnn_version, cuda_variant)));
+        return Ok(CudnnLookup::Compatible {
+            cudnn_version,
+            cuda_variant,
+        });
     }
 
-    Ok(None)
+    let Some(newest_version) = fetch_available_cudnn_versions().await?.pop_last() else {
+        return Ok(CudnnLookup::Unavailable);
+    };
+    let metadata = fetch_cudnn_version_metadata(&newest_version).await?;
+    let available_variants = cudnn_variants(&metadata);
+
+    let requested = format!("cuda{}", cuda_version.major());
+    if let Some(fallback) =
+        fallback_variant(&requested, available_variants.iter().map(String::as_str))
+    {
+        log::warn!(
+            "No cuDNN is built for {}; falling back to cuDNN {} built for {}",
+            requested,
+            newest_version,
+            fallback
+        );
+        return Ok(CudnnLookup::Compatible {
+            cuda_variant: fallback.to_string(),
+            cudnn_version: newest_version,
+        });
+    }
+
+    Ok(CudnnLookup::NoMatchingVariant {
+        available_variants,
+        newest_version,
+    })
+}
+
+/// Named sets of CUDA packages for `install --components`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Components {
+    /// Runtime, compiler and runtime compilation library.
+    Minimal,
+    /// What's needed to run CUDA programs, without the compiler.
+    Runtime,
+    /// Every package in the release.
+    #[default]
+    Full,
+}
+
+impl Components {
+    /// Package names in the preset, or `None` for every package.
+    pub fn packages(&self) -> Option<&'static [&'static str]> {
+        match self {
+            Components::Minimal => Some(&["cuda_cudart", "cuda_nvcc", "cuda_nvrtc"]),
+            Components::Runtime => Some(&["cuda_cudart", "cuda_nvrtc"]),
+            Components::Full => None,
+        }
+    }
+
+    fn includes(&self, package_name: &str) -> bool {
+        self.packages()
+            .is_none_or(|packages| packages.contains(&package_name))
+    }
+}
+
+impl FromStr for Components {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "minimal" => Ok(Components::Minimal),
+            "runtime" => Ok(Components::Runtime),
+            "full" => Ok(Components::Full),
+            _ => bail!(
+                "Unknown component preset: {}. Presets: minimal, runtime, full.",
+                s
+            ),
+        }
+    }
 }
 
 pub fn collect_cuda_download_tasks(
+    source: &dyn Source,
     metadata: &CudaReleaseMetadata,
     cuda_version: &CudaVersion,
     platform: &str,
+    components: Components,
 ) -> Vec<DownloadTask> {
     let mut tasks = Vec::with_capacity(metadata.packages.len());
     let variant_key = format!("cuda{}", cuda_version.major());
 
     for (package_name, package_info) in &metadata.packages {
-        if package_name.starts_with("release_") {
+        if package_name.starts_with("release_") || !components.includes(package_name) {
             continue;
         }
 
@@ -49,7 +170,7 @@ pub fn collect_cuda_download_tasks(
             },
         };
 
-        let url = format!("{}/{}", CUDA_BASE_URL, download_info.relative_path);
+        let url = source.package_url(&download_info.relative_path);
         let size = parse_size(&download_info.size, package_name);
 
         tasks.push(DownloadTask {
@@ -61,6 +182,17 @@ pub fn collect_cuda_download_tasks(
         });
     }
 
+    for name in components.packages().unwrap_or_default() {
+        if !tasks.iter().any(|task| task.package_name == *name) {
+            log::warn!(
+                "CUDA {} has no {} package for {}; installing the rest of the preset",
+                cuda_version,
+                name,
+                platform
+            );
+        }
+    }
+
     // Sort by size descending, with unknown sizes (None) at the end
     tasks.sort_unstable_by_key(|t| Reverse(t.size));
 
@@ -68,6 +200,7 @@ pub fn collect_cuda_download_tasks(
 }
 
 pub fn collect_cudnn_download_task(
+    source: &dyn Source,
     metadata: &CudaReleaseMetadata,
     cuda_variant: &str,
     platform: &str,
@@ -77,10 +210,22 @@ pub fn collect_cudnn_download_task(
 
     let download_info = match platform_info {
         PlatformInfo::Simple(info) => info,
-        PlatformInfo::Variants(variants) => variants.get(cuda_variant)?,
+        PlatformInfo::Variants(variants) => match variants.get(cuda_variant) {
+            Some(info) => info,
+            None => {
+                let fallback = fallback_variant(cuda_variant, variants.keys().map(String::as_str))?;
+                log::warn!(
+                    "cuDNN has no {} build for {}; using the {} build instead",
+                    cuda_variant,
+                    platform,
+                    fallback
+                );
+                &variants[fallback]
+            }
+        },
     };
 
-    let url = format!("{}/{}", CUDNN_BASE_URL, download_info.relative_path);
+    let url = source.package_url(&download_info.relative_path);
     let size = parse_size(&download_info.size, "cudnn");
 
     Some(DownloadTask {
@@ -91,3 +236,119 @@ pub fn collect_cudnn_download_task(
         relative_path: download_info.relative_path.clone(),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cuda::source::{FakeSource, MirrorSource};
+
+    /// cuDNN metadata with a `linux-x86_64` build for each of `variants`.
+    fn cudnn_metadata(variants: &[&str]) -> CudaReleaseMetadata {
+        let builds: serde_json::Map<String, serde_json::Value> = variants
+            .iter()
+            .map(|variant| {
+                let build = serde_json::json!({
+                    "relative_path": format!("cudnn/linux-x86_64/cudnn-9.1.0_{variant}-archive.tar.xz"),
+                    "sha256": "abc",
+                    "md5": "",
+                    "size": "1024",
+                });
+                (variant.to_string(), build)
+            })
+            .collect();
+        let metadata = serde_json::json!({
+            "release_date": "2024-04-01",
+            "cudnn": {
+                "name": "cuDNN",
+                "license": "cudnn",
+                "version": "9.1.0",
+                "linux-x86_64": builds,
+            },
+        });
+        serde_json::from_value(metadata).unwrap()
+    }
+
+    fn relative_path(metadata: &CudaReleaseMetadata, cuda_variant: &str) -> Option<String> {
+        let source = MirrorSource::new("https://mirror.example/cudnn");
+        collect_cudnn_download_task(&source, metadata, cuda_variant, "linux-x86_64")
+            .map(|task| task.relative_path)
+    }
+
+    #[test]
+    fn exact_variant_is_preferred() {
+        let metadata = cudnn_metadata(&["cuda11", "cuda12", "cuda13"]);
+        assert_eq!(
+            relative_path(&metadata, "cuda12").as_deref(),
+            Some("cudnn/linux-x86_64/cudnn-9.1.0_cuda12-archive.tar.xz")
+        );
+    }
+
+    #[test]
+    fn missing_variant_falls_back_to_the_nearest_lower_major() {
+        let metadata = cudnn_metadata(&["cuda11", "cuda12"]);
+        assert_eq!(
+            relative_path(&metadata, "cuda13").as_deref(),
+            Some("cudnn/linux-x86_64/cudnn-9.1.0_cuda12-archive.tar.xz")
+        );
+    }
+
+    #[test]
+    fn only_newer_variants_give_no_task() {
+        let metadata = cudnn_metadata(&["cuda13"]);
+        assert_eq!(relative_path(&metadata, "cuda12"), None);
+    }
+
+    #[test]
+    fn fallback_ignores_unparseable_variants() {
+        assert_eq!(
+            fallback_variant("cuda13", ["cuda11", "cudaX", "cuda12", "cpu"]),
+            Some("cuda12")
+        );
+        assert_eq!(fallback_variant("cudaX", ["cuda12"]), None);
+    }
+
+    #[test]
+    fn cuda_task_urls_come_from_the_source() {
+        let metadata: CudaReleaseMetadata = serde_json::from_value(serde_json::json!({
+            "release_date": "2024-04-01",
+            "cuda_nvcc": {
+                "name": "CUDA NVCC",
+                "license": "CUDA Toolkit",
+                "version": "12.4.131",
+                "linux-x86_64": {
+                    "relative_path": "cuda_nvcc/linux-x86_64/nvcc.tar.xz",
+                    "sha256": "abc",
+                    "md5": "",
+                    "size": "2048",
+                },
+            },
+        }))
+        .unwrap();
+        let version = CudaVersion::new("12.4.1").unwrap();
+
+        let tasks = collect_cuda_download_tasks(
+            &FakeSource,
+            &metadata,
+            &version,
+            "linux-x86_64",
+            Components::Full,
+        );
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(
+            tasks[0].url,
+            "fake://redist/package?path=cuda_nvcc/linux-x86_64/nvcc.tar.xz"
+        );
+        assert_eq!(tasks[0].size, Some(2048));
+    }
+
+    #[test]
+    fn cudnn_task_url_comes_from_the_source() {
+        let metadata = cudnn_metadata(&["cuda12"]);
+        let task =
+            collect_cudnn_download_task(&FakeSource, &metadata, "cuda12", "linux-x86_64").unwrap();
+        assert_eq!(
+            task.url,
+            "fake://redist/package?path=cudnn/linux-x86_64/cudnn-9.1.0_cuda12-archive.tar.xz"
+        );
+    }
+}
@@ -1,25 +1,37 @@
-use anyhow::{Result, bail};
+use anyhow::Result;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use crate::config;
+use crate::error::CudupError;
 
-pub fn target_platform() -> Result<&'static str> {
-    match (std::env::consts::OS, std::env::consts::ARCH) {
+fn platform_for(os: &str, arch: &str) -> Result<&'static str, CudupError> {
+    match (os, arch) {
         ("linux", "x86_64") => Ok("linux-x86_64"),
         ("linux", "aarch64") => Ok("linux-sbsa"),
-        (os, arch) => bail!(
-            "Unsupported platform: {}-{}. \
-             cudup supports linux-x86_64 and linux-sbsa (ARM64 server).",
-            os,
-            arch
-        ),
+        (os, arch) => Err(CudupError::PlatformUnsupported {
+            os: os.to_string(),
+            arch: arch.to_string(),
+        }),
     }
 }
 
+/// Platform key to install for: `arch` if given, else the `install.default_arch` config
+/// override, else the host architecture.
+pub fn target_platform(arch: Option<&str>) -> Result<&'static str, CudupError> {
+    let arch = arch
+        .or(config::get().install.default_arch.as_deref())
+        .unwrap_or(std::env::consts::ARCH);
+
+    platform_for(std::env::consts::OS, arch)
+}
+
 pub fn version_install_dir(cuda_version: &str) -> Result<PathBuf> {
     Ok(config::versions_dir()?.join(cuda_version))
 }
 
+/// Formats a byte count with 1024-based units and two decimals. Every command that
+/// prints a size goes through this so install, list and uninstall output agree.
 pub fn format_size(bytes: u64) -> String {
     const KB: u64 = 1024;
     const MB: u64 = KB * 1024;
@@ -35,3 +47,34 @@ pub fn format_size(bytes: u64) -> String {
         format!("{bytes} B")
     }
 }
+
+/// Formats an average transfer rate, e.g. "12.34 MB/s".
+pub fn format_rate(bytes: u64, elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs_f64().max(0.001);
+    format!("{}/s", format_size((bytes as f64 / seconds) as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_size_uses_binary_units_with_two_decimals() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(3 * 1024), "3.00 KB");
+        assert_eq!(format_size(1536 * 1024), "1.50 MB");
+        assert_eq!(format_size(5 * 1024 * 1024 * 1024), "5.00 GB");
+    }
+
+    #[test]
+    fn unsupported_platforms_are_a_typed_error() {
+        assert_eq!(platform_for("linux", "x86_64").unwrap(), "linux-x86_64");
+        assert_eq!(platform_for("linux", "aarch64").unwrap(), "linux-sbsa");
+
+        let error = platform_for("macos", "aarch64").unwrap_err();
+        assert!(
+            matches!(&error, CudupError::PlatformUnsupported { os, arch } if os == "macos" && arch == "aarch64"),
+            "{error}"
+        );
+    }
+}
@@ -1,17 +1,39 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
 use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
 
+mod cache;
 mod commands;
 mod config;
 mod cuda;
+mod error;
 mod fetch;
+mod output;
 
 use cuda::CudaVersion;
+use cuda::metadata::ReleaseDate;
 
 #[derive(Parser)]
 #[command(name = "cudup", author, version, about, long_about = None)]
 struct Cli {
+    #[arg(long, global = true, help = "Don't read or write the metadata cache")]
+    no_cache: bool,
+    #[arg(
+        long,
+        global = true,
+        help = "Refetch version lists and metadata instead of using the cache",
+        conflicts_with = "no_cache"
+    )]
+    refresh: bool,
+    #[arg(
+        short,
+        long,
+        global = true,
+        help = "Only print errors and essential output (no status text or progress bars)"
+    )]
+    quiet: bool,
     #[command(subcommand)]
     command: Commands,
 }
@@ -20,11 +42,141 @@ struct Cli {
 enum Commands {
     Install {
         #[arg(
-            help = "CUDA version to install (e.g., 12.4.1)",
+            help = "CUDA versions to install (e.g., 12.4.1); prompts when omitted",
+            value_name = "VERSION",
+            value_parser = clap::value_parser!(CudaVersion)
+        )]
+        version: Vec<CudaVersion>,
+        #[arg(
+            long,
+            help = "Install the exact packages pinned in a lockfile",
+            value_name = "PATH",
+            conflicts_with = "version"
+        )]
+        from_lockfile: Option<PathBuf>,
+        #[command(flatten)]
+        download: DownloadArgs,
+        #[arg(
+            long,
+            help = "Metadata filename to use instead of redistrib_<VERSION>.json",
+            value_name = "FILE",
+            conflicts_with = "from_lockfile"
+        )]
+        redist_file: Option<String>,
+        #[arg(
+            long,
+            help = "Package preset to install: minimal (cudart, nvcc, nvrtc), runtime (cudart, nvrtc) or full",
+            value_name = "PRESET",
+            default_value = "full",
+            value_parser = clap::value_parser!(fetch::Components),
+            conflicts_with_all = ["from_lockfile", "only_cudnn"]
+        )]
+        components: fetch::Components,
+        #[arg(
+            long,
+            help = "Install CUDA without cuDNN",
+            conflicts_with = "only_cudnn"
+        )]
+        skip_cudnn: bool,
+        #[arg(
+            long,
+            help = "Add cuDNN to an already installed CUDA version",
+            conflicts_with = "from_lockfile"
+        )]
+        only_cudnn: bool,
+        #[arg(
+            long,
+            help = "cuDNN version to install instead of the newest compatible one",
+            value_name = "VERSION",
+            conflicts_with_all = ["skip_cudnn", "from_lockfile"]
+        )]
+        cudnn_version: Option<String>,
+        #[arg(
+            long,
+            help = "Install into this directory instead of cudup's versions directory (not managed by `cudup use`)",
+            value_name = "DIR",
+            conflicts_with_all = ["only_cudnn", "manifest_only", "resume"]
+        )]
+        dest: Option<PathBuf>,
+        #[arg(
+            long,
+            help = "Print the archive URLs that would be downloaded and exit",
+            conflicts_with = "only_cudnn"
+        )]
+        print_urls: bool,
+        #[arg(
+            long,
+            help = "Record the resolved packages in the version directory without downloading them",
+            requires = "version",
+            conflicts_with_all = ["only_cudnn", "print_urls"]
+        )]
+        manifest_only: bool,
+        #[arg(
+            long,
+            help = "Download a version staged with --manifest-only",
+            requires = "version",
+            conflicts_with_all = ["only_cudnn", "print_urls", "manifest_only"]
+        )]
+        resume: bool,
+        #[arg(
+            long,
+            help = "Append a tab-separated SHA256 to each printed URL",
+            requires = "print_urls"
+        )]
+        with_sha256: bool,
+        #[arg(
+            long,
+            help = "Re-download only the packages of an installed version that are missing files",
+            requires = "version",
+            conflicts_with_all = ["only_cudnn", "dest", "print_urls", "manifest_only", "resume"]
+        )]
+        verify_existing: bool,
+        #[arg(
+            long,
+            help = "Stop at the first version that fails when installing several",
+            requires = "version"
+        )]
+        fail_fast: bool,
+    },
+    Lock {
+        #[arg(
+            help = "CUDA version to lock (e.g., 12.4.1)",
             value_name = "VERSION",
             value_parser = clap::value_parser!(CudaVersion)
         )]
         version: CudaVersion,
+        #[arg(
+            short,
+            long,
+            help = "Lockfile to write",
+            value_name = "PATH",
+            default_value = fetch::DEFAULT_LOCKFILE_NAME
+        )]
+        output: PathBuf,
+        #[arg(
+            long,
+            help = "Architecture to resolve packages for (x86_64 or aarch64)",
+            value_name = "ARCH"
+        )]
+        arch: Option<String>,
+    },
+    Freeze {
+        #[arg(
+            short,
+            long,
+            help = "Freeze file to write",
+            value_name = "PATH",
+            default_value = fetch::DEFAULT_FREEZE_FILE_NAME
+        )]
+        output: PathBuf,
+    },
+    Restore {
+        #[arg(help = "Freeze file written by `cudup freeze`", value_name = "PATH")]
+        path: PathBuf,
+        #[arg(long, help = "Stop at the first version that fails to install")]
+        fail_fast: bool,
+        #[command(flatten)]
+        download: DownloadArgs,
     },
     Uninstall {
         #[arg(
@@ -38,17 +190,160 @@ enum Commands {
         force: bool,
         #[arg(short, long, help = "Uninstall all versions")]
         all: bool,
+        #[arg(
+            long,
+            help = "Show what would be removed without removing anything or prompting"
+        )]
+        dry_run: bool,
     },
-    List,
-    Check,
-    Use {
+    Verify {
         #[arg(
-            help = "CUDA version to activate (e.g., 12.4.1)",
+            help = "Installed CUDA version to verify (e.g., 12.4.1)",
             value_name = "VERSION",
             value_parser = clap::value_parser!(CudaVersion)
         )]
         version: CudaVersion,
     },
+    List {
+        #[arg(
+            long,
+            help = "Only print available versions, skipping the installed check"
+        )]
+        remote_only: bool,
+        #[arg(
+            long,
+            alias = "installed",
+            help = "Only list installed versions, marking the active one",
+            conflicts_with = "remote_only"
+        )]
+        installed_only: bool,
+        #[arg(long, help = "Print versions as JSON")]
+        json: bool,
+        #[arg(
+            long,
+            help = "Show each installed version's disk usage and the total",
+            requires = "installed_only"
+        )]
+        size: bool,
+        #[arg(
+            long,
+            help = "Only list versions released on or after this date (slower: fetches each version's metadata)",
+            value_name = "YYYY-MM-DD",
+            value_parser = clap::value_parser!(ReleaseDate),
+            conflicts_with = "installed_only"
+        )]
+        since: Option<ReleaseDate>,
+        #[arg(
+            long,
+            help = "Only list versions released on or before this date (slower: fetches each version's metadata)",
+            value_name = "YYYY-MM-DD",
+            value_parser = clap::value_parser!(ReleaseDate),
+            conflicts_with = "installed_only"
+        )]
+        until: Option<ReleaseDate>,
+        #[arg(
+            long,
+            help = "Only list versions of this CUDA major release (e.g., 12)",
+            value_name = "N",
+            conflicts_with = "installed_only"
+        )]
+        major: Option<u32>,
+        #[arg(
+            long,
+            help = "Only list the newest N versions",
+            value_name = "N",
+            value_parser = clap::value_parser!(u16).range(1..),
+            conflicts_with = "installed_only"
+        )]
+        limit: Option<u16>,
+    },
+    ListPackages {
+        #[arg(
+            help = "CUDA version to list packages for (e.g., 12.4.1)",
+            value_name = "VERSION",
+            value_parser = clap::value_parser!(CudaVersion)
+        )]
+        version: CudaVersion,
+        #[arg(
+            long,
+            help = "Architecture to resolve packages for (x86_64 or aarch64)",
+            value_name = "ARCH"
+        )]
+        arch: Option<String>,
+    },
+    Info {
+        #[arg(
+            help = "CUDA version to inspect (e.g., 12.4.1)",
+            value_name = "VERSION",
+            value_parser = clap::value_parser!(CudaVersion)
+        )]
+        version: CudaVersion,
+        #[arg(
+            long,
+            help = "List every package and the platforms it is published for"
+        )]
+        list_packages: bool,
+        #[arg(
+            long,
+            help = "Show a matrix of every package's archive size on each platform",
+            conflicts_with = "list_packages"
+        )]
+        all_platforms: bool,
+    },
+    Check {
+        #[arg(long, help = "Probe connectivity to the NVIDIA redist servers")]
+        network: bool,
+        #[arg(
+            long,
+            help = "Connect timeout in seconds for network probes",
+            value_name = "SECS",
+            default_value_t = 10
+        )]
+        timeout_connect: u64,
+        #[arg(long, help = "Print results as JSON")]
+        json: bool,
+        #[arg(long, help = "Also exit non-zero when a check reports a warning")]
+        strict: bool,
+    },
+    Use {
+        #[arg(
+            help = "CUDA version to activate (defaults to .cuda-version, then install.default_version, then the newest installed)",
+            value_name = "VERSION",
+            value_parser = clap::value_parser!(CudaVersion)
+        )]
+        version: Option<CudaVersion>,
+        #[arg(
+            long,
+            help = "Also add the driver stub libraries (lib64/stubs) to LD_LIBRARY_PATH"
+        )]
+        with_stubs: bool,
+        #[arg(
+            long,
+            help = "Undo `cudup use`: unset CUDA_HOME and drop cudup's entries from PATH and LD_LIBRARY_PATH",
+            conflicts_with_all = ["version", "with_stubs"]
+        )]
+        deactivate: bool,
+        #[arg(
+            long,
+            help = "Also save the exports to ~/.cudup/active.env so new shells start with this version",
+            conflicts_with = "deactivate"
+        )]
+        export_only: bool,
+    },
+    Env {
+        #[arg(
+            long,
+            help = "CUDA version to emit (defaults to .cuda-version, then install.default_version)",
+            value_name = "VERSION",
+            value_parser = clap::value_parser!(CudaVersion)
+        )]
+        version: Option<CudaVersion>,
+        #[arg(
+            long,
+            help = "Also add the driver stub libraries (lib64/stubs) to LD_LIBRARY_PATH"
+        )]
+        with_stubs: bool,
+    },
     Local {
         #[arg(
             help = "CUDA version to set in .cuda-version",
@@ -56,22 +351,251 @@ enum Commands {
             value_parser = clap::value_parser!(CudaVersion)
         )]
         version: Option<CudaVersion>,
+        #[arg(
+            long,
+            help = "Remove the nearest .cuda-version file",
+            conflicts_with = "version"
+        )]
+        clear: bool,
+        #[arg(
+            long,
+            help = "Only remove .cuda-version from the current directory",
+            requires = "clear"
+        )]
+        here: bool,
+        #[arg(short, long, help = "Skip confirmation prompt", requires = "clear")]
+        force: bool,
+        #[arg(
+            long,
+            help = "Remove .cuda-version from the current directory without prompting",
+            conflicts_with_all = ["version", "clear"]
+        )]
+        unset: bool,
+        #[arg(
+            long,
+            help = "Only look for .cuda-version in the current directory, not its parents",
+            conflicts_with_all = ["version", "clear", "unset"]
+        )]
+        no_ascend: bool,
+    },
+    Gc,
+    Prompt,
+    SelfUninstall {
+        #[arg(
+            short,
+            long,
+            help = "Skip confirmation prompt, even if the active CUDA version would be removed"
+        )]
+        force: bool,
     },
     Manage {
         #[command(subcommand)]
         command: ManageCommand,
     },
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+    #[command(hide = true)]
+    Completions {
+        #[arg(help = "Shell to generate completions for", value_enum)]
+        shell: clap_complete::Shell,
+    },
+    #[command(name = "__platforms", hide = true)]
+    Platforms {
+        #[arg(
+            help = "CUDA version whose metadata to inspect (e.g., 12.4.1)",
+            value_name = "VERSION",
+            value_parser = clap::value_parser!(CudaVersion)
+        )]
+        version: CudaVersion,
+    },
+    #[command(name = "__complete-versions", hide = true)]
+    CompleteVersions {
+        #[arg(
+            long,
+            help = "Complete installed versions (default)",
+            conflicts_with = "available"
+        )]
+        installed: bool,
+        #[arg(long, help = "Complete versions available for install")]
+        available: bool,
+    },
 }
 
 #[derive(Subcommand)]
 enum ManageCommand {
-    Setup,
-    Remove,
+    Setup {
+        #[arg(
+            long,
+            help = "Shell to configure (bash, zsh, fish) instead of detecting it from $SHELL",
+            value_name = "SHELL",
+            value_parser = clap::value_parser!(commands::manage::Shell)
+        )]
+        shell: Option<commands::manage::Shell>,
+        #[arg(long, help = "Also install shell completions")]
+        completions: bool,
+    },
+    Remove {
+        #[arg(
+            long,
+            help = "Shell to unconfigure (bash, zsh, fish) instead of detecting it from $SHELL",
+            value_name = "SHELL",
+            value_parser = clap::value_parser!(commands::manage::Shell)
+        )]
+        shell: Option<commands::manage::Shell>,
+    },
+    Status {
+        #[arg(
+            long,
+            help = "Shell to inspect (bash, zsh, fish) instead of detecting it from $SHELL",
+            value_name = "SHELL",
+            value_parser = clap::value_parser!(commands::manage::Shell)
+        )]
+        shell: Option<commands::manage::Shell>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    Get {
+        #[arg(help = "Config key (e.g., mirror.cuda)", value_name = "KEY")]
+        key: String,
+    },
+    Set {
+        #[arg(help = "Config key (e.g., mirror.cuda)", value_name = "KEY")]
+        key: String,
+        #[arg(help = "Value to store", value_name = "VALUE")]
+        value: String,
+    },
+    List,
+    Path {
+        #[arg(long, help = "Print the paths as JSON")]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommand {
+    Stats,
+}
+
+// Download and extraction flags shared by every command that installs versions. (A doc
+// comment here would become the about text of the commands that flatten it.)
+#[derive(clap::Args)]
+struct DownloadArgs {
+    #[arg(
+        long,
+        help = "Abort a download after this many seconds without receiving data",
+        value_name = "SECS",
+        default_value_t = fetch::DEFAULT_IDLE_TIMEOUT_SECS
+    )]
+    timeout: u64,
+    #[arg(
+        short,
+        long,
+        help = "Number of packages to download in parallel [default: download.concurrency, else 4]",
+        value_name = "N",
+        value_parser = clap::value_parser!(u16).range(1..)
+    )]
+    jobs: Option<u16>,
+    #[arg(
+        long,
+        help = "Maximum simultaneous downloads from a single host",
+        value_name = "N",
+        default_value_t = fetch::DEFAULT_CONCURRENCY_PER_HOST,
+        value_parser = clap::value_parser!(u16).range(1..)
+    )]
+    concurrency_per_host: u16,
+    #[arg(
+        long,
+        help = "Fail when two packages extract different files to the same path"
+    )]
+    strict_extract: bool,
+    #[arg(long, help = "Print a JSON summary of the install to stdout")]
+    json: bool,
+    #[arg(
+        long,
+        help = "Architecture to resolve packages for (x86_64 or aarch64)",
+        value_name = "ARCH"
+    )]
+    arch: Option<String>,
+    #[arg(
+        long,
+        help = "Don't warn when the NVIDIA driver is too old for the toolkit"
+    )]
+    ignore_driver: bool,
+    #[arg(
+        long,
+        help = "Keep downloaded archives after extraction and reuse them on later installs"
+    )]
+    keep_downloads: bool,
+    #[arg(
+        long,
+        help = "Download archives to this directory instead of ~/.cudup/downloads and keep them there (implies --keep-downloads)",
+        value_name = "DIR"
+    )]
+    archive_dir: Option<PathBuf>,
+    #[arg(
+        long,
+        visible_alias = "skip-errors",
+        help = "Skip packages that fail to download or extract instead of aborting the install"
+    )]
+    continue_on_error: bool,
+    #[arg(
+        long,
+        help = "Command to run after a successful install (overrides hooks.post_install)",
+        value_name = "CMD"
+    )]
+    post_hook: Option<String>,
+    #[arg(
+        long,
+        help = "Progress output: auto (bars on a terminal) or plain (log lines for build logs)",
+        value_name = "MODE",
+        default_value = "auto",
+        value_parser = clap::value_parser!(fetch::ProgressMode)
+    )]
+    progress: fetch::ProgressMode,
+}
+
+impl DownloadArgs {
+    /// Install options with these flags applied and everything else at its default.
+    fn install_options(&self) -> fetch::InstallOptions {
+        fetch::InstallOptions {
+            idle_timeout: Duration::from_secs(self.timeout),
+            strict_extract: self.strict_extract,
+            json: self.json,
+            arch: self.arch.clone(),
+            ignore_driver: self.ignore_driver,
+            post_hook: self.post_hook.clone(),
+            keep_downloads: self.keep_downloads || self.archive_dir.is_some(),
+            jobs: self.jobs.map_or_else(fetch::default_jobs, usize::from),
+            concurrency_per_host: usize::from(self.concurrency_per_host),
+            continue_on_error: self.continue_on_error,
+            archive_dir: self.archive_dir.clone(),
+            progress: self.progress,
+            ..Default::default()
+        }
+    }
+}
+
+/// Generates the completion script for `shell` from the CLI definition.
+pub fn completion_script(shell: clap_complete::Shell) -> Vec<u8> {
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut Cli::command(), "cudup", &mut script);
+    commands::complete::with_dynamic_versions(shell, script)
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
+    let cli = Cli::parse();
+
+    let default_level = if cli.quiet { "error" } else { "info" };
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(default_level))
         .format(|buf, record| {
             let level_style = buf.default_level_style(record.level());
             writeln!(
@@ -83,26 +607,204 @@ async fn main() -> Result<()> {
         })
         .init();
 
-    let cli = Cli::parse();
+    config::init();
+    if cli.no_cache {
+        cache::disable();
+    }
+    if cli.refresh {
+        cache::refresh();
+    }
+    if cli.quiet {
+        output::set_quiet();
+    }
 
     match &cli.command {
-        Commands::Install { version } => commands::install(version).await?,
+        Commands::Install {
+            version,
+            from_lockfile,
+            download,
+            redist_file,
+            components,
+            skip_cudnn,
+            only_cudnn,
+            cudnn_version,
+            dest,
+            print_urls,
+            manifest_only,
+            resume,
+            with_sha256,
+            verify_existing,
+            fail_fast,
+        } => {
+            let options = fetch::InstallOptions {
+                redist_file: redist_file.clone(),
+                skip_cudnn: *skip_cudnn,
+                cudnn_version: cudnn_version.clone(),
+                components: *components,
+                dest: dest.clone(),
+                ..download.install_options()
+            };
+            if version.len() > 1
+                && (*print_urls
+                    || *manifest_only
+                    || *resume
+                    || *verify_existing
+                    || download.json
+                    || dest.is_some())
+            {
+                anyhow::bail!(
+                    "--print-urls, --manifest-only, --resume, --verify-existing, --json and --dest take a single version"
+                );
+            }
+            match (version.first(), from_lockfile) {
+                _ if *print_urls => {
+                    commands::print_urls(
+                        version.first(),
+                        from_lockfile.as_deref(),
+                        *with_sha256,
+                        &options,
+                    )
+                    .await?
+                }
+                (Some(v), None) if *manifest_only => {
+                    commands::install_manifest_only(v, &options).await?
+                }
+                (Some(v), None) if *resume => commands::resume_install(v, &options).await?,
+                (Some(v), None) if *verify_existing => {
+                    commands::repair_install(v, &options).await?
+                }
+                (_, Some(path)) => commands::install_from_lockfile(path, &options).await?,
+                (Some(_), None) => {
+                    commands::install_versions(version, *only_cudnn, *fail_fast, &options).await?
+                }
+                (None, None) => {
+                    let version = commands::pick_version().await?;
+                    commands::install(&version, *only_cudnn, &options).await?
+                }
+            }
+        }
+        Commands::Lock {
+            version,
+            output,
+            arch,
+        } => commands::lock(version, arch.clone(), output).await?,
+        Commands::Freeze { output } => commands::freeze(output)?,
+        Commands::Restore {
+            path,
+            fail_fast,
+            download,
+        } => commands::restore(path, *fail_fast, &download.install_options()).await?,
         Commands::Uninstall {
             version,
             force,
             all,
-        } => commands::uninstall(version.as_ref().map(CudaVersion::as_str), *force, *all)?,
-        Commands::List => commands::list_available_versions().await?,
-        Commands::Check => commands::check()?,
-        Commands::Use { version } => commands::use_version(version.as_str())?,
-        Commands::Local { version } => match version {
+            dry_run,
+        } => commands::uninstall(
+            version.as_ref().map(CudaVersion::as_str),
+            *force,
+            *all,
+            *dry_run,
+        )?,
+        Commands::Verify { version } => commands::verify(version).await?,
+        Commands::List {
+            remote_only,
+            installed_only,
+            json,
+            size,
+            since,
+            until,
+            major,
+            limit,
+        } => {
+            commands::list_available_versions(
+                *remote_only,
+                *installed_only,
+                *json,
+                *size,
+                commands::ListFilters {
+                    since: *since,
+                    until: *until,
+                    major: *major,
+                    limit: limit.map(usize::from),
+                },
+            )
+            .await?
+        }
+        Commands::ListPackages { version, arch } => {
+            commands::list_packages(version, arch.as_deref()).await?
+        }
+        Commands::Info {
+            version,
+            list_packages,
+            all_platforms,
+        } => commands::info(version, *list_packages, *all_platforms).await?,
+        Commands::Check {
+            network,
+            timeout_connect,
+            json,
+            strict,
+        } => {
+            commands::check(
+                *network,
+                Duration::from_secs(*timeout_connect),
+                *json,
+                *strict,
+            )
+            .await?
+        }
+        Commands::Use {
+            version,
+            with_stubs,
+            deactivate,
+            export_only,
+        } => {
+            if *deactivate {
+                commands::deactivate()?
+            } else {
+                commands::use_version(version.as_ref(), *with_stubs, *export_only)?
+            }
+        }
+        Commands::Env {
+            version,
+            with_stubs,
+        } => commands::env(version.as_ref(), *with_stubs)?,
+        Commands::Local {
+            version,
+            clear,
+            here,
+            force,
+            unset,
+            no_ascend,
+        } => match version {
             Some(v) => commands::local_write(v)?,
-            None => commands::local_activate()?,
+            None if *clear => commands::local_clear(*here, *force)?,
+            None if *unset => commands::local_clear(true, true)?,
+            None => commands::local_activate(!*no_ascend)?,
         },
+        Commands::Gc => commands::gc().await?,
+        Commands::Prompt => commands::prompt(),
+        Commands::SelfUninstall { force } => commands::self_uninstall(*force)?,
         Commands::Manage { command } => match command {
-            ManageCommand::Setup => commands::setup()?,
-            ManageCommand::Remove => commands::remove()?,
+            ManageCommand::Setup { shell, completions } => commands::setup(*shell, *completions)?,
+            ManageCommand::Remove { shell } => commands::remove(*shell)?,
+            ManageCommand::Status { shell } => commands::status(*shell)?,
+        },
+        Commands::Config { command } => match command {
+            ConfigCommand::Get { key } => commands::config_get(key)?,
+            ConfigCommand::Set { key, value } => commands::config_set(key, value)?,
+            ConfigCommand::List => commands::config_list()?,
+            ConfigCommand::Path { json } => commands::config_paths(*json)?,
+        },
+        Commands::Cache { command } => match command {
+            CacheCommand::Stats => commands::cache_stats()?,
         },
+        Commands::Completions { shell } => {
+            std::io::stdout().write_all(&completion_script(*shell))?;
+        }
+        Commands::Platforms { version } => commands::platforms(version).await?,
+        Commands::CompleteVersions { available, .. } => {
+            commands::complete_versions(*available).await?
+        }
     }
 
     Ok(())
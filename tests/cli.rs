@@ -0,0 +1,539 @@
+//! Tests of commands that work offline, each run against an isolated `CUDUP_HOME`.
+
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use tempfile::TempDir;
+
+/// An isolated `CUDUP_HOME` and a working directory to run cudup in.
+struct Cudup {
+    home: TempDir,
+    cwd: TempDir,
+}
+
+impl Cudup {
+    fn new() -> Self {
+        Self {
+            home: TempDir::new().expect("failed to create temp CUDUP_HOME"),
+            cwd: TempDir::new().expect("failed to create temp working dir"),
+        }
+    }
+
+    fn home(&self) -> &Path {
+        self.home.path()
+    }
+
+    fn cwd(&self) -> &Path {
+        self.cwd.path()
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_cudup"));
+        command
+            .args(args)
+            .current_dir(self.cwd())
+            .env("CUDUP_HOME", self.home())
+            .env_remove("CUDUP_VERSIONS_DIR")
+            .env("RUST_LOG", "info");
+        command
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.command(args).output().expect("failed to run cudup")
+    }
+
+    /// Runs cudup and returns its stdout, failing the test if it exits non-zero.
+    fn stdout(&self, args: &[&str]) -> String {
+        let output = self.run(args);
+        assert!(
+            output.status.success(),
+            "cudup {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+}
+
+#[test]
+fn config_path_honors_cudup_versions_dir() {
+    let cudup = Cudup::new();
+    let versions = cudup.cwd().join("big-disk/versions");
+
+    let output = cudup
+        .command(&["config", "path", "--json"])
+        .env("CUDUP_VERSIONS_DIR", &versions)
+        .output()
+        .expect("failed to run cudup");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let paths: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(paths["versions_dir"]["path"], versions.to_str().unwrap());
+    assert_eq!(paths["versions_dir"]["exists"], false);
+    assert_eq!(
+        paths["downloads_dir"]["path"],
+        cudup.home().join("downloads").to_str().unwrap(),
+        "only the versions dir should move"
+    );
+
+    let plain = cudup.stdout(&["config", "path"]);
+    assert!(
+        plain.contains(&format!(
+            "versions:    {}",
+            cudup.home().join("versions").display()
+        )),
+        "{plain}"
+    );
+}
+
+#[test]
+fn config_set_repairs_an_unparseable_config_file() {
+    let cudup = Cudup::new();
+    let config = cudup.home().join("config.toml");
+    fs::write(&config, "[mirror\ncuda = ").unwrap();
+
+    let paths = cudup.run(&["config", "path"]);
+    assert!(
+        paths.status.success(),
+        "a broken config file should not stop other commands: {}",
+        String::from_utf8_lossy(&paths.stderr)
+    );
+    assert!(String::from_utf8_lossy(&paths.stderr).contains("using default settings"));
+
+    cudup.stdout(&["config", "set", "download.concurrency", "2"]);
+
+    assert_eq!(
+        fs::read_to_string(cudup.home().join("config.toml.bak")).unwrap(),
+        "[mirror\ncuda = "
+    );
+    assert_eq!(
+        cudup
+            .stdout(&["config", "get", "download.concurrency"])
+            .trim(),
+        "2"
+    );
+}
+
+#[test]
+fn zsh_completion_completes_every_install_version() {
+    let script = Cudup::new().stdout(&["completions", "zsh"]);
+
+    let install_versions = script
+        .lines()
+        .map(str::trim)
+        .find(|line| line.starts_with("'*::version -- CUDA versions to install"))
+        .expect("install has a repeated version positional");
+    assert!(
+        install_versions.ends_with(":_cudup_available_versions' \\"),
+        "install versions don't complete dynamically: {install_versions}"
+    );
+}
+
+#[test]
+fn bash_completion_completes_every_install_version() {
+    let cudup = Cudup::new();
+    let script = cudup.home().join("cudup.bash");
+    fs::write(&script, cudup.stdout(&["completions", "bash"])).unwrap();
+
+    // A stand-in for cudup that the completion script calls for version lists.
+    let bin = cudup.home().join("bin");
+    fs::create_dir(&bin).unwrap();
+    let fake = bin.join("cudup");
+    fs::write(&fake, "#!/bin/sh\nprintf '12.4.1\\n12.6.0\\n'\n").unwrap();
+    fs::set_permissions(&fake, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let complete = |words: &str| {
+        let output = Command::new("bash")
+            .arg("-c")
+            .arg(format!(
+                "source {}; COMP_WORDS=({words}); COMP_CWORD=$((${{#COMP_WORDS[@]}} - 1)); \
+                 _cudup_dynamic cudup; echo \"${{COMPREPLY[*]}}\"",
+                script.display()
+            ))
+            .env("PATH", format!("{}:/usr/bin:/bin", bin.display()))
+            .output()
+            .expect("failed to run bash");
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    };
+
+    assert_eq!(complete("cudup install 12."), "12.4.1 12.6.0");
+    assert_eq!(complete("cudup install 12.4.1 12.6"), "12.6.0");
+    assert_eq!(complete("cudup install --jobs 2 12.6"), "12.6.0");
+}
+
+/// Runs `command` with `input` on stdin, for commands that ask for confirmation.
+fn run_with_input(mut command: Command, input: &str) -> Output {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run cudup");
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(input.as_bytes())
+        .expect("failed to write to cudup");
+    child.wait_with_output().expect("failed to run cudup")
+}
+
+/// Creates an installed-looking version directory holding `bytes` bytes.
+fn fake_install(cudup: &Cudup, version: &str, bytes: usize) -> std::path::PathBuf {
+    let dir = cudup.home().join("versions").join(version);
+    fs::create_dir_all(dir.join("lib64")).unwrap();
+    fs::write(dir.join("lib64/libcudart.so"), vec![0; bytes]).unwrap();
+    dir
+}
+
+#[test]
+fn list_installed_json_has_a_stable_shape() {
+    let cudup = Cudup::new();
+    let older = fake_install(&cudup, "11.8.0", 2048);
+    let newer = fake_install(&cudup, "12.4.1", 4096);
+
+    let output = cudup.stdout(&["list", "--installed-only", "--json"]);
+    let actual: serde_json::Value =
+        serde_json::from_str(&output).expect("stdout should hold only the JSON document");
+    let expected = serde_json::json!({
+        "schema_version": 1,
+        "versions": [
+            {
+                "version": "11.8.0",
+                "installed": true,
+                "path": older.to_str().unwrap(),
+                "size_bytes": 2048,
+            },
+            {
+                "version": "12.4.1",
+                "installed": true,
+                "path": newer.to_str().unwrap(),
+                "size_bytes": 4096,
+            },
+        ],
+    });
+    assert_eq!(actual, expected);
+}
+
+/// Backdates `path` past gc's grace period for archives that may be in use.
+fn backdate(path: &Path) -> std::time::SystemTime {
+    let modified = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+    fs::File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+    modified
+}
+
+#[test]
+fn gc_keeps_stamped_archives() {
+    let cudup = Cudup::new();
+    // Cache pruning can't reach this mirror, which gc only warns about.
+    fs::write(
+        cudup.home().join("config.toml"),
+        "[mirror]\ncuda = \"http://127.0.0.1:9/cuda\"\ncudnn = \"http://127.0.0.1:9/cudnn\"\n",
+    )
+    .unwrap();
+    let downloads = cudup.home().join("downloads");
+    fs::create_dir_all(&downloads).unwrap();
+
+    let kept = downloads.join("cuda_nvcc-linux-x86_64-12.4.131-archive.tar.xz");
+    fs::write(&kept, b"kept archive").unwrap();
+    let modified = backdate(&kept);
+    let stamp = downloads.join("cuda_nvcc-linux-x86_64-12.4.131-archive.tar.xz.sha256");
+    let modified_nanos = modified
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    fs::write(
+        &stamp,
+        format!(
+            r#"{{"size":12,"modified_nanos":{modified_nanos},"checksum":"{}"}}"#,
+            "a".repeat(64)
+        ),
+    )
+    .unwrap();
+    backdate(&stamp);
+
+    let orphan = downloads.join("cuda_cudart-linux-x86_64-12.4.127-archive.tar.xz");
+    fs::write(&orphan, b"orphaned archive").unwrap();
+    backdate(&orphan);
+
+    let output = cudup.stdout(&["gc"]);
+    assert!(
+        output.contains("Removed 16 B of orphaned downloads"),
+        "{output}"
+    );
+    assert!(
+        kept.exists(),
+        "archives kept with --keep-downloads should survive gc"
+    );
+    assert!(stamp.exists());
+    assert!(!orphan.exists());
+}
+
+#[test]
+fn uninstall_reports_sizes_in_the_same_format_as_install() {
+    let cudup = Cudup::new();
+    fake_install(&cudup, "12.4.1", 3 * 1024);
+    fake_install(&cudup, "12.6.0", 1536 * 1024);
+
+    let single = cudup.stdout(&["uninstall", "12.4.1", "--dry-run"]);
+    assert!(single.contains("(3.00 KB)"), "{single}");
+
+    let all = cudup.stdout(&["uninstall", "--all", "--dry-run"]);
+    assert!(all.contains("1.50 MB") && all.contains("3.00 KB"), "{all}");
+}
+
+#[test]
+fn manage_shell_override_ignores_shell_env() {
+    for (shell, rc_file, env_file, source_line) in [
+        ("bash", ".bashrc", "env", ". \"$HOME/.cudup/env\""),
+        ("zsh", ".zshrc", "env", ". \"$HOME/.cudup/env\""),
+        (
+            "fish",
+            ".config/fish/config.fish",
+            "env.fish",
+            "source \"$HOME/.cudup/env.fish\"",
+        ),
+    ] {
+        let cudup = Cudup::new();
+        let home = cudup.home();
+        let run = |command: &str| {
+            let mut process = cudup.command(&["manage", command, "--shell", shell]);
+            process
+                .env("HOME", home)
+                .env("CUDUP_HOME", home.join(".cudup"))
+                .env("SHELL", "/bin/tcsh");
+            let output = run_with_input(process, "y\n");
+            assert!(
+                output.status.success(),
+                "manage {command} --shell {shell} failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        };
+
+        let setup = run("setup");
+        assert!(setup.contains(&format!("Using shell: {shell}")), "{setup}");
+        let rc = fs::read_to_string(home.join(rc_file)).unwrap();
+        assert!(rc.contains(source_line), "{shell}: {rc}");
+        assert!(home.join(".cudup").join(env_file).exists());
+
+        run("remove");
+        let rc = fs::read_to_string(home.join(rc_file)).unwrap();
+        assert!(!rc.contains(".cudup/env"), "{shell}: {rc}");
+        assert!(!home.join(".cudup").join(env_file).exists());
+    }
+}
+
+#[test]
+fn manage_rejects_an_unsupported_shell_override() {
+    let output = Cudup::new().run(&["manage", "setup", "--shell", "tcsh"]);
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Supported shells: bash, zsh, fish"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn local_walks_up_to_the_project_root() {
+    let cudup = Cudup::new();
+    fake_install(&cudup, "12.4.1", 16);
+    fs::write(cudup.cwd().join(".cuda-version"), "12.4.1\n").unwrap();
+    let nested = cudup.cwd().join("project/src");
+    fs::create_dir_all(&nested).unwrap();
+
+    let local = |args: &[&str]| {
+        let mut command = cudup.command(&[&["local"], args].concat());
+        command
+            .current_dir(&nested)
+            .output()
+            .expect("failed to run cudup")
+    };
+
+    let output = local(&[]);
+    assert!(
+        String::from_utf8_lossy(&output.stdout).contains("# CUDA 12.4.1 activated"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let output = local(&["--no-ascend"]);
+    assert!(
+        !output.status.success(),
+        "--no-ascend should not look in parents"
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No .cuda-version file found"));
+
+    fs::create_dir(cudup.cwd().join("project/.hg")).unwrap();
+    let output = local(&[]);
+    assert!(
+        !output.status.success(),
+        "the search should stop at the .hg root"
+    );
+
+    cudup.stdout(&["config", "set", "local.root_markers", "pyproject.toml"]);
+    let output = local(&[]);
+    assert!(
+        output.status.success(),
+        "configured markers replace the defaults: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn uninstall_dry_run_removes_nothing() {
+    let cudup = Cudup::new();
+    let active = fake_install(&cudup, "12.4.1", 16);
+    let other = fake_install(&cudup, "12.6.0", 16);
+    let active_env = cudup.home().join("active.env");
+    let persisted = format!("export CUDA_HOME=\"{}\"\n", active.display());
+    fs::write(&active_env, &persisted).unwrap();
+
+    for args in [
+        &["uninstall", "12.4.1", "--dry-run"][..],
+        &["uninstall", "12.4.1", "--dry-run", "--force"],
+        &["uninstall", "--all", "--dry-run", "--force"],
+    ] {
+        let output = cudup
+            .command(args)
+            .env("CUDA_HOME", &active)
+            .output()
+            .expect("failed to run cudup");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "cudup {} failed", args.join(" "));
+        assert!(
+            stdout.contains("invalid CUDA paths after removal"),
+            "the active version should be flagged: {stdout}"
+        );
+        assert!(stdout.contains("Dry run: nothing was removed."), "{stdout}");
+
+        assert!(active.join("lib64/libcudart.so").exists());
+        assert!(other.join("lib64/libcudart.so").exists());
+        assert_eq!(fs::read_to_string(&active_env).unwrap(), persisted);
+    }
+}
+
+#[test]
+fn local_unset_removes_only_the_current_directory_pin() {
+    let cudup = Cudup::new();
+    let nested = cudup.cwd().join("project");
+    fs::create_dir(&nested).unwrap();
+    fs::write(cudup.cwd().join(".cuda-version"), "12.4.1\n").unwrap();
+
+    let unset = || {
+        let output = cudup
+            .command(&["local", "--unset"])
+            .current_dir(&nested)
+            .output()
+            .expect("failed to run cudup");
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    let stdout = unset();
+    assert!(
+        stdout.contains("No .cuda-version file in the current directory."),
+        "{stdout}"
+    );
+    assert!(
+        cudup.cwd().join(".cuda-version").exists(),
+        "--unset must not walk up"
+    );
+
+    let set = cudup
+        .command(&["local", "12.6.0"])
+        .current_dir(&nested)
+        .output()
+        .expect("failed to run cudup");
+    assert!(set.status.success());
+    let pin = nested.join(".cuda-version");
+    assert_eq!(fs::read_to_string(&pin).unwrap(), "12.6.0\n");
+
+    let stdout = unset();
+    assert!(
+        stdout.contains(&format!("Removed {} (pinned CUDA 12.6.0)", pin.display())),
+        "{stdout}"
+    );
+    assert!(!pin.exists());
+    assert!(cudup.cwd().join(".cuda-version").exists());
+}
+
+#[test]
+fn env_resolves_flag_then_version_file_then_default() {
+    let cudup = Cudup::new();
+    for version in ["11.8.0", "12.4.1", "12.6.0"] {
+        fake_install(&cudup, version, 16);
+    }
+    let cuda_home = |stdout: String| {
+        stdout
+            .lines()
+            .find_map(|line| line.strip_prefix("export CUDA_HOME="))
+            .map(|value| value.trim_matches('"').to_string())
+            .unwrap_or_else(|| panic!("no CUDA_HOME export in {stdout}"))
+    };
+    let expected = |version: &str| {
+        cudup
+            .home()
+            .join("versions")
+            .join(version)
+            .display()
+            .to_string()
+    };
+
+    let output = cudup.run(&["env"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No CUDA version selected"));
+
+    cudup.stdout(&["config", "set", "install.default_version", "11.8.0"]);
+    assert_eq!(cuda_home(cudup.stdout(&["env"])), expected("11.8.0"));
+
+    fs::write(cudup.cwd().join(".cuda-version"), "12.4.1\n").unwrap();
+    assert_eq!(cuda_home(cudup.stdout(&["env"])), expected("12.4.1"));
+
+    assert_eq!(
+        cuda_home(cudup.stdout(&["env", "--version", "12.6.0"])),
+        expected("12.6.0")
+    );
+}
+
+#[test]
+fn activation_exports_cuda_home_and_cuda_path() {
+    let cudup = Cudup::new();
+    let install = fake_install(&cudup, "12.4.1", 16);
+    fs::write(cudup.cwd().join(".cuda-version"), "12.4.1\n").unwrap();
+    let cuda_home = format!("export CUDA_HOME=\"{}\"", install.display());
+
+    for args in [&["use", "12.4.1"][..], &["local"], &["env"]] {
+        let stdout = cudup.stdout(args);
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert!(lines.contains(&cuda_home.as_str()), "{stdout}");
+        assert!(
+            lines.contains(&"export CUDA_PATH=\"$CUDA_HOME\""),
+            "cudup {} should export CUDA_PATH: {stdout}",
+            args.join(" ")
+        );
+    }
+
+    let output = cudup
+        .command(&["check"])
+        .env_remove("CUDA_HOME")
+        .env("CUDA_PATH", &install)
+        .output()
+        .expect("failed to run cudup");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("active version: 12.4.1"),
+        "check should find the version through CUDA_PATH: {stdout}"
+    );
+}
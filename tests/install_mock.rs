@@ -0,0 +1,1235 @@
+//! End-to-end install tests against a mock redist server.
+
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use tempfile::TempDir;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const CUDA_VERSION: &str = "12.4.1";
+
+/// A mock NVIDIA redist server plus an isolated `CUDUP_HOME` pointing at it.
+struct MockRedist {
+    server: MockServer,
+    home: TempDir,
+}
+
+impl MockRedist {
+    async fn start() -> Self {
+        let server = MockServer::start().await;
+        let home = TempDir::new().expect("failed to create temp CUDUP_HOME");
+
+        let config = format!(
+            "[mirror]\ncuda = \"{uri}/cuda\"\ncudnn = \"{uri}/cudnn\"\n\n[install]\ndefault_arch = \"x86_64\"\n",
+            uri = server.uri()
+        );
+        fs::write(home.path().join("config.toml"), config).expect("failed to write config");
+
+        Self { server, home }
+    }
+
+    fn home(&self) -> &Path {
+        self.home.path()
+    }
+
+    fn downloads_dir(&self) -> PathBuf {
+        self.home().join("downloads")
+    }
+
+    fn install_dir(&self, version: &str) -> PathBuf {
+        self.home().join("versions").join(version)
+    }
+
+    async fn serve(&self, route: &str, body: impl Into<Vec<u8>>) {
+        Mock::given(method("GET"))
+            .and(path(route))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(body.into()))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Serves the CUDA index and a metadata file with one package per `(name, relative_path, sha256, size)`.
+    async fn serve_cuda_release(&self, version: &str, packages: &[(&str, &str, String, usize)]) {
+        let packages: serde_json::Map<String, serde_json::Value> = packages
+            .iter()
+            .map(|(name, relative_path, sha256, size)| {
+                let package = serde_json::json!({
+                    "name": name,
+                    "license": "CUDA Toolkit",
+                    "version": version,
+                    "linux-x86_64": {
+                        "relative_path": relative_path,
+                        "sha256": sha256,
+                        "md5": "",
+                        "size": size.to_string(),
+                    },
+                });
+                (name.to_string(), package)
+            })
+            .collect();
+
+        self.serve_cuda_metadata(version, packages).await;
+    }
+
+    /// Serves the CUDA index and a metadata file holding `packages` as given, for tests
+    /// that need platform blocks other than `linux-x86_64`.
+    async fn serve_cuda_metadata(
+        &self,
+        version: &str,
+        packages: serde_json::Map<String, serde_json::Value>,
+    ) {
+        self.serve(
+            "/cuda/",
+            format!("<a href='redistrib_{version}.json'>redistrib_{version}.json</a>"),
+        )
+        .await;
+
+        let mut metadata = serde_json::json!({ "release_date": "2024-04-01" });
+        metadata
+            .as_object_mut()
+            .expect("metadata is an object")
+            .extend(packages);
+
+        self.serve(
+            &format!("/cuda/redistrib_{version}.json"),
+            metadata.to_string(),
+        )
+        .await;
+    }
+
+    /// Lists `versions` in the CUDA index, overriding the single version listed by
+    /// `serve_cuda_metadata`, for tests that install several releases.
+    async fn serve_cuda_index(&self, versions: &[&str]) {
+        let links: String = versions
+            .iter()
+            .map(|v| format!("<a href='redistrib_{v}.json'>redistrib_{v}.json</a>\n"))
+            .collect();
+        Mock::given(method("GET"))
+            .and(path("/cuda/"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(links))
+            .with_priority(1)
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Serves `version` as a release holding a single cudart package with `files`.
+    async fn serve_cudart_release(&self, version: &str, files: &[(&str, &[u8])]) {
+        let top_level = format!("cuda_cudart-linux-x86_64-{version}-archive");
+        let relative_path = format!("cuda_cudart/linux-x86_64/{top_level}.tar.xz");
+        let archive = package_archive(&top_level, files);
+
+        self.serve_cuda_release(
+            version,
+            &[(
+                "cuda_cudart",
+                &relative_path,
+                sha256_hex(&archive),
+                archive.len(),
+            )],
+        )
+        .await;
+        self.serve(&format!("/cuda/{relative_path}"), archive).await;
+    }
+
+    /// Lists every `(version, cuda_majors)` in the cuDNN index and serves its metadata, with a
+    /// `cudnn` package built for each major. Each build holds a `lib/libcudnn.so` naming itself.
+    async fn serve_cudnn_releases(&self, releases: &[(&str, &[&str])]) {
+        let links: String = releases
+            .iter()
+            .map(|(v, _)| format!("<a href='redistrib_{v}.json'>redistrib_{v}.json</a>\n"))
+            .collect();
+        self.serve("/cudnn/", links).await;
+
+        for (version, majors) in releases {
+            let mut builds = serde_json::Map::new();
+            for major in *majors {
+                let top_level = format!("cudnn-linux-x86_64-{version}_cuda{major}-archive");
+                let relative_path = format!("cudnn/linux-x86_64/{top_level}.tar.xz");
+                let contents = format!("cudnn {version} cuda{major}");
+                let archive =
+                    package_archive(&top_level, &[("lib/libcudnn.so", contents.as_bytes())]);
+                builds.insert(
+                    format!("cuda{major}"),
+                    serde_json::json!({
+                        "relative_path": relative_path,
+                        "sha256": sha256_hex(&archive),
+                        "md5": "",
+                        "size": archive.len().to_string(),
+                    }),
+                );
+                self.serve(&format!("/cudnn/{relative_path}"), archive)
+                    .await;
+            }
+
+            let metadata = serde_json::json!({
+                "release_date": "2024-04-01",
+                "cudnn": {
+                    "name": "NVIDIA CUDA Deep Neural Network library",
+                    "license": "cudnn",
+                    "version": version,
+                    "cuda_variant": majors,
+                    "linux-x86_64": builds,
+                },
+            });
+            self.serve(
+                &format!("/cudnn/redistrib_{version}.json"),
+                metadata.to_string(),
+            )
+            .await;
+        }
+    }
+
+    fn cudup(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_cudup"))
+            .args(args)
+            .env("CUDUP_HOME", self.home())
+            .env_remove("CUDUP_VERSIONS_DIR")
+            .env("RUST_LOG", "info")
+            .output()
+            .expect("failed to run cudup")
+    }
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Builds a `.tar.xz` laid out like NVIDIA's, with `files` under a single top-level directory.
+fn package_archive(top_level: &str, files: &[(&str, &[u8])]) -> Vec<u8> {
+    let files: Vec<(String, &[u8])> = files
+        .iter()
+        .map(|(relative, contents)| (format!("{top_level}/{relative}"), *contents))
+        .collect();
+    tar_xz(&[top_level], &files)
+}
+
+/// Builds a `.tar.xz` holding `files`, with each of `roots` as a top-level entry.
+fn tar_xz<P: AsRef<Path>>(roots: &[&str], files: &[(P, &[u8])]) -> Vec<u8> {
+    let dir = TempDir::new().expect("failed to create archive staging dir");
+    for (relative, contents) in files {
+        let file = dir.path().join(relative);
+        fs::create_dir_all(file.parent().expect("file has a parent"))
+            .expect("failed to create dir");
+        fs::write(file, contents).expect("failed to write archive file");
+    }
+
+    let archive = dir.path().join("archive.tar.xz");
+    let status = Command::new("tar")
+        .arg("cJf")
+        .arg(&archive)
+        .arg("-C")
+        .arg(dir.path())
+        .args(roots)
+        .status()
+        .expect("failed to run tar");
+    assert!(status.success(), "tar failed to build the archive");
+
+    fs::read(archive).expect("failed to read archive")
+}
+
+#[tokio::test]
+async fn checksum_mismatch_removes_archive_and_install_dir() {
+    let redist = MockRedist::start().await;
+
+    let advertised = b"the archive NVIDIA published";
+    let served = b"a corrupted archive";
+    let relative_path = "cuda_cudart/linux-x86_64/cuda_cudart-linux-x86_64-12.4.127-archive.tar.xz";
+
+    redist
+        .serve_cuda_release(
+            CUDA_VERSION,
+            &[(
+                "cuda_cudart",
+                relative_path,
+                sha256_hex(advertised),
+                served.len(),
+            )],
+        )
+        .await;
+    redist
+        .serve(&format!("/cuda/{relative_path}"), served.to_vec())
+        .await;
+
+    let output = redist.cudup(&["install", CUDA_VERSION, "--skip-cudnn"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(!output.status.success(), "install should fail: {stderr}");
+    assert!(
+        stderr.contains("Checksum mismatch"),
+        "expected checksum error, got: {stderr}"
+    );
+    assert!(
+        stderr.contains("the size matches (19 bytes) but the content differs"),
+        "expected the mismatch to be diagnosed: {stderr}"
+    );
+
+    let archive = redist
+        .downloads_dir()
+        .join("cuda_cudart-linux-x86_64-12.4.127-archive.tar.xz");
+    assert!(!archive.exists(), "corrupt archive was left behind");
+    assert!(
+        !redist.install_dir(CUDA_VERSION).exists(),
+        "partial install dir was left behind"
+    );
+}
+
+#[tokio::test]
+async fn arch_override_installs_sbsa_archives() {
+    let redist = MockRedist::start().await;
+
+    let x86_path = "cuda_cudart/linux-x86_64/cuda_cudart-linux-x86_64-12.4.127-archive.tar.xz";
+    let sbsa_path = "cuda_cudart/linux-sbsa/cuda_cudart-linux-sbsa-12.4.127-archive.tar.xz";
+    let x86_archive = package_archive(
+        "cuda_cudart-linux-x86_64-12.4.127-archive",
+        &[("lib/libcudart.so", b"x86_64 cudart")],
+    );
+    let sbsa_archive = package_archive(
+        "cuda_cudart-linux-sbsa-12.4.127-archive",
+        &[("lib/libcudart.so", b"sbsa cudart")],
+    );
+
+    let block = |relative_path: &str, archive: &[u8]| {
+        serde_json::json!({
+            "relative_path": relative_path,
+            "sha256": sha256_hex(archive),
+            "md5": "",
+            "size": archive.len().to_string(),
+        })
+    };
+    let mut packages = serde_json::Map::new();
+    packages.insert(
+        "cuda_cudart".to_string(),
+        serde_json::json!({
+            "name": "CUDA Runtime (cudart)",
+            "license": "CUDA Toolkit",
+            "version": "12.4.127",
+            "linux-x86_64": block(x86_path, &x86_archive),
+            "linux-sbsa": block(sbsa_path, &sbsa_archive),
+        }),
+    );
+    redist.serve_cuda_metadata(CUDA_VERSION, packages).await;
+    redist
+        .serve(&format!("/cuda/{x86_path}"), x86_archive)
+        .await;
+    redist
+        .serve(&format!("/cuda/{sbsa_path}"), sbsa_archive)
+        .await;
+
+    let urls = redist.cudup(&["install", CUDA_VERSION, "--arch", "aarch64", "--print-urls"]);
+    let stdout = String::from_utf8_lossy(&urls.stdout);
+    assert!(
+        urls.status.success(),
+        "--print-urls failed: {}",
+        String::from_utf8_lossy(&urls.stderr)
+    );
+    assert!(
+        stdout.contains(sbsa_path),
+        "expected the sbsa archive, got: {stdout}"
+    );
+    assert!(
+        !stdout.contains(x86_path),
+        "x86_64 archive was chosen: {stdout}"
+    );
+
+    let output = redist.cudup(&["install", CUDA_VERSION, "--arch", "aarch64", "--skip-cudnn"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "install failed: {stderr}");
+
+    let install_dir = redist.install_dir(CUDA_VERSION);
+    let library = fs::read(install_dir.join("lib/libcudart.so")).expect("cudart was not extracted");
+    assert_eq!(library, b"sbsa cudart");
+
+    let manifest: serde_json::Value = serde_json::from_slice(
+        &fs::read(install_dir.join(".cudup-manifest.json")).expect("manifest was not written"),
+    )
+    .expect("manifest is JSON");
+    assert_eq!(manifest["platform"], "linux-sbsa");
+    assert_eq!(manifest["packages"][0]["relative_path"], sbsa_path);
+
+    let lockfile =
+        fs::read_to_string(install_dir.join("cudup.lock")).expect("lockfile was not written");
+    assert!(
+        lockfile.contains(sbsa_path) && lockfile.contains("linux-sbsa"),
+        "lockfile should pin the installed archives: {lockfile}"
+    );
+}
+
+#[tokio::test]
+async fn installs_several_versions_and_skips_installed_ones() {
+    let redist = MockRedist::start().await;
+    redist.serve_cuda_index(&["12.4.1", "12.6.0"]).await;
+    redist
+        .serve_cudart_release("12.4.1", &[("lib/libcudart.so", b"cudart 12.4")])
+        .await;
+    redist
+        .serve_cudart_release("12.6.0", &[("lib/libcudart.so", b"cudart 12.6")])
+        .await;
+
+    let output = redist.cudup(&["install", "12.4.1", "12.6.0", "12.4.1", "--skip-cudnn"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "install failed: {stderr}");
+    assert!(
+        stderr.contains("Installed: 12.4.1, 12.6.0"),
+        "expected both versions in the rollup: {stderr}"
+    );
+    for (version, contents) in [("12.4.1", "cudart 12.4"), ("12.6.0", "cudart 12.6")] {
+        let library = fs::read(redist.install_dir(version).join("lib/libcudart.so"))
+            .expect("cudart was not extracted");
+        assert_eq!(library, contents.as_bytes());
+    }
+
+    let again = redist.cudup(&["install", "12.4.1", "12.6.0", "--skip-cudnn"]);
+    let stderr = String::from_utf8_lossy(&again.stderr);
+    assert!(
+        again.status.success(),
+        "installed versions should be skipped: {stderr}"
+    );
+    assert!(
+        stderr.contains("Already installed: 12.4.1, 12.6.0"),
+        "expected both versions to be skipped: {stderr}"
+    );
+}
+
+#[tokio::test]
+async fn failed_version_stops_the_batch_only_with_fail_fast() {
+    let redist = MockRedist::start().await;
+    redist
+        .serve_cudart_release(CUDA_VERSION, &[("lib/libcudart.so", b"cudart")])
+        .await;
+
+    let output = redist.cudup(&[
+        "install",
+        "12.0.0",
+        CUDA_VERSION,
+        "--skip-cudnn",
+        "--fail-fast",
+    ]);
+    assert!(!output.status.success(), "unavailable version should fail");
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("CUDA version 12.0.0 is not available"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(
+        !redist.install_dir(CUDA_VERSION).exists(),
+        "--fail-fast should stop before the next version"
+    );
+
+    let output = redist.cudup(&["install", "12.0.0", CUDA_VERSION, "--skip-cudnn"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        !output.status.success(),
+        "a failed version should fail the batch: {stderr}"
+    );
+    assert!(
+        stderr.contains("1 of 2 versions failed to install: 12.0.0"),
+        "expected the failure rollup: {stderr}"
+    );
+    assert!(
+        redist
+            .install_dir(CUDA_VERSION)
+            .join("lib/libcudart.so")
+            .exists(),
+        "later versions should still be installed"
+    );
+}
+
+#[tokio::test]
+async fn stalled_download_fails_with_idle_timeout() {
+    let redist = MockRedist::start().await;
+
+    let archive = package_archive(
+        "cuda_cudart-linux-x86_64-12.4.127-archive",
+        &[("lib/libcudart.so", b"cudart")],
+    );
+    let relative_path = "cuda_cudart/linux-x86_64/cuda_cudart-linux-x86_64-12.4.127-archive.tar.xz";
+    redist
+        .serve_cuda_release(
+            CUDA_VERSION,
+            &[(
+                "cuda_cudart",
+                relative_path,
+                sha256_hex(&archive),
+                archive.len(),
+            )],
+        )
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("/cuda/{relative_path}")))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(archive)
+                .set_delay(std::time::Duration::from_secs(5)),
+        )
+        .mount(&redist.server)
+        .await;
+
+    let started = std::time::Instant::now();
+    let output = redist.cudup(&["install", CUDA_VERSION, "--skip-cudnn", "--timeout", "1"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(
+        !output.status.success(),
+        "install should time out: {stderr}"
+    );
+    assert!(
+        stderr.contains("no data received for 1s"),
+        "expected an idle timeout error, got: {stderr}"
+    );
+    assert!(
+        started.elapsed() < std::time::Duration::from_secs(5),
+        "the download should be abandoned before the server responds"
+    );
+    assert!(!redist.install_dir(CUDA_VERSION).exists());
+}
+
+#[tokio::test]
+async fn flat_archive_is_extracted_as_is_with_a_warning() {
+    let redist = MockRedist::start().await;
+
+    let wrapped_path = "cuda_cudart/linux-x86_64/cuda_cudart-linux-x86_64-12.4.127-archive.tar.xz";
+    let flat_path = "cuda_nvcc/linux-x86_64/cuda_nvcc-linux-x86_64-12.4.131-archive.tar.xz";
+    let wrapped = package_archive(
+        "cuda_cudart-linux-x86_64-12.4.127-archive",
+        &[("lib/libcudart.so", b"cudart")],
+    );
+    let flat = tar_xz(
+        &["bin", "nvvm"],
+        &[("bin/nvcc", b"nvcc"), ("nvvm/libnvvm.so", b"nvvm")],
+    );
+
+    redist
+        .serve_cuda_release(
+            CUDA_VERSION,
+            &[
+                (
+                    "cuda_cudart",
+                    wrapped_path,
+                    sha256_hex(&wrapped),
+                    wrapped.len(),
+                ),
+                ("cuda_nvcc", flat_path, sha256_hex(&flat), flat.len()),
+            ],
+        )
+        .await;
+    redist
+        .serve(&format!("/cuda/{wrapped_path}"), wrapped)
+        .await;
+    redist.serve(&format!("/cuda/{flat_path}"), flat).await;
+
+    let output = redist.cudup(&["install", CUDA_VERSION, "--skip-cudnn"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "install failed: {stderr}");
+
+    let warnings: Vec<&str> = stderr
+        .lines()
+        .filter(|line| line.contains("has no single top-level directory"))
+        .collect();
+    assert_eq!(warnings.len(), 1, "expected one warning: {stderr}");
+    assert!(warnings[0].contains("cuda_nvcc"), "{}", warnings[0]);
+
+    let install_dir = redist.install_dir(CUDA_VERSION);
+    assert_eq!(
+        fs::read(install_dir.join("lib/libcudart.so")).unwrap(),
+        b"cudart"
+    );
+    assert_eq!(fs::read(install_dir.join("bin/nvcc")).unwrap(), b"nvcc");
+    assert_eq!(
+        fs::read(install_dir.join("nvvm/libnvvm.so")).unwrap(),
+        b"nvvm"
+    );
+}
+
+#[tokio::test]
+async fn restore_reinstalls_frozen_versions_with_install_flags() {
+    let redist = MockRedist::start().await;
+    redist.serve_cuda_index(&["12.4.1", "12.6.0"]).await;
+    redist
+        .serve_cudart_release("12.4.1", &[("lib/libcudart.so", b"cudart 12.4")])
+        .await;
+    redist
+        .serve_cudart_release("12.6.0", &[("lib/libcudart.so", b"cudart 12.6")])
+        .await;
+    let output = redist.cudup(&["install", "12.4.1", "12.6.0", "--skip-cudnn"]);
+    assert!(output.status.success(), "install failed");
+
+    let freeze_file = redist.home().join("cudup-freeze.lock");
+    let freeze_arg = freeze_file.to_str().unwrap();
+    let output = redist.cudup(&["freeze", "--output", freeze_arg]);
+    assert!(output.status.success(), "freeze failed");
+
+    let output = redist.cudup(&["restore", freeze_arg]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "restore failed: {stderr}");
+    assert!(
+        stderr.contains("Already installed: 12.4.1, 12.6.0"),
+        "installed versions should be skipped: {stderr}"
+    );
+
+    let output = redist.cudup(&["uninstall", "12.6.0", "--force"]);
+    assert!(output.status.success(), "uninstall failed");
+    let archive_dir = redist.home().join("archives");
+    let output = redist.cudup(&[
+        "restore",
+        freeze_arg,
+        "--archive-dir",
+        archive_dir.to_str().unwrap(),
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "restore failed: {stderr}");
+    assert!(
+        stderr.contains("Installed: 12.6.0") && stderr.contains("Already installed: 12.4.1"),
+        "only the removed version should be reinstalled: {stderr}"
+    );
+    assert_eq!(
+        fs::read(redist.install_dir("12.6.0").join("lib/libcudart.so")).unwrap(),
+        b"cudart 12.6"
+    );
+    assert!(
+        archive_dir
+            .join("cuda_cudart-linux-x86_64-12.6.0-archive.tar.xz")
+            .exists(),
+        "--archive-dir should be honored by restore"
+    );
+}
+
+#[tokio::test]
+async fn verify_existing_refetches_only_the_package_missing_files() {
+    let redist = MockRedist::start().await;
+
+    let cudart_path = "cuda_cudart/linux-x86_64/cuda_cudart-linux-x86_64-12.4.127-archive.tar.xz";
+    let nvcc_path = "cuda_nvcc/linux-x86_64/cuda_nvcc-linux-x86_64-12.4.131-archive.tar.xz";
+    let cudart = package_archive(
+        "cuda_cudart-linux-x86_64-12.4.127-archive",
+        &[("lib/libcudart.so", b"cudart")],
+    );
+    let nvcc = package_archive(
+        "cuda_nvcc-linux-x86_64-12.4.131-archive",
+        &[("bin/nvcc", b"nvcc"), ("bin/ptxas", b"ptxas")],
+    );
+    redist
+        .serve_cuda_release(
+            CUDA_VERSION,
+            &[
+                (
+                    "cuda_cudart",
+                    cudart_path,
+                    sha256_hex(&cudart),
+                    cudart.len(),
+                ),
+                ("cuda_nvcc", nvcc_path, sha256_hex(&nvcc), nvcc.len()),
+            ],
+        )
+        .await;
+    redist.serve(&format!("/cuda/{cudart_path}"), cudart).await;
+    redist.serve(&format!("/cuda/{nvcc_path}"), nvcc).await;
+
+    let output = redist.cudup(&["install", CUDA_VERSION, "--skip-cudnn"]);
+    assert!(output.status.success(), "install failed");
+    let nvcc_binary = redist.install_dir(CUDA_VERSION).join("bin/nvcc");
+    fs::remove_file(&nvcc_binary).unwrap();
+
+    let output = redist.cudup(&["install", CUDA_VERSION, "--verify-existing"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "repair failed: {stderr}");
+    assert!(
+        stderr.contains("Repairing 1 of 2 packages of CUDA 12.4.1: cuda_nvcc"),
+        "{stderr}"
+    );
+    assert_eq!(fs::read(&nvcc_binary).unwrap(), b"nvcc");
+
+    let requests = redist.server.received_requests().await.unwrap_or_default();
+    let fetches = |relative_path: &str| {
+        let route = format!("/cuda/{relative_path}");
+        requests
+            .iter()
+            .filter(|request| request.url.path() == route)
+            .count()
+    };
+    assert_eq!(
+        fetches(cudart_path),
+        1,
+        "the intact package is not re-fetched"
+    );
+    assert_eq!(
+        fetches(nvcc_path),
+        2,
+        "the damaged package is fetched again"
+    );
+}
+
+#[tokio::test]
+async fn continue_on_error_installs_the_rest_but_exits_non_zero() {
+    let redist = MockRedist::start().await;
+
+    let cudart_path = "cuda_cudart/linux-x86_64/cuda_cudart-linux-x86_64-12.4.127-archive.tar.xz";
+    let nvcc_path = "cuda_nvcc/linux-x86_64/cuda_nvcc-linux-x86_64-12.4.131-archive.tar.xz";
+    let cudart = package_archive(
+        "cuda_cudart-linux-x86_64-12.4.127-archive",
+        &[("lib/libcudart.so", b"cudart")],
+    );
+    redist
+        .serve_cuda_release(
+            CUDA_VERSION,
+            &[
+                (
+                    "cuda_cudart",
+                    cudart_path,
+                    sha256_hex(&cudart),
+                    cudart.len(),
+                ),
+                ("cuda_nvcc", nvcc_path, "b".repeat(64), 1024),
+            ],
+        )
+        .await;
+    redist.serve(&format!("/cuda/{cudart_path}"), cudart).await;
+
+    let output = redist.cudup(&["install", CUDA_VERSION, "--skip-cudnn"]);
+    assert!(!output.status.success(), "a 404 should fail the install");
+    assert!(
+        !redist.install_dir(CUDA_VERSION).exists(),
+        "without --continue-on-error a failed install is cleaned up"
+    );
+
+    let output = redist.cudup(&[
+        "install",
+        CUDA_VERSION,
+        "--skip-cudnn",
+        "--continue-on-error",
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        !output.status.success(),
+        "a partial install should exit non-zero: {stderr}"
+    );
+    assert!(
+        stderr.contains("CUDA 12.4.1 was installed without 1 package(s): cuda_nvcc"),
+        "expected the skipped package to be named: {stderr}"
+    );
+    assert_eq!(
+        fs::read(redist.install_dir(CUDA_VERSION).join("lib/libcudart.so")).unwrap(),
+        b"cudart"
+    );
+
+    let manifest: serde_json::Value = serde_json::from_slice(
+        &fs::read(
+            redist
+                .install_dir(CUDA_VERSION)
+                .join(".cudup-manifest.json"),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        manifest["failed_packages"],
+        serde_json::json!(["cuda_nvcc"])
+    );
+}
+
+#[tokio::test]
+async fn every_request_identifies_cudup() {
+    let redist = MockRedist::start().await;
+    redist
+        .serve_cudart_release(CUDA_VERSION, &[("lib/libcudart.so", b"cudart")])
+        .await;
+
+    let output = redist.cudup(&["install", CUDA_VERSION, "--skip-cudnn"]);
+    assert!(output.status.success(), "install failed");
+
+    let expected = format!(
+        "cudup/{} (+https://github.com/zocrit/cudup)",
+        env!("CARGO_PKG_VERSION")
+    );
+    let requests = redist.server.received_requests().await.unwrap_or_default();
+    assert!(!requests.is_empty());
+    for request in &requests {
+        let user_agent = request
+            .headers
+            .get("user-agent")
+            .and_then(|value| value.to_str().ok());
+        assert_eq!(
+            user_agent,
+            Some(expected.as_str()),
+            "{} {}",
+            request.method,
+            request.url
+        );
+    }
+}
+
+#[tokio::test]
+async fn corrupt_cache_file_is_refetched() {
+    let redist = MockRedist::start().await;
+    redist.serve_cuda_index(&["12.4.1", "12.6.0"]).await;
+    let versions_cache = redist.home().join("cache/cuda/versions.json");
+    fs::create_dir_all(versions_cache.parent().unwrap()).unwrap();
+    fs::write(&versions_cache, "[\"12.4.1\", \"12.").unwrap();
+
+    let output = redist.cudup(&["list", "--remote-only"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "list failed: {stderr}");
+    assert!(
+        stderr.contains("Ignoring unreadable cache file"),
+        "{stderr}"
+    );
+    assert!(
+        stdout.contains("12.4.1") && stdout.contains("12.6.0"),
+        "{stdout}"
+    );
+
+    let cached: Vec<String> =
+        serde_json::from_str(&fs::read_to_string(&versions_cache).unwrap()).unwrap();
+    assert_eq!(cached, ["12.4.1", "12.6.0"]);
+}
+
+#[tokio::test]
+async fn no_cache_writes_no_cache_files() {
+    let redist = MockRedist::start().await;
+    redist
+        .serve_cudart_release(CUDA_VERSION, &[("lib/libcudart.so", b"cudart")])
+        .await;
+    let cache_dir = redist.home().join("cache");
+
+    for args in [
+        &["--no-cache", "list", "--remote-only"][..],
+        &["--no-cache", "list-packages", CUDA_VERSION],
+    ] {
+        let output = redist.cudup(args);
+        assert!(
+            output.status.success(),
+            "cudup {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert!(
+            !cache_dir.exists(),
+            "cudup {} wrote the cache",
+            args.join(" ")
+        );
+    }
+
+    let output = redist.cudup(&["list", "--remote-only"]);
+    assert!(output.status.success());
+    assert!(
+        cache_dir.join("cuda/versions.json").exists(),
+        "the cache is written without --no-cache"
+    );
+}
+
+#[tokio::test]
+async fn warm_archive_dir_skips_the_download() {
+    let redist = MockRedist::start().await;
+
+    let archive_name = "cuda_cudart-linux-x86_64-12.4.127-archive.tar.xz";
+    let relative_path = format!("cuda_cudart/linux-x86_64/{archive_name}");
+    let archive = package_archive(
+        "cuda_cudart-linux-x86_64-12.4.127-archive",
+        &[("lib/libcudart.so", b"cudart")],
+    );
+    redist
+        .serve_cuda_release(
+            CUDA_VERSION,
+            &[(
+                "cuda_cudart",
+                &relative_path,
+                sha256_hex(&archive),
+                archive.len(),
+            )],
+        )
+        .await;
+    // The archive itself isn't served: a download attempt would fail the install.
+
+    let archive_dir = redist.home().join("ci-cache");
+    fs::create_dir_all(&archive_dir).unwrap();
+    fs::write(archive_dir.join(archive_name), &archive).unwrap();
+
+    let output = redist.cudup(&[
+        "install",
+        CUDA_VERSION,
+        "--skip-cudnn",
+        "--archive-dir",
+        archive_dir.to_str().unwrap(),
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "install failed: {stderr}");
+    assert!(
+        stderr.contains("Using previously downloaded cuda_cudart"),
+        "{stderr}"
+    );
+    assert_eq!(
+        fs::read(redist.install_dir(CUDA_VERSION).join("lib/libcudart.so")).unwrap(),
+        b"cudart"
+    );
+
+    let requests = redist.server.received_requests().await.unwrap_or_default();
+    assert!(
+        !requests
+            .iter()
+            .any(|request| request.url.path().ends_with(archive_name)),
+        "the seeded archive should not be downloaded again"
+    );
+    assert!(
+        archive_dir.join(archive_name).exists(),
+        "archives in --archive-dir are kept"
+    );
+}
+
+#[tokio::test]
+async fn post_hook_gets_the_install_environment() {
+    let redist = MockRedist::start().await;
+    redist
+        .serve_cudart_release(CUDA_VERSION, &[("lib/libcudart.so", b"cudart")])
+        .await;
+
+    let hook_output = redist.home().join("hook.out");
+    let hook = format!(
+        "printf '%s\\n' \"$CUDA_HOME\" \"$CUDUP_VERSION\" \"${{CUDUP_CUDNN_VERSION-unset}}\" > {}; exit 3",
+        hook_output.display()
+    );
+    let output = redist.cudup(&[
+        "install",
+        CUDA_VERSION,
+        "--skip-cudnn",
+        "--post-hook",
+        &hook,
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(
+        output.status.success(),
+        "a failing hook must not fail the install: {stderr}"
+    );
+    assert!(
+        stderr.contains("Post-install hook exited with exit status: 3"),
+        "{stderr}"
+    );
+    assert_eq!(
+        fs::read_to_string(&hook_output).unwrap(),
+        format!(
+            "{}\n{CUDA_VERSION}\nunset\n",
+            redist.install_dir(CUDA_VERSION).display()
+        )
+    );
+    assert!(
+        redist
+            .install_dir(CUDA_VERSION)
+            .join("lib/libcudart.so")
+            .exists()
+    );
+}
+
+#[tokio::test]
+async fn json_summary_describes_the_install() {
+    let redist = MockRedist::start().await;
+
+    let cudart_path = "cuda_cudart/linux-x86_64/cuda_cudart-linux-x86_64-12.4.127-archive.tar.xz";
+    let nvcc_path = "cuda_nvcc/linux-x86_64/cuda_nvcc-linux-x86_64-12.4.131-archive.tar.xz";
+    let cudart = package_archive(
+        "cuda_cudart-linux-x86_64-12.4.127-archive",
+        &[("lib/libcudart.so", b"cudart")],
+    );
+    let nvcc = package_archive(
+        "cuda_nvcc-linux-x86_64-12.4.131-archive",
+        &[("bin/nvcc", b"nvcc")],
+    );
+    let total_bytes = cudart.len() + nvcc.len();
+    redist
+        .serve_cuda_release(
+            CUDA_VERSION,
+            &[
+                (
+                    "cuda_cudart",
+                    cudart_path,
+                    sha256_hex(&cudart),
+                    cudart.len(),
+                ),
+                ("cuda_nvcc", nvcc_path, sha256_hex(&nvcc), nvcc.len()),
+            ],
+        )
+        .await;
+    redist.serve(&format!("/cuda/{cudart_path}"), cudart).await;
+    redist.serve(&format!("/cuda/{nvcc_path}"), nvcc).await;
+
+    let output = redist.cudup(&[
+        "install",
+        CUDA_VERSION,
+        "--skip-cudnn",
+        "--json",
+        "--post-hook",
+        "echo hook ran",
+    ]);
+    assert!(
+        output.status.success(),
+        "install failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let summary: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("stdout should hold only the JSON summary");
+    assert_eq!(summary["cuda_version"], CUDA_VERSION);
+    assert_eq!(summary["cudnn_version"], serde_json::Value::Null);
+    assert_eq!(
+        summary["install_path"],
+        redist.install_dir(CUDA_VERSION).to_str().unwrap()
+    );
+    assert_eq!(summary["total_bytes"], total_bytes);
+    assert_eq!(summary["package_count"], 2);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("hook ran"));
+}
+
+#[tokio::test]
+async fn installs_the_newest_cudnn_listing_the_cuda_major() {
+    let redist = MockRedist::start().await;
+    redist
+        .serve_cudart_release(CUDA_VERSION, &[("lib/libcudart.so", b"cudart")])
+        .await;
+    redist
+        .serve_cudnn_releases(&[
+            ("8.9.7", &["11", "12"]),
+            ("9.1.0", &["12"]),
+            ("9.5.0", &["13"]),
+        ])
+        .await;
+
+    let output = redist.cudup(&["install", CUDA_VERSION]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "install failed: {stderr}");
+    assert!(
+        stderr.contains("Found cuDNN 9.1.0 (selected for CUDA 12 via cuda12 variant)"),
+        "9.5.0 doesn't list CUDA 12, so 9.1.0 should be picked: {stderr}"
+    );
+    assert_eq!(
+        fs::read(redist.install_dir(CUDA_VERSION).join("lib/libcudnn.so")).unwrap(),
+        b"cudnn 9.1.0 cuda12"
+    );
+
+    let requests = redist.server.received_requests().await.unwrap_or_default();
+    assert!(
+        !requests
+            .iter()
+            .any(|request| request.url.path() == "/cudnn/redistrib_8.9.7.json"),
+        "releases older than the first compatible one shouldn't be probed"
+    );
+}
+
+#[tokio::test]
+async fn incompatible_cudnn_version_lists_the_compatible_releases() {
+    let redist = MockRedist::start().await;
+    redist
+        .serve_cudart_release(CUDA_VERSION, &[("lib/libcudart.so", b"cudart")])
+        .await;
+    redist
+        .serve_cudnn_releases(&[
+            ("8.9.7", &["11", "12"]),
+            ("9.1.0", &["12"]),
+            ("9.5.0", &["13"]),
+        ])
+        .await;
+
+    let output = redist.cudup(&["install", CUDA_VERSION, "--cudnn-version", "9.5.0"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("cuDNN 9.5.0 has no cuda12 build for linux-x86_64 (available: cuda13)"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("cuDNN versions built for CUDA 12: 8.9.7, 9.1.0"),
+        "only releases listing CUDA 12 should be suggested: {stderr}"
+    );
+    assert!(
+        !redist
+            .install_dir(CUDA_VERSION)
+            .join("lib/libcudnn.so")
+            .exists()
+    );
+}
+
+#[tokio::test]
+async fn piped_install_prints_no_cursor_escapes() {
+    let redist = MockRedist::start().await;
+    redist
+        .serve_cudart_release(CUDA_VERSION, &[("lib/libcudart.so", b"cudart")])
+        .await;
+
+    let output = redist.cudup(&["install", CUDA_VERSION, "--skip-cudnn"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "install failed: {stderr}");
+    assert!(
+        stderr.contains("CUDA 12.4.1 installed successfully!"),
+        "status lines should still be printed: {stderr}"
+    );
+    for output in [&output.stdout, &output.stderr] {
+        assert!(
+            !output.contains(&0x1b),
+            "piped output should hold no escape sequences: {:?}",
+            String::from_utf8_lossy(output)
+        );
+    }
+}
+
+#[tokio::test]
+async fn dest_installs_outside_the_managed_tree() {
+    let redist = MockRedist::start().await;
+    redist
+        .serve_cudart_release(CUDA_VERSION, &[("lib/libcudart.so", b"cudart")])
+        .await;
+
+    let target = TempDir::new().unwrap();
+    let dest = target.path().join("opt/cuda");
+    let output = redist.cudup(&[
+        "install",
+        CUDA_VERSION,
+        "--skip-cudnn",
+        "--dest",
+        dest.to_str().unwrap(),
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "install failed: {stderr}");
+    assert!(stderr.contains("`cudup use` won't manage it"), "{stderr}");
+    assert_eq!(fs::read(dest.join("lib/libcudart.so")).unwrap(), b"cudart");
+    assert!(
+        !redist.install_dir(CUDA_VERSION).exists(),
+        "a --dest install shouldn't be registered as a managed version"
+    );
+
+    let again = redist.cudup(&[
+        "install",
+        CUDA_VERSION,
+        "--skip-cudnn",
+        "--dest",
+        dest.to_str().unwrap(),
+    ]);
+    assert!(!again.status.success());
+    assert!(
+        String::from_utf8_lossy(&again.stderr).contains("needs a new or empty directory"),
+        "{}",
+        String::from_utf8_lossy(&again.stderr)
+    );
+}
+
+#[tokio::test]
+async fn corrupt_download_is_fetched_again_once() {
+    let redist = MockRedist::start().await;
+
+    let relative_path = "cuda_cudart/linux-x86_64/cuda_cudart-linux-x86_64-12.4.127-archive.tar.xz";
+    let archive = package_archive(
+        "cuda_cudart-linux-x86_64-12.4.127-archive",
+        &[("lib/libcudart.so", b"cudart")],
+    );
+    let mut corrupt = archive.clone();
+    let middle = corrupt.len() / 2;
+    corrupt[middle] ^= 0xff;
+    redist
+        .serve_cuda_release(
+            CUDA_VERSION,
+            &[(
+                "cuda_cudart",
+                relative_path,
+                sha256_hex(&archive),
+                archive.len(),
+            )],
+        )
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("/cuda/{relative_path}")))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(corrupt))
+        .with_priority(1)
+        .up_to_n_times(1)
+        .mount(&redist.server)
+        .await;
+    redist
+        .serve(&format!("/cuda/{relative_path}"), archive)
+        .await;
+
+    let output = redist.cudup(&["install", CUDA_VERSION, "--skip-cudnn"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success(),
+        "the second download should succeed: {stderr}"
+    );
+    assert!(
+        stderr.contains("cuda_cudart: checksum mismatch, downloading it again"),
+        "{stderr}"
+    );
+    assert_eq!(
+        fs::read(redist.install_dir(CUDA_VERSION).join("lib/libcudart.so")).unwrap(),
+        b"cudart"
+    );
+
+    let requests = redist.server.received_requests().await.unwrap_or_default();
+    let archive_requests = requests
+        .iter()
+        .filter(|request| request.url.path().ends_with(".tar.xz"))
+        .count();
+    assert_eq!(archive_requests, 2);
+}
+
+#[tokio::test]
+async fn partial_install_skips_the_post_install_hook() {
+    let redist = MockRedist::start().await;
+
+    let cudart_path = "cuda_cudart/linux-x86_64/cuda_cudart-linux-x86_64-12.4.127-archive.tar.xz";
+    let nvcc_path = "cuda_nvcc/linux-x86_64/cuda_nvcc-linux-x86_64-12.4.131-archive.tar.xz";
+    let cudart = package_archive(
+        "cuda_cudart-linux-x86_64-12.4.127-archive",
+        &[("lib/libcudart.so", b"cudart")],
+    );
+    redist
+        .serve_cuda_release(
+            CUDA_VERSION,
+            &[
+                (
+                    "cuda_cudart",
+                    cudart_path,
+                    sha256_hex(&cudart),
+                    cudart.len(),
+                ),
+                ("cuda_nvcc", nvcc_path, "b".repeat(64), 1024),
+            ],
+        )
+        .await;
+    redist.serve(&format!("/cuda/{cudart_path}"), cudart).await;
+
+    let hook_output = redist.home().join("hook.out");
+    let hook = format!("touch {}", hook_output.display());
+    let output = redist.cudup(&[
+        "install",
+        CUDA_VERSION,
+        "--skip-cudnn",
+        "--continue-on-error",
+        "--post-hook",
+        &hook,
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "{stderr}");
+    assert!(
+        stderr.contains("Skipping the post-install hook, as CUDA 12.4.1 is incomplete"),
+        "{stderr}"
+    );
+    assert!(
+        !hook_output.exists(),
+        "the hook should only run after a complete install"
+    );
+}
+
+#[tokio::test]
+async fn unwritable_cache_does_not_fail_the_install() {
+    let redist = MockRedist::start().await;
+    redist
+        .serve_cudart_release(CUDA_VERSION, &[("lib/libcudart.so", b"cudart")])
+        .await;
+    // A file where the cache directory belongs makes every cache write fail.
+    fs::write(redist.home().join("cache"), "not a directory").unwrap();
+
+    let output = redist.cudup(&["install", CUDA_VERSION, "--skip-cudnn"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "install failed: {stderr}");
+    assert!(stderr.contains("Failed to update the cache"), "{stderr}");
+    assert_eq!(
+        fs::read(redist.install_dir(CUDA_VERSION).join("lib/libcudart.so")).unwrap(),
+        b"cudart"
+    );
+}